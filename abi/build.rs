@@ -31,6 +31,30 @@ fn main() {
             "reservation.ReservationQuery.page_size",
             "#[builder(setter(into), default)]",
         )
+        .field_attribute(
+            "reservation.ReservationQuery.resource_ids",
+            "#[builder(setter(into), default)]",
+        )
+        .field_attribute(
+            "reservation.ReservationQuery.user_ids",
+            "#[builder(setter(into), default)]",
+        )
+        .field_attribute(
+            "reservation.ReservationQuery.statuses",
+            "#[builder(setter(into), default)]",
+        )
+        .field_attribute(
+            "reservation.ReservationQuery.sort_by",
+            "#[builder(setter(into), default)]",
+        )
+        .field_attribute(
+            "reservation.ReservationQuery.match_mode",
+            "#[builder(setter(into), default)]",
+        )
+        .field_attribute(
+            "reservation.ReservationQuery.cursor",
+            "#[builder(setter(into, strip_option), default)]",
+        )
         .field_attribute(
             "reservation.ReservationQuery.start",
             "#[builder(setter(into, strip_option), default)]",
@@ -39,6 +63,46 @@ fn main() {
             "reservation.ReservationQuery.end",
             "#[builder(setter(into, strip_option), default)]",
         )
+        .type_attribute(
+            "reservation.Reservation",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .type_attribute(
+            "reservation.ReservationUpdateType",
+            "#[derive(serde::Serialize)] #[serde(rename_all = \"lowercase\")]",
+        )
+        .field_attribute(
+            "reservation.Reservation.status",
+            "#[serde(with = \"crate::types::json::status\")]",
+        )
+        .field_attribute(
+            "reservation.Reservation.start",
+            "#[serde(with = \"crate::types::json::timestamp\")]",
+        )
+        .field_attribute(
+            "reservation.Reservation.end",
+            "#[serde(with = \"crate::types::json::timestamp\")]",
+        )
+        .type_attribute(
+            "reservation.Webhook",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .field_attribute(
+            "reservation.Webhook.ops",
+            "#[serde(with = \"crate::types::json::ops\")]",
+        )
+        .type_attribute(
+            "reservation.Delegation",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .field_attribute(
+            "reservation.Delegation.scope",
+            "#[serde(with = \"crate::types::json::scope\")]",
+        )
+        .field_attribute(
+            "reservation.Delegation.expires_at",
+            "#[serde(with = \"crate::types::json::timestamp\")]",
+        )
         .compile(&["protos/reservation.proto"], &["protos"])
         .unwrap();
 
@@ -6,6 +6,9 @@ pub use pb::*;
 use prost_types::Timestamp;
 pub use types::*;
 
+/// grpc metadata key carrying the name of the `ErrorReason` of a failed request
+pub const ERROR_REASON_KEY: &str = "x-rsvp-error";
+
 /// convert prost_types::Timestamp to utc time
 pub fn to_utc_time(timestamp: &Timestamp) -> DateTime<Utc> {
     DateTime::from_utc(
@@ -1,5 +1,8 @@
 /// Core reservation object. Contains all the information for a reservation
-/// if ListenResponse op is DELETE, only id will be populated
+/// if ListenResponse op is DELETE, only id, tenant_id, user_id, resource_id and
+/// status will be populated
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Reservation {
     /// unique id for the reservation, if put into ReservationRequest, id should be
@@ -11,19 +14,30 @@ pub struct Reservation {
     pub user_id: ::prost::alloc::string::String,
     /// reservation status, used for differentating purpose
     #[prost(enumeration = "ReservationStatus", tag = "3")]
+    #[serde(with = "crate::types::json::status")]
     pub status: i32,
     /// resource id for the reservation
     #[prost(string, tag = "4")]
     pub resource_id: ::prost::alloc::string::String,
     /// start time for the reservation
     #[prost(message, optional, tag = "5")]
+    #[serde(with = "crate::types::json::timestamp")]
     pub start: ::core::option::Option<::prost_types::Timestamp>,
     /// end time for the reservation
     #[prost(message, optional, tag = "6")]
+    #[serde(with = "crate::types::json::timestamp")]
     pub end: ::core::option::Option<::prost_types::Timestamp>,
     /// extra note
     #[prost(string, tag = "7")]
     pub note: ::prost::alloc::string::String,
+    /// tenant the reservation belongs to, set by the server from the request
+    /// metadata. resources of different tenants never conflict
+    #[prost(string, tag = "8")]
+    pub tenant_id: ::prost::alloc::string::String,
+    /// user id of the caller who booked the reservation, set by the server.
+    /// differs from user_id if booked on behalf of the user
+    #[prost(string, tag = "9")]
+    pub booked_by: ::prost::alloc::string::String,
 }
 /// To make a reservation, send a ReservationRequest with Reservation object (id
 /// should be empty)
@@ -31,6 +45,15 @@ pub struct Reservation {
 pub struct ReserveRequest {
     #[prost(message, optional, tag = "1")]
     pub reservation: ::core::option::Option<Reservation>,
+    /// optional idempotency key, a retried request with the same key returns the
+    /// original reservation instead of reserving again
+    #[prost(string, tag = "2")]
+    pub idempotency_key: ::prost::alloc::string::String,
+    /// an admin or a delegate of the user books for the user_id of the reservation
+    /// instead of themselves, required if the service fills user_id from the
+    /// caller
+    #[prost(bool, tag = "3")]
+    pub on_behalf_of: bool,
 }
 /// Created reservation will be returned in ReserveResponse
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -41,8 +64,8 @@ pub struct ReserveResponse {
 /// To update a reservation, send an UpdateRequest. Only note is updatable.
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UpdateRequest {
-    #[prost(int64, tag = "1")]
-    pub id: i64,
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub note: ::prost::alloc::string::String,
 }
@@ -55,8 +78,11 @@ pub struct UpdateResponse {
 /// To change a reservation from pending to confirmed, send a ConfirmRequest
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ConfirmRequest {
-    #[prost(int64, tag = "1")]
-    pub id: i64,
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// optional idempotency key, see ReserveRequest
+    #[prost(string, tag = "2")]
+    pub idempotency_key: ::prost::alloc::string::String,
 }
 /// Confirmed reservation will be returned in ConfirmResponse
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -67,8 +93,11 @@ pub struct ConfirmResponse {
 /// To cancel a reservation, send a CancelRequest
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CancelRequest {
-    #[prost(int64, tag = "1")]
-    pub id: i64,
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// optional idempotency key, see ReserveRequest
+    #[prost(string, tag = "2")]
+    pub idempotency_key: ::prost::alloc::string::String,
 }
 /// Canceled reservation will be returned in CancelResponse
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -76,11 +105,29 @@ pub struct CancelResponse {
     #[prost(message, optional, tag = "1")]
     pub reservation: ::core::option::Option<Reservation>,
 }
+/// To cancel every reservation matching a query, send a CancelByFilterRequest.
+/// page, page_size and desc of the query are ignored.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelByFilterRequest {
+    #[prost(message, optional, tag = "1")]
+    pub query: ::core::option::Option<ReservationQuery>,
+    /// if true, nothing is cancelled and the reservations that would be cancelled
+    /// are returned
+    #[prost(bool, tag = "2")]
+    pub dry_run: bool,
+}
+/// Canceled reservations (or the ones would be canceled in dry run mode) will be
+/// returned in CancelByFilterResponse
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CancelByFilterResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub reservations: ::prost::alloc::vec::Vec<Reservation>,
+}
 /// To get a reservation, send a GetRequest
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetRequest {
-    #[prost(int64, tag = "1")]
-    pub id: i64,
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
 }
 /// Reservation will be returned in GetResponse
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -124,6 +171,31 @@ pub struct ReservationQuery {
     #[prost(int64, tag = "8")]
     #[builder(setter(into), default)]
     pub page_size: i64,
+    /// more resource ids, matched together with resource_id
+    #[prost(string, repeated, tag = "9")]
+    #[builder(setter(into), default)]
+    pub resource_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// more user ids, matched together with user_id
+    #[prost(string, repeated, tag = "10")]
+    #[builder(setter(into), default)]
+    pub user_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// more statuses, matched together with status
+    #[prost(enumeration = "ReservationStatus", repeated, tag = "11")]
+    #[builder(setter(into), default)]
+    pub statuses: ::prost::alloc::vec::Vec<i32>,
+    /// sort key, default to start time
+    #[prost(enumeration = "ReservationSortKey", tag = "12")]
+    #[builder(setter(into), default)]
+    pub sort_by: i32,
+    /// how start and end are matched, default to containment
+    #[prost(enumeration = "ReservationMatchMode", tag = "13")]
+    #[builder(setter(into), default)]
+    pub match_mode: i32,
+    /// return the reservations after this id, page is ignored if set. only
+    /// allowed when sorted by id
+    #[prost(string, optional, tag = "14")]
+    #[builder(setter(into, strip_option), default)]
+    pub cursor: ::core::option::Option<::prost::alloc::string::String>,
 }
 /// To query reservations, send a QueryRequest
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -143,16 +215,17 @@ pub struct ReservationFilter {
     /// use status to filter result. If UNKNOWN, return all reservations
     #[prost(enumeration = "ReservationStatus", tag = "3")]
     pub status: i32,
-    #[prost(int64, optional, tag = "4")]
-    pub cursor: ::core::option::Option<i64>,
-    /// page size for the query
+    /// page size for the query, 0 to use default page size
     #[prost(int64, tag = "5")]
     pub page_size: i64,
     /// sort direction
     #[prost(bool, tag = "6")]
     pub desc: bool,
+    /// return the reservations after this id, if not set, start from the first one
+    #[prost(string, optional, tag = "7")]
+    pub cursor: ::core::option::Option<::prost::alloc::string::String>,
 }
-/// To query reservations, send a QueryRequest
+/// To filter reservations, send a FilterRequest
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FilterRequest {
     #[prost(message, optional, tag = "1")]
@@ -161,10 +234,14 @@ pub struct FilterRequest {
 /// filter pager info
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FilterPager {
-    #[prost(int64, optional, tag = "1")]
-    pub prev: ::core::option::Option<i64>,
-    #[prost(int64, optional, tag = "2")]
-    pub next: ::core::option::Option<i64>,
+    /// id of the first reservation of the page, send it as cursor with desc
+    /// flipped to page backward. not set on the first page
+    #[prost(string, optional, tag = "4")]
+    pub prev: ::core::option::Option<::prost::alloc::string::String>,
+    /// cursor of the next page, not set on the last page
+    #[prost(string, optional, tag = "5")]
+    pub next: ::core::option::Option<::prost::alloc::string::String>,
+    /// how many reservations match the filter
     #[prost(int64, optional, tag = "3")]
     pub total: ::core::option::Option<i64>,
 }
@@ -175,18 +252,162 @@ pub struct FilterResponse {
     #[prost(message, optional, tag = "2")]
     pub pager: ::core::option::Option<FilterPager>,
 }
-/// Client can listen to reservation updates by sending a ListenRequest
+/// Client can listen to reservation updates by sending a ListenRequest, empty
+/// filters match every change
 #[derive(Clone, PartialEq, ::prost::Message)]
-pub struct ListenRequest {}
+pub struct ListenRequest {
+    /// only changes of these resources
+    #[prost(string, repeated, tag = "1")]
+    pub resource_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// only changes of these users
+    #[prost(string, repeated, tag = "2")]
+    pub user_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// only changes of reservations in these statuses
+    #[prost(enumeration = "ReservationStatus", repeated, tag = "3")]
+    pub statuses: ::prost::alloc::vec::Vec<i32>,
+    /// only changes of these types
+    #[prost(enumeration = "ReservationUpdateType", repeated, tag = "4")]
+    pub ops: ::prost::alloc::vec::Vec<i32>,
+    /// replay the changes after this sequence number first, if not set, only
+    /// changes made after listening are sent
+    #[prost(int64, optional, tag = "5")]
+    pub since: ::core::option::Option<i64>,
+}
 /// Server will send ListenResponse to client in streaming response
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListenResponse {
     /// update type
     #[prost(enumeration = "ReservationUpdateType", tag = "1")]
     pub op: i32,
-    /// id for updated reservation
+    /// updated reservation, if op is DELETE, only id, tenant_id, user_id,
+    /// resource_id and status will be populated
     #[prost(message, optional, tag = "2")]
     pub reservation: ::core::option::Option<Reservation>,
+    /// sequence number of the change, send it as since to resume listening
+    #[prost(int64, tag = "3")]
+    pub id: i64,
+    /// user id of the caller who made the change, empty if unknown
+    #[prost(string, tag = "4")]
+    pub actor: ::prost::alloc::string::String,
+    /// whether the actor changed a reservation of another user
+    #[prost(bool, tag = "5")]
+    pub on_behalf_of: bool,
+}
+/// a webhook called on reservation changes
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Webhook {
+    /// unique id for the webhook, should be empty when creating one
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// url the change is posted to
+    #[prost(string, tag = "2")]
+    pub url: ::prost::alloc::string::String,
+    /// key to sign the payload with HMAC-SHA256, only returned on creation
+    #[prost(string, tag = "3")]
+    pub secret: ::prost::alloc::string::String,
+    /// only changes of these types, empty means every type
+    #[prost(enumeration = "ReservationUpdateType", repeated, tag = "4")]
+    #[serde(with = "crate::types::json::ops")]
+    pub ops: ::prost::alloc::vec::Vec<i32>,
+    /// only changes of these resources, empty means every resource
+    #[prost(string, repeated, tag = "5")]
+    pub resource_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// tenant the webhook belongs to, only its changes are posted. set by the server
+    #[prost(string, tag = "6")]
+    pub tenant_id: ::prost::alloc::string::String,
+}
+/// To create a webhook, send a CreateWebhookRequest
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateWebhookRequest {
+    #[prost(message, optional, tag = "1")]
+    pub webhook: ::core::option::Option<Webhook>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateWebhookResponse {
+    #[prost(message, optional, tag = "1")]
+    pub webhook: ::core::option::Option<Webhook>,
+}
+/// To list the webhooks, send a ListWebhooksRequest
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListWebhooksRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListWebhooksResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub webhooks: ::prost::alloc::vec::Vec<Webhook>,
+}
+/// To delete a webhook, send a DeleteWebhookRequest with its id
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteWebhookRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteWebhookResponse {
+    #[prost(message, optional, tag = "1")]
+    pub webhook: ::core::option::Option<Webhook>,
+}
+/// the grantor lets the grantee book for them, e.g. an assistant for a manager
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Delegation {
+    /// unique id for the delegation, should be empty when creating one
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    /// user id of who delegates, the owner of the reservations
+    #[prost(string, tag = "2")]
+    pub grantor: ::prost::alloc::string::String,
+    /// user id of the delegate, who books as the actor
+    #[prost(string, tag = "3")]
+    pub grantee: ::prost::alloc::string::String,
+    #[prost(enumeration = "DelegationScope", tag = "4")]
+    #[serde(with = "crate::types::json::scope")]
+    pub scope: i32,
+    /// the delegation is void from this time, never expires if empty
+    #[prost(message, optional, tag = "5")]
+    #[serde(with = "crate::types::json::timestamp")]
+    pub expires_at: ::core::option::Option<::prost_types::Timestamp>,
+    /// tenant the delegation belongs to, set by the server
+    #[prost(string, tag = "6")]
+    pub tenant_id: ::prost::alloc::string::String,
+}
+/// To delegate, send a CreateDelegationRequest. delegating again to the same
+/// grantee replaces the scope and expiry
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateDelegationRequest {
+    #[prost(message, optional, tag = "1")]
+    pub delegation: ::core::option::Option<Delegation>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateDelegationResponse {
+    #[prost(message, optional, tag = "1")]
+    pub delegation: ::core::option::Option<Delegation>,
+}
+/// To list the delegations granted by or to a user, send a
+/// ListDelegationsRequest
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDelegationsRequest {
+    /// the caller if empty
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDelegationsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub delegations: ::prost::alloc::vec::Vec<Delegation>,
+}
+/// To revoke a delegation, send a DeleteDelegationRequest with its id
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteDelegationRequest {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteDelegationResponse {
+    #[prost(message, optional, tag = "1")]
+    pub delegation: ::core::option::Option<Delegation>,
 }
 /// reservation status for a given time period
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
@@ -212,6 +433,8 @@ impl ReservationStatus {
     }
 }
 /// when reservation is updated, record the update type
+#[derive(serde::Serialize)]
+#[serde(rename_all = "lowercase")]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum ReservationUpdateType {
@@ -234,6 +457,115 @@ impl ReservationUpdateType {
         }
     }
 }
+/// how the time range of a query is matched against reservations
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ReservationMatchMode {
+    /// reservation is entirely within the query range
+    Contains = 0,
+    /// reservation overlaps the query range
+    Overlaps = 1,
+    /// reservation starts within the query range, start inclusive and end
+    /// exclusive
+    StartsWithin = 2,
+}
+impl ReservationMatchMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ReservationMatchMode::Contains => "RESERVATION_MATCH_MODE_CONTAINS",
+            ReservationMatchMode::Overlaps => "RESERVATION_MATCH_MODE_OVERLAPS",
+            ReservationMatchMode::StartsWithin => "RESERVATION_MATCH_MODE_STARTS_WITHIN",
+        }
+    }
+}
+/// sort key of query results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ReservationSortKey {
+    /// sort by reservation start time
+    Start = 0,
+    /// sort by reservation end time
+    End = 1,
+    /// sort by reservation id
+    Id = 2,
+}
+impl ReservationSortKey {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ReservationSortKey::Start => "RESERVATION_SORT_KEY_START",
+            ReservationSortKey::End => "RESERVATION_SORT_KEY_END",
+            ReservationSortKey::Id => "RESERVATION_SORT_KEY_ID",
+        }
+    }
+}
+/// what a delegate may do for the grantor of a delegation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DelegationScope {
+    Unknown = 0,
+    /// book reservations for the grantor
+    Book = 1,
+    /// book, confirm, update and cancel the reservations of the grantor
+    Manage = 2,
+}
+impl DelegationScope {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DelegationScope::Unknown => "DELEGATION_SCOPE_UNKNOWN",
+            DelegationScope::Book => "DELEGATION_SCOPE_BOOK",
+            DelegationScope::Manage => "DELEGATION_SCOPE_MANAGE",
+        }
+    }
+}
+/// machine readable reason of a failed request, the service sends its name in
+/// the `x-rsvp-error` metadata of the grpc status
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorReason {
+    Unknown = 0,
+    InvalidReservationId = 1,
+    InvalidTimespan = 2,
+    /// any other invalid argument
+    InvalidArgument = 3,
+    NotFound = 4,
+    Conflict = 5,
+    IdempotencyKeyReused = 6,
+    Unauthenticated = 7,
+    PermissionDenied = 8,
+    Internal = 9,
+}
+impl ErrorReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ErrorReason::Unknown => "ERROR_REASON_UNKNOWN",
+            ErrorReason::InvalidReservationId => "ERROR_REASON_INVALID_RESERVATION_ID",
+            ErrorReason::InvalidTimespan => "ERROR_REASON_INVALID_TIMESPAN",
+            ErrorReason::InvalidArgument => "ERROR_REASON_INVALID_ARGUMENT",
+            ErrorReason::NotFound => "ERROR_REASON_NOT_FOUND",
+            ErrorReason::Conflict => "ERROR_REASON_CONFLICT",
+            ErrorReason::IdempotencyKeyReused => "ERROR_REASON_IDEMPOTENCY_KEY_REUSED",
+            ErrorReason::Unauthenticated => "ERROR_REASON_UNAUTHENTICATED",
+            ErrorReason::PermissionDenied => "ERROR_REASON_PERMISSION_DENIED",
+            ErrorReason::Internal => "ERROR_REASON_INTERNAL",
+        }
+    }
+}
 /// Generated client implementations.
 pub mod reservation_service_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -367,6 +699,23 @@ pub mod reservation_service_client {
                 http::uri::PathAndQuery::from_static("/reservation.ReservationService/cancel");
             self.inner.unary(request.into_request(), path, codec).await
         }
+        /// cancel all reservations matching the query in one transaction
+        pub async fn cancel_by_filter(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CancelByFilterRequest>,
+        ) -> Result<tonic::Response<super::CancelByFilterResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/cancel_by_filter",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
         /// get a reservation by id
         pub async fn get(
             &mut self,
@@ -421,7 +770,7 @@ pub mod reservation_service_client {
         pub async fn listen(
             &mut self,
             request: impl tonic::IntoRequest<super::ListenRequest>,
-        ) -> Result<tonic::Response<tonic::codec::Streaming<super::Reservation>>, tonic::Status>
+        ) -> Result<tonic::Response<tonic::codec::Streaming<super::ListenResponse>>, tonic::Status>
         {
             self.inner.ready().await.map_err(|e| {
                 tonic::Status::new(
@@ -436,6 +785,108 @@ pub mod reservation_service_client {
                 .server_streaming(request.into_request(), path, codec)
                 .await
         }
+        /// create a webhook called on reservation changes
+        pub async fn create_webhook(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateWebhookRequest>,
+        ) -> Result<tonic::Response<super::CreateWebhookResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/create_webhook",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// list the webhooks, secrets are not returned
+        pub async fn list_webhooks(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListWebhooksRequest>,
+        ) -> Result<tonic::Response<super::ListWebhooksResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/list_webhooks",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// delete a webhook, its pending deliveries are dropped
+        pub async fn delete_webhook(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteWebhookRequest>,
+        ) -> Result<tonic::Response<super::DeleteWebhookResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/delete_webhook",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// let another user book for the grantor
+        pub async fn create_delegation(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CreateDelegationRequest>,
+        ) -> Result<tonic::Response<super::CreateDelegationResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/create_delegation",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// list the delegations granted by or to a user, expired ones included
+        pub async fn list_delegations(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ListDelegationsRequest>,
+        ) -> Result<tonic::Response<super::ListDelegationsResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/list_delegations",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// revoke a delegation
+        pub async fn delete_delegation(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteDelegationRequest>,
+        ) -> Result<tonic::Response<super::DeleteDelegationResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/reservation.ReservationService/delete_delegation",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -465,6 +916,11 @@ pub mod reservation_service_server {
             &self,
             request: tonic::Request<super::CancelRequest>,
         ) -> Result<tonic::Response<super::CancelResponse>, tonic::Status>;
+        /// cancel all reservations matching the query in one transaction
+        async fn cancel_by_filter(
+            &self,
+            request: tonic::Request<super::CancelByFilterRequest>,
+        ) -> Result<tonic::Response<super::CancelByFilterResponse>, tonic::Status>;
         /// get a reservation by id
         async fn get(
             &self,
@@ -485,7 +941,7 @@ pub mod reservation_service_server {
             request: tonic::Request<super::FilterRequest>,
         ) -> Result<tonic::Response<super::FilterResponse>, tonic::Status>;
         ///Server streaming response type for the listen method.
-        type listenStream: futures_core::Stream<Item = Result<super::Reservation, tonic::Status>>
+        type listenStream: futures_core::Stream<Item = Result<super::ListenResponse, tonic::Status>>
             + Send
             + 'static;
         /// another system could monitor newly added/confirmed/cancelled reservations
@@ -493,6 +949,36 @@ pub mod reservation_service_server {
             &self,
             request: tonic::Request<super::ListenRequest>,
         ) -> Result<tonic::Response<Self::listenStream>, tonic::Status>;
+        /// create a webhook called on reservation changes
+        async fn create_webhook(
+            &self,
+            request: tonic::Request<super::CreateWebhookRequest>,
+        ) -> Result<tonic::Response<super::CreateWebhookResponse>, tonic::Status>;
+        /// list the webhooks, secrets are not returned
+        async fn list_webhooks(
+            &self,
+            request: tonic::Request<super::ListWebhooksRequest>,
+        ) -> Result<tonic::Response<super::ListWebhooksResponse>, tonic::Status>;
+        /// delete a webhook, its pending deliveries are dropped
+        async fn delete_webhook(
+            &self,
+            request: tonic::Request<super::DeleteWebhookRequest>,
+        ) -> Result<tonic::Response<super::DeleteWebhookResponse>, tonic::Status>;
+        /// let another user book for the grantor
+        async fn create_delegation(
+            &self,
+            request: tonic::Request<super::CreateDelegationRequest>,
+        ) -> Result<tonic::Response<super::CreateDelegationResponse>, tonic::Status>;
+        /// list the delegations granted by or to a user, expired ones included
+        async fn list_delegations(
+            &self,
+            request: tonic::Request<super::ListDelegationsRequest>,
+        ) -> Result<tonic::Response<super::ListDelegationsResponse>, tonic::Status>;
+        /// revoke a delegation
+        async fn delete_delegation(
+            &self,
+            request: tonic::Request<super::DeleteDelegationRequest>,
+        ) -> Result<tonic::Response<super::DeleteDelegationResponse>, tonic::Status>;
     }
     /// Reservation service
     #[derive(Debug)]
@@ -672,6 +1158,40 @@ pub mod reservation_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/reservation.ReservationService/cancel_by_filter" => {
+                    #[allow(non_camel_case_types)]
+                    struct cancel_by_filterSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::CancelByFilterRequest>
+                        for cancel_by_filterSvc<T>
+                    {
+                        type Response = super::CancelByFilterResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CancelByFilterRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).cancel_by_filter(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = cancel_by_filterSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/reservation.ReservationService/get" => {
                     #[allow(non_camel_case_types)]
                     struct getSvc<T: ReservationService>(pub Arc<T>);
@@ -776,7 +1296,7 @@ pub mod reservation_service_server {
                         tonic::server::ServerStreamingService<super::ListenRequest>
                         for listenSvc<T>
                     {
-                        type Response = super::Reservation;
+                        type Response = super::ListenResponse;
                         type ResponseStream = T::listenStream;
                         type Future =
                             BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
@@ -805,6 +1325,210 @@ pub mod reservation_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/reservation.ReservationService/create_webhook" => {
+                    #[allow(non_camel_case_types)]
+                    struct create_webhookSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::CreateWebhookRequest>
+                        for create_webhookSvc<T>
+                    {
+                        type Response = super::CreateWebhookResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateWebhookRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).create_webhook(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = create_webhookSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/reservation.ReservationService/list_webhooks" => {
+                    #[allow(non_camel_case_types)]
+                    struct list_webhooksSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::ListWebhooksRequest>
+                        for list_webhooksSvc<T>
+                    {
+                        type Response = super::ListWebhooksResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListWebhooksRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_webhooks(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = list_webhooksSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/reservation.ReservationService/delete_webhook" => {
+                    #[allow(non_camel_case_types)]
+                    struct delete_webhookSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::DeleteWebhookRequest>
+                        for delete_webhookSvc<T>
+                    {
+                        type Response = super::DeleteWebhookResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteWebhookRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).delete_webhook(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = delete_webhookSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/reservation.ReservationService/create_delegation" => {
+                    #[allow(non_camel_case_types)]
+                    struct create_delegationSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::CreateDelegationRequest>
+                        for create_delegationSvc<T>
+                    {
+                        type Response = super::CreateDelegationResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CreateDelegationRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).create_delegation(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = create_delegationSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/reservation.ReservationService/list_delegations" => {
+                    #[allow(non_camel_case_types)]
+                    struct list_delegationsSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::ListDelegationsRequest>
+                        for list_delegationsSvc<T>
+                    {
+                        type Response = super::ListDelegationsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListDelegationsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).list_delegations(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = list_delegationsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/reservation.ReservationService/delete_delegation" => {
+                    #[allow(non_camel_case_types)]
+                    struct delete_delegationSvc<T: ReservationService>(pub Arc<T>);
+                    impl<T: ReservationService>
+                        tonic::server::UnaryService<super::DeleteDelegationRequest>
+                        for delete_delegationSvc<T>
+                    {
+                        type Response = super::DeleteDelegationResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteDelegationRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).delete_delegation(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = delete_delegationSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -0,0 +1,36 @@
+use crate::DelegationScope;
+use std::{fmt, str::FromStr};
+
+impl DelegationScope {
+    /// whether a delegation of this scope allows what `scope` allows.
+    pub fn covers(&self, scope: DelegationScope) -> bool {
+        match self {
+            DelegationScope::Manage => scope != DelegationScope::Unknown,
+            DelegationScope::Book => scope == DelegationScope::Book,
+            DelegationScope::Unknown => false,
+        }
+    }
+}
+
+impl fmt::Display for DelegationScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelegationScope::Book => write!(f, "book"),
+            DelegationScope::Manage => write!(f, "manage"),
+            DelegationScope::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromStr for DelegationScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "book" => Ok(DelegationScope::Book),
+            "manage" => Ok(DelegationScope::Manage),
+            "unknown" => Ok(DelegationScope::Unknown),
+            _ => Err(format!("invalid delegation scope: {s}")),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use crate::ErrorReason;
+use std::str::FromStr;
+
+impl FromStr for ErrorReason {
+    type Err = String;
+
+    /// parse the name of the reason, as sent in the `x-rsvp-error` metadata.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..)
+            .map_while(ErrorReason::from_i32)
+            .find(|reason| reason.as_str_name() == s)
+            .ok_or_else(|| format!("invalid error reason: {s}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_reason_should_parse_its_name() {
+        let reason = ErrorReason::InvalidTimespan;
+        assert_eq!(reason.as_str_name().parse::<ErrorReason>().unwrap(), reason);
+        assert!("invalid timespan".parse::<ErrorReason>().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! serde helpers used by the generated types, so they can be serialized to json.
+
+/// (de)serialize `Option<Timestamp>` as an RFC 3339 string or null
+pub(crate) mod timestamp {
+    use crate::{to_timestamp, to_utc_time};
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use prost_types::Timestamp;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(ts: &Option<Timestamp>, s: S) -> Result<S::Ok, S::Error> {
+        match ts {
+            Some(ts) => {
+                s.serialize_str(&to_utc_time(ts).to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Timestamp>, D::Error> {
+        let Some(s) = Option::<String>::deserialize(d)? else {
+            return Ok(None);
+        };
+        let dt = DateTime::parse_from_rfc3339(&s).map_err(D::Error::custom)?;
+        Ok(Some(to_timestamp(dt.with_timezone(&Utc))))
+    }
+}
+
+/// (de)serialize a `ReservationStatus` stored as i32 by its lowercase name
+pub(crate) mod status {
+    use crate::ReservationStatus;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(status: &i32, s: S) -> Result<S::Ok, S::Error> {
+        let status = ReservationStatus::from_i32(*status).unwrap_or(ReservationStatus::Unknown);
+        s.serialize_str(&status.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<i32, D::Error> {
+        let s = String::deserialize(d)?;
+        let status: ReservationStatus = s.parse().map_err(D::Error::custom)?;
+        Ok(status as i32)
+    }
+}
+
+/// (de)serialize `ReservationUpdateType`s stored as i32 by their lowercase names
+pub(crate) mod ops {
+    use crate::ReservationUpdateType;
+    use serde::{de::Error, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(ops: &[i32], s: S) -> Result<S::Ok, S::Error> {
+        let mut seq = s.serialize_seq(Some(ops.len()))?;
+        for op in ops {
+            let op = ReservationUpdateType::from_i32(*op).unwrap_or(ReservationUpdateType::Unknown);
+            seq.serialize_element(&op.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<i32>, D::Error> {
+        Vec::<String>::deserialize(d)?
+            .iter()
+            .map(|s| {
+                let op: ReservationUpdateType = s.parse().map_err(D::Error::custom)?;
+                Ok(op as i32)
+            })
+            .collect()
+    }
+}
+
+/// (de)serialize a `DelegationScope` stored as i32 by its lowercase name
+pub(crate) mod scope {
+    use crate::DelegationScope;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(scope: &i32, s: S) -> Result<S::Ok, S::Error> {
+        let scope = DelegationScope::from_i32(*scope).unwrap_or(DelegationScope::Unknown);
+        s.serialize_str(&scope.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<i32, D::Error> {
+        let s = String::deserialize(d)?;
+        let scope: DelegationScope = s.parse().map_err(D::Error::custom)?;
+        Ok(scope as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{to_timestamp, Reservation, ReservationStatus};
+
+    #[test]
+    fn reservation_should_round_trip_json() {
+        let rsvp = Reservation {
+            id: "a3a4f0ce-c2a7-4a06-a08f-2d7f0c5e0d4b".to_string(),
+            user_id: "M4n5ter".to_string(),
+            status: ReservationStatus::Confirmed as i32,
+            resource_id: "class room 1".to_string(),
+            start: Some(to_timestamp("2022-11-18T04:00:00Z".parse().unwrap())),
+            end: None,
+            note: "".to_string(),
+            tenant_id: "".to_string(),
+            booked_by: "".to_string(),
+        };
+        let json = serde_json::to_value(&rsvp).unwrap();
+        assert_eq!(json["status"], "confirmed");
+        assert_eq!(json["start"], "2022-11-18T04:00:00Z");
+        assert!(json["end"].is_null());
+        assert_eq!(serde_json::from_value::<Reservation>(json).unwrap(), rsvp);
+
+        // missing fields take their defaults, offsets are converted to utc
+        let rsvp: Reservation = serde_json::from_str(
+            r#"{"user_id":"M4n5ter","status":"pending","start":"2022-11-18T12:00:00+08:00"}"#,
+        )
+        .unwrap();
+        assert_eq!(rsvp.status, ReservationStatus::Pending as i32);
+        assert_eq!(
+            rsvp.start,
+            Some(to_timestamp("2022-11-18T04:00:00Z".parse().unwrap()))
+        );
+
+        assert!(serde_json::from_str::<Reservation>(r#"{"status":"done"}"#).is_err());
+    }
+}
@@ -1,5 +1,10 @@
+mod delegation_scope;
+mod error_reason;
+pub(crate) mod json;
 mod reservation;
 mod reservation_query;
 mod reservation_status;
+mod reservation_update_type;
 
 pub use reservation_status::RsvpStatus;
+pub use reservation_update_type::RsvpUpdateType;
@@ -38,6 +38,8 @@ impl FromRow<'_, PgRow> for Reservation {
             start: Some(to_timestamp(start)),
             end: Some(to_timestamp(end)),
             note: row.get("note"),
+            tenant_id: row.get("tenant_id"),
+            booked_by: row.get("booked_by"),
         })
     }
 }
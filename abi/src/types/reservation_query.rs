@@ -1,4 +1,8 @@
-use crate::{pb::ReservationQuery, ReservationStatus};
+use crate::{
+    pb::{ReservationFilter, ReservationQuery},
+    to_timestamp, ReservationQueryBuilder, ReservationSortKey, ReservationStatus,
+};
+use chrono::{DateTime, Utc};
 
 impl ReservationQuery {
     // / get status that can be used in sqlx query.
@@ -13,4 +17,59 @@ impl ReservationQuery {
             )
         }
     }
+
+    /// get all statuses (status and statuses) that can be used in sqlx query, UNKNOWN is skipped.
+    pub fn get_statuses(&self) -> Vec<String> {
+        let mut statuses: Vec<String> = std::iter::once(&self.status)
+            .chain(self.statuses.iter())
+            .filter_map(|s| ReservationStatus::from_i32(*s))
+            .filter(|s| *s != ReservationStatus::Unknown)
+            .map(|s| s.to_string())
+            .collect();
+        statuses.sort();
+        statuses.dedup();
+        statuses
+    }
+
+    /// get all user ids (user_id and user_ids), empty ids are skipped.
+    pub fn get_user_ids(&self) -> Vec<String> {
+        merge_ids(&self.user_id, &self.user_ids)
+    }
+
+    /// get all resource ids (resource_id and resource_ids), empty ids are skipped.
+    pub fn get_resource_ids(&self) -> Vec<String> {
+        merge_ids(&self.resource_id, &self.resource_ids)
+    }
+}
+
+impl ReservationFilter {
+    /// the query matching the same reservations in the same order, without paging.
+    pub fn to_query(&self) -> ReservationQuery {
+        ReservationQuery {
+            resource_id: self.resource_id.clone(),
+            user_id: self.user_id.clone(),
+            status: self.status,
+            desc: self.desc,
+            sort_by: ReservationSortKey::Id as i32,
+            ..Default::default()
+        }
+    }
+}
+
+impl ReservationQueryBuilder {
+    /// set start and end time of the query with chrono types.
+    pub fn timespan(&mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> &mut Self {
+        self.start(to_timestamp(start)).end(to_timestamp(end))
+    }
+}
+
+fn merge_ids(id: &str, ids: &[String]) -> Vec<String> {
+    let mut ids: Vec<String> = std::iter::once(id)
+        .chain(ids.iter().map(String::as_str))
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
 }
@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use sqlx::{postgres::PgRow, FromRow, Row};
 
@@ -15,6 +15,20 @@ impl fmt::Display for ReservationStatus {
     }
 }
 
+impl FromStr for ReservationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(ReservationStatus::Pending),
+            "blocked" => Ok(ReservationStatus::Blocked),
+            "confirmed" => Ok(ReservationStatus::Confirmed),
+            "unknown" => Ok(ReservationStatus::Unknown),
+            _ => Err(format!("invalid reservation status: {s}")),
+        }
+    }
+}
+
 impl From<RsvpStatus> for ReservationStatus {
     fn from(status: RsvpStatus) -> Self {
         match status {
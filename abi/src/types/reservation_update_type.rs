@@ -0,0 +1,48 @@
+use crate::ReservationUpdateType;
+use std::{fmt, str::FromStr};
+
+impl fmt::Display for ReservationUpdateType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReservationUpdateType::Create => write!(f, "create"),
+            ReservationUpdateType::Update => write!(f, "update"),
+            ReservationUpdateType::Delete => write!(f, "delete"),
+            ReservationUpdateType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromStr for ReservationUpdateType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "create" => Ok(ReservationUpdateType::Create),
+            "update" => Ok(ReservationUpdateType::Update),
+            "delete" => Ok(ReservationUpdateType::Delete),
+            "unknown" => Ok(ReservationUpdateType::Unknown),
+            _ => Err(format!("invalid reservation update type: {s}")),
+        }
+    }
+}
+
+impl From<RsvpUpdateType> for ReservationUpdateType {
+    fn from(op: RsvpUpdateType) -> Self {
+        match op {
+            RsvpUpdateType::Unknown => ReservationUpdateType::Unknown,
+            RsvpUpdateType::Create => ReservationUpdateType::Create,
+            RsvpUpdateType::Update => ReservationUpdateType::Update,
+            RsvpUpdateType::Delete => ReservationUpdateType::Delete,
+        }
+    }
+}
+
+/// database equivalent of the "reservation_update_type" enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "reservation_update_type", rename_all = "lowercase")]
+pub enum RsvpUpdateType {
+    Unknown,
+    Create,
+    Update,
+    Delete,
+}
@@ -0,0 +1,75 @@
+use abi::{ErrorReason, ERROR_REASON_KEY};
+use thiserror::Error;
+use tonic::{Code, Status};
+
+/// errors returned by the reservation client, mirroring the server side ReservationError
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("invalid reservation id")]
+    InvalidReservationId,
+    #[error("invalid timespan")]
+    InvalidTimespan,
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("not found")]
+    NotFound,
+    #[error("reservation conflict: {0}")]
+    Conflict(String),
+    #[error("idempotency key reused: {0}")]
+    IdempotencyKeyReused(String),
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+    #[error("unimplemented: {0}")]
+    Unimplemented(String),
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("server error: {0}")]
+    Internal(String),
+}
+
+// map a grpc status returned by the server to a client error, by the reason the server sends
+// in the metadata or else the status code
+impl From<Status> for ClientError {
+    fn from(status: Status) -> Self {
+        let reason = status
+            .metadata()
+            .get(ERROR_REASON_KEY)
+            .and_then(|reason| reason.to_str().ok())
+            .and_then(|reason| reason.parse::<ErrorReason>().ok());
+        let message = status.message().to_string();
+        match reason {
+            Some(ErrorReason::InvalidReservationId) => ClientError::InvalidReservationId,
+            Some(ErrorReason::InvalidTimespan) => ClientError::InvalidTimespan,
+            Some(ErrorReason::InvalidArgument) => ClientError::InvalidArgument(message),
+            Some(ErrorReason::NotFound) => ClientError::NotFound,
+            Some(ErrorReason::Conflict) => ClientError::Conflict(message),
+            Some(ErrorReason::IdempotencyKeyReused) => ClientError::IdempotencyKeyReused(message),
+            Some(ErrorReason::Unauthenticated) => ClientError::Unauthenticated(message),
+            Some(ErrorReason::PermissionDenied) => ClientError::PermissionDenied(message),
+            Some(ErrorReason::Internal) => ClientError::Internal(message),
+            // errors of older servers or of the transport have no reason
+            Some(ErrorReason::Unknown) | None => from_code(status.code(), message),
+        }
+    }
+}
+
+fn from_code(code: Code, message: String) -> ClientError {
+    match code {
+        Code::InvalidArgument => ClientError::InvalidArgument(message),
+        Code::NotFound => ClientError::NotFound,
+        // the server reports conflicting reservations as failed precondition
+        Code::FailedPrecondition | Code::AlreadyExists => ClientError::Conflict(message),
+        Code::Unavailable => ClientError::Unavailable(message),
+        Code::DeadlineExceeded => ClientError::DeadlineExceeded,
+        Code::Unimplemented => ClientError::Unimplemented(message),
+        Code::Unauthenticated => ClientError::Unauthenticated(message),
+        Code::PermissionDenied => ClientError::PermissionDenied(message),
+        _ => ClientError::Internal(message),
+    }
+}
@@ -0,0 +1,536 @@
+mod error;
+
+use abi::{
+    reservation_service_client::ReservationServiceClient, to_timestamp, CancelByFilterRequest,
+    CancelRequest, ConfirmRequest, CreateDelegationRequest, CreateWebhookRequest,
+    DeleteDelegationRequest, DeleteWebhookRequest, FilterRequest, GetRequest,
+    ListDelegationsRequest, ListWebhooksRequest, QueryRequest, ReserveRequest, UpdateRequest,
+};
+pub use abi::{
+    Delegation, DelegationScope, FilterPager, FilterResponse, ListenRequest, ListenResponse,
+    Reservation, ReservationFilter, ReservationMatchMode, ReservationQuery,
+    ReservationQueryBuilder, ReservationSortKey, ReservationStatus, ReservationUpdateType, Webhook,
+};
+use chrono::{DateTime, Utc};
+pub use error::ClientError;
+use futures::{Stream, StreamExt, TryStreamExt};
+use std::{future::Future, time::Duration};
+use tonic::{
+    transport::{Channel, Endpoint},
+    Code, Request, Response, Status,
+};
+use uuid::Uuid;
+
+/// metadata key of the tenant, see the service
+const TENANT_HEADER: &str = "x-tenant-id";
+
+/// options of the reservation client
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// deadline of a single call, not applied to `listen`
+    pub timeout: Duration,
+    /// how many times an unavailable call is retried
+    pub max_retries: u32,
+    /// delay before the first retry, doubled for every following retry
+    pub backoff: Duration,
+    /// tenant sent in the `x-tenant-id` metadata, empty for the default tenant
+    pub tenant: String,
+    /// bearer token sent in the `authorization` metadata, none if the service has no auth
+    pub token: Option<String>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+            tenant: String::new(),
+            token: None,
+        }
+    }
+}
+
+/// high level client of ReservationService
+#[derive(Debug, Clone)]
+pub struct ReservationClient {
+    inner: ReservationServiceClient<Channel>,
+    options: ClientOptions,
+}
+
+impl ReservationClient {
+    /// connect to the service with default options, e.g. `http://127.0.0.1:50051`.
+    pub async fn connect(endpoint: impl Into<String>) -> Result<Self, ClientError> {
+        Self::connect_with(endpoint, ClientOptions::default()).await
+    }
+
+    /// connect to the service with the given options.
+    pub async fn connect_with(
+        endpoint: impl Into<String>,
+        options: ClientOptions,
+    ) -> Result<Self, ClientError> {
+        let channel = Endpoint::from_shared(endpoint.into())?
+            .connect_timeout(options.timeout)
+            .connect()
+            .await?;
+        Ok(Self::with_channel(channel, options))
+    }
+
+    /// create a client from an existing channel.
+    pub fn with_channel(channel: Channel, options: ClientOptions) -> Self {
+        Self {
+            inner: ReservationServiceClient::new(channel),
+            options,
+        }
+    }
+
+    /// make a pending reservation.
+    ///
+    /// Retries reuse a generated idempotency key, so a retried request never books twice.
+    pub async fn reserve(
+        &self,
+        user_id: impl Into<String>,
+        resource_id: impl Into<String>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        note: impl Into<String>,
+    ) -> Result<Reservation, ClientError> {
+        let (user_id, resource_id, note) = (user_id.into(), resource_id.into(), note.into());
+        self.reserve_for(user_id, resource_id, start, end, note, false)
+            .await
+    }
+
+    /// make a pending reservation for another user, only admins may. the service records the
+    /// caller as the actor.
+    pub async fn reserve_on_behalf_of(
+        &self,
+        user_id: impl Into<String>,
+        resource_id: impl Into<String>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        note: impl Into<String>,
+    ) -> Result<Reservation, ClientError> {
+        let (user_id, resource_id, note) = (user_id.into(), resource_id.into(), note.into());
+        self.reserve_for(user_id, resource_id, start, end, note, true)
+            .await
+    }
+
+    async fn reserve_for(
+        &self,
+        user_id: String,
+        resource_id: String,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        note: String,
+        on_behalf_of: bool,
+    ) -> Result<Reservation, ClientError> {
+        if start >= end {
+            return Err(ClientError::InvalidTimespan);
+        }
+        let request = ReserveRequest {
+            reservation: Some(Reservation {
+                user_id,
+                status: ReservationStatus::Pending as i32,
+                resource_id,
+                start: Some(to_timestamp(start)),
+                end: Some(to_timestamp(end)),
+                note,
+                ..Default::default()
+            }),
+            idempotency_key: Uuid::new_v4().to_string(),
+            on_behalf_of,
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.reserve(request).await
+            })
+            .await?;
+        Ok(response.reservation.unwrap_or_default())
+    }
+
+    /// confirm a pending reservation.
+    pub async fn confirm(&self, id: &str) -> Result<Reservation, ClientError> {
+        let request = ConfirmRequest {
+            id: parse_id(id)?,
+            idempotency_key: Uuid::new_v4().to_string(),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.confirm(request).await
+            })
+            .await?;
+        Ok(response.reservation.unwrap_or_default())
+    }
+
+    /// update the note of a reservation.
+    pub async fn update_note(
+        &self,
+        id: &str,
+        note: impl Into<String>,
+    ) -> Result<Reservation, ClientError> {
+        let request = UpdateRequest {
+            id: parse_id(id)?,
+            note: note.into(),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.update(request).await
+            })
+            .await?;
+        Ok(response.reservation.unwrap_or_default())
+    }
+
+    /// cancel a reservation.
+    pub async fn cancel(&self, id: &str) -> Result<Reservation, ClientError> {
+        let request = CancelRequest {
+            id: parse_id(id)?,
+            idempotency_key: Uuid::new_v4().to_string(),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.cancel(request).await
+            })
+            .await?;
+        Ok(response.reservation.unwrap_or_default())
+    }
+
+    /// cancel all reservations matching the query, only return them if `dry_run` is true.
+    pub async fn cancel_by_filter(
+        &self,
+        query: ReservationQuery,
+        dry_run: bool,
+    ) -> Result<Vec<Reservation>, ClientError> {
+        let request = CancelByFilterRequest {
+            query: Some(query),
+            dry_run,
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.cancel_by_filter(request).await
+            })
+            .await?;
+        Ok(response.reservations)
+    }
+
+    /// get a reservation by id.
+    pub async fn get(&self, id: &str) -> Result<Reservation, ClientError> {
+        let request = GetRequest { id: parse_id(id)? };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.get(request).await
+            })
+            .await?;
+        Ok(response.reservation.unwrap_or_default())
+    }
+
+    /// query reservations, build the query with `ReservationQueryBuilder`.
+    pub async fn query(&self, query: ReservationQuery) -> Result<Vec<Reservation>, ClientError> {
+        let request = QueryRequest { query: Some(query) };
+        let stream = self
+            .call(request, |mut client, request| async move {
+                client.query(request).await
+            })
+            .await?;
+        Ok(stream.try_collect().await?)
+    }
+
+    /// filter reservations, order by reservation id.
+    pub async fn filter(&self, filter: ReservationFilter) -> Result<FilterResponse, ClientError> {
+        let request = FilterRequest {
+            filter: Some(filter),
+        };
+        self.call(request, |mut client, request| async move {
+            client.filter(request).await
+        })
+        .await
+    }
+
+    /// listen to reservation changes matching the request, the stream ends when the server closes
+    /// it.
+    ///
+    /// To resume after a disconnect, listen again with `since` set to the id of the last change.
+    pub async fn listen(
+        &self,
+        request: ListenRequest,
+    ) -> Result<impl Stream<Item = Result<ListenResponse, ClientError>>, ClientError> {
+        let request = self.request(request)?;
+        let stream = self.inner.clone().listen(request).await?.into_inner();
+        Ok(stream.map(|change| change.map_err(ClientError::from)))
+    }
+
+    /// create a webhook called on reservation changes, keep the returned secret to verify them.
+    pub async fn create_webhook(&self, webhook: Webhook) -> Result<Webhook, ClientError> {
+        let request = CreateWebhookRequest {
+            webhook: Some(webhook),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.create_webhook(request).await
+            })
+            .await?;
+        Ok(response.webhook.unwrap_or_default())
+    }
+
+    /// list the webhooks, secrets are left empty.
+    pub async fn list_webhooks(&self) -> Result<Vec<Webhook>, ClientError> {
+        let response = self
+            .call(ListWebhooksRequest {}, |mut client, request| async move {
+                client.list_webhooks(request).await
+            })
+            .await?;
+        Ok(response.webhooks)
+    }
+
+    /// delete a webhook by id.
+    pub async fn delete_webhook(&self, id: &str) -> Result<Webhook, ClientError> {
+        let request = DeleteWebhookRequest { id: id.to_string() };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.delete_webhook(request).await
+            })
+            .await?;
+        Ok(response.webhook.unwrap_or_default())
+    }
+
+    /// let the grantee book or manage reservations for the grantor, the caller if empty.
+    /// delegating again to the same grantee replaces the scope and expiry.
+    pub async fn create_delegation(
+        &self,
+        delegation: Delegation,
+    ) -> Result<Delegation, ClientError> {
+        let request = CreateDelegationRequest {
+            delegation: Some(delegation),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.create_delegation(request).await
+            })
+            .await?;
+        Ok(response.delegation.unwrap_or_default())
+    }
+
+    /// list the delegations granted by or to the user, the caller if empty.
+    pub async fn list_delegations(&self, user_id: &str) -> Result<Vec<Delegation>, ClientError> {
+        let request = ListDelegationsRequest {
+            user_id: user_id.to_string(),
+        };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.list_delegations(request).await
+            })
+            .await?;
+        Ok(response.delegations)
+    }
+
+    /// revoke a delegation by id.
+    pub async fn delete_delegation(&self, id: &str) -> Result<Delegation, ClientError> {
+        let request = DeleteDelegationRequest { id: id.to_string() };
+        let response = self
+            .call(request, |mut client, request| async move {
+                client.delete_delegation(request).await
+            })
+            .await?;
+        Ok(response.delegation.unwrap_or_default())
+    }
+
+    /// send a request with deadline, retry with exponential backoff if the service is unavailable.
+    async fn call<Req, Res, F, Fut>(&self, request: Req, f: F) -> Result<Res, ClientError>
+    where
+        Req: Clone,
+        F: Fn(ReservationServiceClient<Channel>, Request<Req>) -> Fut,
+        Fut: Future<Output = Result<Response<Res>, Status>>,
+    {
+        let mut backoff = self.options.backoff;
+        let mut retries = 0;
+        loop {
+            let mut req = self.request(request.clone())?;
+            req.set_timeout(self.options.timeout);
+            match f(self.inner.clone(), req).await {
+                Ok(response) => return Ok(response.into_inner()),
+                Err(status)
+                    if status.code() == Code::Unavailable && retries < self.options.max_retries =>
+                {
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(status) => return Err(status.into()),
+            }
+        }
+    }
+
+    /// a request of the client's tenant, with the client's token
+    fn request<T>(&self, message: T) -> Result<Request<T>, ClientError> {
+        let mut request = Request::new(message);
+        if !self.options.tenant.is_empty() {
+            let tenant = self.options.tenant.parse().map_err(|_| {
+                ClientError::InvalidArgument(format!("invalid tenant: {}", self.options.tenant))
+            })?;
+            request.metadata_mut().insert(TENANT_HEADER, tenant);
+        }
+        if let Some(token) = &self.options.token {
+            let value = format!("Bearer {token}")
+                .parse()
+                .map_err(|_| ClientError::InvalidArgument("invalid token".to_string()))?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        Ok(request)
+    }
+}
+
+/// validate a reservation id before sending it
+fn parse_id(id: &str) -> Result<String, ClientError> {
+    Uuid::parse_str(id)
+        .map(|id| id.to_string())
+        .map_err(|_| ClientError::InvalidReservationId)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::{reservation_service_server::ReservationServiceServer, ErrorReason};
+    use reservation::ReservationManager;
+    use reservation_service::RsvpService;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    /// start a service on a random port and connect to it
+    async fn client(pool: sqlx::PgPool) -> ReservationClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = RsvpService::new(ReservationManager::new(pool));
+        tokio::spawn(
+            Server::builder()
+                .add_service(ReservationServiceServer::new(service))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        ReservationClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn errors_should_be_told_by_reason() {
+        let with_reason = |reason: ErrorReason| {
+            // the code is left unknown, only the reason tells the error
+            let mut status = Status::unknown("message");
+            let reason = reason.as_str_name().parse().unwrap();
+            status.metadata_mut().insert(abi::ERROR_REASON_KEY, reason);
+            ClientError::from(status)
+        };
+        let errors = [
+            (ErrorReason::InvalidReservationId, "invalid reservation id"),
+            (ErrorReason::InvalidTimespan, "invalid timespan"),
+            (ErrorReason::InvalidArgument, "invalid argument: message"),
+            (ErrorReason::NotFound, "not found"),
+            (ErrorReason::Conflict, "reservation conflict: message"),
+            (
+                ErrorReason::IdempotencyKeyReused,
+                "idempotency key reused: message",
+            ),
+            (ErrorReason::Unauthenticated, "unauthenticated: message"),
+            (ErrorReason::PermissionDenied, "permission denied: message"),
+            (ErrorReason::Internal, "server error: message"),
+        ];
+        for (reason, expected) in errors {
+            assert_eq!(with_reason(reason).to_string(), expected, "{reason:?}");
+        }
+        assert!(matches!(
+            with_reason(ErrorReason::IdempotencyKeyReused),
+            ClientError::IdempotencyKeyReused(_)
+        ));
+        // the message alone tells nothing
+        let status = Status::invalid_argument("invalid timespan");
+        assert!(matches!(
+            ClientError::from(status),
+            ClientError::InvalidArgument(_)
+        ));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn client_should_work() {
+        let client = client(migrated_pool.clone()).await;
+        let start: DateTime<Utc> = "2022-11-18T04:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2022-11-18T06:00:00Z".parse().unwrap();
+
+        let rsvp = client
+            .reserve("M4n5ter", "class room 1", start, end, "note")
+            .await
+            .unwrap();
+        let rsvp = client.confirm(&rsvp.id).await.unwrap();
+        assert_eq!(rsvp.status, ReservationStatus::Confirmed as i32);
+
+        let err = client
+            .reserve("Syuu", "class room 1", start, end, "")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Conflict(_)));
+
+        let query = ReservationQueryBuilder::default()
+            .timespan(start, end)
+            .build()
+            .unwrap();
+        let rsvps = client.query(query).await.unwrap();
+        assert_eq!(rsvps, vec![rsvp.clone()]);
+
+        client.cancel(&rsvp.id).await.unwrap();
+        let err = client.get(&rsvp.id).await.unwrap_err();
+        assert!(matches!(err, ClientError::NotFound));
+
+        let err = client.get("not a uuid").await.unwrap_err();
+        assert!(matches!(err, ClientError::InvalidReservationId));
+        let err = client
+            .reserve("M4n5ter", "class room 1", end, start, "")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::InvalidTimespan));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn listen_should_filter_and_resume() {
+        let client = client(migrated_pool.clone()).await;
+        let start: DateTime<Utc> = "2022-11-18T04:00:00Z".parse().unwrap();
+        let end: DateTime<Utc> = "2022-11-18T06:00:00Z".parse().unwrap();
+        let rsvp_1 = client
+            .reserve("M4n5ter", "class room 1", start, end, "")
+            .await
+            .unwrap();
+        let rsvp_2 = client
+            .reserve("Syuu", "class room 2", start, end, "")
+            .await
+            .unwrap();
+        client.cancel(&rsvp_1.id).await.unwrap();
+
+        // replay everything of class room 1
+        let request = ListenRequest {
+            resource_ids: vec!["class room 1".to_string()],
+            since: Some(0),
+            ..Default::default()
+        };
+        let changes: Vec<_> = client
+            .listen(request)
+            .await
+            .unwrap()
+            .take(2)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(changes[0].op, ReservationUpdateType::Create as i32);
+        assert_eq!(changes[1].op, ReservationUpdateType::Delete as i32);
+        assert!(changes
+            .iter()
+            .all(|c| c.reservation.as_ref().unwrap().id == rsvp_1.id));
+
+        // resume after the last one, only new changes follow
+        let request = ListenRequest {
+            since: Some(changes[1].id),
+            ..Default::default()
+        };
+        let mut stream = Box::pin(client.listen(request).await.unwrap());
+        client.confirm(&rsvp_2.id).await.unwrap();
+        let change = stream.next().await.unwrap().unwrap();
+        assert_eq!(change.op, ReservationUpdateType::Update as i32);
+        assert_eq!(change.reservation.unwrap().id, rsvp_2.id);
+    }
+}
@@ -0,0 +1,9 @@
+-- responses of requests sent with an idempotency key, a retried request returns the stored response
+CREATE TABLE rsvp.idempotency_keys (
+ KEY VARCHAR ( 128 ) NOT NULL,
+ op VARCHAR ( 16 ) NOT NULL,
+ response BYTEA,
+ expires_at TIMESTAMPTZ NOT NULL,
+ CONSTRAINT idempotency_keys_pkey PRIMARY KEY ( KEY )
+);
+CREATE INDEX idempotency_keys_expires_at_idx ON rsvp.idempotency_keys ( expires_at );
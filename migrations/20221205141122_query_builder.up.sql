@@ -0,0 +1,4 @@
+-- reservations are queried by a query builder in the application now
+DROP FUNCTION rsvp.query;
+-- the conflict constraint index only helps when resource_id is given, index timespan for the other queries
+CREATE INDEX reservations_timespan_idx ON rsvp.reservations USING gist ( timespan );
@@ -0,0 +1,2 @@
+-- for queries matching or sorting by the start of a reservation
+CREATE INDEX reservations_start_idx ON rsvp.reservations ( LOWER ( timespan ) );
@@ -0,0 +1,31 @@
+CREATE
+ OR REPLACE FUNCTION rsvp.reservations_trigger ( ) RETURNS TRIGGER AS $$ BEGIN
+ IF
+   -- update reservation_changes
+  TG_OP = 'INSERT' THEN
+   INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id )
+  VALUES
+   ( NEW.ID, 'create', NEW.user_id, NEW.resource_id );
+   -- if status changed, update reservation_changes
+  ELSIF TG_OP = 'UPDATE' THEN
+   IF
+    OLD.status <> NEW.status THEN
+     INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id )
+    VALUES
+     ( NEW.ID, 'update', NEW.user_id, NEW.resource_id );
+
+   END IF;
+   -- update reservation_changes
+   ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id )
+    VALUES
+     ( OLD.ID, 'delete', OLD.user_id, OLD.resource_id );
+
+   END IF;
+   -- notify a channel called reservation_update
+   NOTIFY reservation_update;
+   RETURN NULL;
+
+  END;
+  $$ LANGUAGE plpgsql;
+ALTER TABLE rsvp.reservation_changes DROP COLUMN status;
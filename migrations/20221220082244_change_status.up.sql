@@ -0,0 +1,38 @@
+-- keep the status of a change, so deleted reservations can be filtered by status
+ALTER TABLE rsvp.reservation_changes ADD COLUMN status rsvp.reservation_status;
+UPDATE rsvp.reservation_changes C
+SET status = r.status
+FROM
+ rsvp.reservations r
+WHERE
+ r.ID = C.reservation_id;
+CREATE
+ OR REPLACE FUNCTION rsvp.reservations_trigger ( ) RETURNS TRIGGER AS $$ BEGIN
+ IF
+   -- update reservation_changes
+  TG_OP = 'INSERT' THEN
+   INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+  VALUES
+   ( NEW.ID, 'create', NEW.user_id, NEW.resource_id, NEW.status );
+   -- if status changed, update reservation_changes
+  ELSIF TG_OP = 'UPDATE' THEN
+   IF
+    OLD.status <> NEW.status THEN
+     INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+    VALUES
+     ( NEW.ID, 'update', NEW.user_id, NEW.resource_id, NEW.status );
+
+   END IF;
+   -- update reservation_changes
+   ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+    VALUES
+     ( OLD.ID, 'delete', OLD.user_id, OLD.resource_id, OLD.status );
+
+   END IF;
+   -- notify a channel called reservation_update
+   NOTIFY reservation_update;
+   RETURN NULL;
+
+  END;
+  $$ LANGUAGE plpgsql;
@@ -0,0 +1,2 @@
+DROP INDEX rsvp.reservation_changes_id_idx;
+DROP TABLE rsvp.outbox_offsets;
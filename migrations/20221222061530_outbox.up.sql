@@ -0,0 +1,9 @@
+-- id of the last change delivered by each outbox publisher
+CREATE TABLE rsvp.outbox_offsets (
+ NAME VARCHAR ( 64 ) NOT NULL,
+ last_id BIGINT NOT NULL DEFAULT 0,
+ updated_at TIMESTAMPTZ NOT NULL DEFAULT now( ),
+ CONSTRAINT outbox_offsets_pkey PRIMARY KEY ( NAME )
+);
+-- changes are read in order after an offset
+CREATE UNIQUE INDEX reservation_changes_id_idx ON rsvp.reservation_changes ( ID );
@@ -0,0 +1,3 @@
+DROP TABLE rsvp.webhook_dead_letters;
+DROP TABLE rsvp.webhook_deliveries;
+DROP TABLE rsvp.webhooks;
@@ -0,0 +1,36 @@
+-- webhooks called on reservation changes
+CREATE TABLE rsvp.webhooks (
+ ID UUID NOT NULL DEFAULT gen_random_uuid ( ),
+ url TEXT NOT NULL,
+ secret TEXT NOT NULL,
+ ops rsvp.reservation_update_type [] NOT NULL DEFAULT '{}',
+ resource_ids VARCHAR ( 64 ) [] NOT NULL DEFAULT '{}',
+ created_at TIMESTAMPTZ NOT NULL DEFAULT now( ),
+ CONSTRAINT webhooks_pkey PRIMARY KEY ( ID )
+);
+-- pending deliveries, a change is delivered to a webhook once
+CREATE TABLE rsvp.webhook_deliveries (
+ ID BIGSERIAL NOT NULL,
+ webhook_id UUID NOT NULL REFERENCES rsvp.webhooks ( ID ) ON DELETE CASCADE,
+ change_id BIGINT NOT NULL,
+ op rsvp.reservation_update_type NOT NULL,
+ payload TEXT NOT NULL,
+ attempts INT NOT NULL DEFAULT 0,
+ next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now( ),
+ last_error TEXT,
+ CONSTRAINT webhook_deliveries_pkey PRIMARY KEY ( ID ),
+ CONSTRAINT webhook_deliveries_change UNIQUE ( webhook_id, change_id )
+);
+CREATE INDEX webhook_deliveries_next_attempt_at_idx ON rsvp.webhook_deliveries ( next_attempt_at );
+-- deliveries given up after too many attempts
+CREATE TABLE rsvp.webhook_dead_letters (
+ ID BIGINT NOT NULL,
+ webhook_id UUID NOT NULL REFERENCES rsvp.webhooks ( ID ) ON DELETE CASCADE,
+ change_id BIGINT NOT NULL,
+ op rsvp.reservation_update_type NOT NULL,
+ payload TEXT NOT NULL,
+ attempts INT NOT NULL,
+ last_error TEXT,
+ failed_at TIMESTAMPTZ NOT NULL DEFAULT now( ),
+ CONSTRAINT webhook_dead_letters_pkey PRIMARY KEY ( ID )
+);
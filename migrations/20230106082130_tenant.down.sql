@@ -0,0 +1,42 @@
+DROP POLICY reservation_changes_tenant ON rsvp.reservation_changes;
+ALTER TABLE rsvp.reservation_changes DISABLE ROW LEVEL SECURITY;
+DROP POLICY reservations_tenant ON rsvp.reservations;
+ALTER TABLE rsvp.reservations DISABLE ROW LEVEL SECURITY;
+CREATE
+ OR REPLACE FUNCTION rsvp.reservations_trigger ( ) RETURNS TRIGGER AS $$ BEGIN
+ IF
+   -- update reservation_changes
+  TG_OP = 'INSERT' THEN
+   INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+  VALUES
+   ( NEW.ID, 'create', NEW.user_id, NEW.resource_id, NEW.status );
+   -- if status changed, update reservation_changes
+  ELSIF TG_OP = 'UPDATE' THEN
+   IF
+    OLD.status <> NEW.status THEN
+     INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+    VALUES
+     ( NEW.ID, 'update', NEW.user_id, NEW.resource_id, NEW.status );
+
+   END IF;
+   -- update reservation_changes
+   ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status )
+    VALUES
+     ( OLD.ID, 'delete', OLD.user_id, OLD.resource_id, OLD.status );
+
+   END IF;
+   -- notify a channel called reservation_update
+   NOTIFY reservation_update;
+   RETURN NULL;
+
+  END;
+  $$ LANGUAGE plpgsql;
+ALTER TABLE rsvp.idempotency_keys DROP CONSTRAINT idempotency_keys_pkey,
+DROP COLUMN tenant_id,
+ADD CONSTRAINT idempotency_keys_pkey PRIMARY KEY ( KEY );
+DROP INDEX rsvp.reservation_changes_tenant_id_idx;
+ALTER TABLE rsvp.reservations DROP CONSTRAINT reservations_conflict,
+ADD CONSTRAINT reservations_conflict EXCLUDE USING gist ( resource_id WITH =, timespan WITH && );
+ALTER TABLE rsvp.reservation_changes DROP COLUMN tenant_id;
+ALTER TABLE rsvp.reservations DROP COLUMN tenant_id;
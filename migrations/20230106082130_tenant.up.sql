@@ -0,0 +1,49 @@
+-- reservations, their changes and idempotency keys belong to a tenant, '' is the default tenant
+ALTER TABLE rsvp.reservations ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+ALTER TABLE rsvp.reservation_changes ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+-- resources of different tenants never conflict
+ALTER TABLE rsvp.reservations DROP CONSTRAINT reservations_conflict,
+ADD CONSTRAINT reservations_conflict EXCLUDE USING gist ( tenant_id WITH =, resource_id WITH =, timespan WITH && );
+CREATE INDEX reservation_changes_tenant_id_idx ON rsvp.reservation_changes ( tenant_id, ID );
+ALTER TABLE rsvp.idempotency_keys ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '',
+DROP CONSTRAINT idempotency_keys_pkey,
+ADD CONSTRAINT idempotency_keys_pkey PRIMARY KEY ( tenant_id, KEY );
+CREATE
+ OR REPLACE FUNCTION rsvp.reservations_trigger ( ) RETURNS TRIGGER AS $$ BEGIN
+ IF
+   -- update reservation_changes
+  TG_OP = 'INSERT' THEN
+   INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+  VALUES
+   ( NEW.ID, 'create', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+   -- if status changed, update reservation_changes
+  ELSIF TG_OP = 'UPDATE' THEN
+   IF
+    OLD.status <> NEW.status THEN
+     INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+    VALUES
+     ( NEW.ID, 'update', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+
+   END IF;
+   -- update reservation_changes
+   ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+    VALUES
+     ( OLD.ID, 'delete', OLD.user_id, OLD.resource_id, OLD.status, OLD.tenant_id );
+
+   END IF;
+   -- notify a channel called reservation_update
+   NOTIFY reservation_update;
+   RETURN NULL;
+
+  END;
+  $$ LANGUAGE plpgsql;
+-- row level security, the manager sets rsvp.tenant_id in every transaction. the policies don't
+-- apply to the owner of the tables, run the service as another role to enforce them. the outbox
+-- and webhook publishers read the changes of every tenant, and need a role bypassing them
+ALTER TABLE rsvp.reservations ENABLE ROW LEVEL SECURITY;
+CREATE POLICY reservations_tenant ON rsvp.reservations
+ USING ( tenant_id = current_setting( 'rsvp.tenant_id', TRUE ) );
+ALTER TABLE rsvp.reservation_changes ENABLE ROW LEVEL SECURITY;
+CREATE POLICY reservation_changes_tenant ON rsvp.reservation_changes
+ USING ( tenant_id = current_setting( 'rsvp.tenant_id', TRUE ) );
@@ -0,0 +1,37 @@
+-- who made every change, the manager sets rsvp.actor in its transactions. a change of another
+-- user's reservation is made on behalf of that user
+ALTER TABLE rsvp.reservation_changes ADD COLUMN actor VARCHAR ( 64 ) NOT NULL DEFAULT '',
+ADD COLUMN on_behalf_of BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE
+ OR REPLACE FUNCTION rsvp.reservations_trigger ( ) RETURNS TRIGGER AS $$
+ DECLARE
+  change_actor VARCHAR ( 64 ) := COALESCE ( current_setting( 'rsvp.actor', TRUE ), '' );
+ BEGIN
+ IF
+   -- update reservation_changes
+  TG_OP = 'INSERT' THEN
+   INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+  VALUES
+   ( NEW.ID, 'create', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id, change_actor, change_actor <> '' AND change_actor <> NEW.user_id );
+   -- if status changed, update reservation_changes
+  ELSIF TG_OP = 'UPDATE' THEN
+   IF
+    OLD.status <> NEW.status THEN
+     INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+    VALUES
+     ( NEW.ID, 'update', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id, change_actor, change_actor <> '' AND change_actor <> NEW.user_id );
+
+   END IF;
+   -- update reservation_changes
+   ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO rsvp.reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+    VALUES
+     ( OLD.ID, 'delete', OLD.user_id, OLD.resource_id, OLD.status, OLD.tenant_id, change_actor, change_actor <> '' AND change_actor <> OLD.user_id );
+
+   END IF;
+   -- notify a channel called reservation_update
+   NOTIFY reservation_update;
+   RETURN NULL;
+
+  END;
+  $$ LANGUAGE plpgsql;
@@ -0,0 +1,2 @@
+DROP TABLE rsvp.delegations;
+ALTER TABLE rsvp.reservations DROP COLUMN booked_by;
@@ -0,0 +1,18 @@
+-- who booked a reservation, differs from user_id if booked on behalf of the user
+ALTER TABLE rsvp.reservations ADD COLUMN booked_by VARCHAR ( 64 ) NOT NULL DEFAULT '';
+-- the grantor lets the grantee book for them, one delegation per pair of users in a tenant
+CREATE TABLE rsvp.delegations (
+ ID UUID NOT NULL DEFAULT gen_random_uuid ( ),
+ tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '',
+ grantor VARCHAR ( 64 ) NOT NULL,
+ grantee VARCHAR ( 64 ) NOT NULL,
+ SCOPE VARCHAR ( 16 ) NOT NULL CHECK ( SCOPE IN ( 'book', 'manage' ) ),
+ expires_at TIMESTAMPTZ,
+ created_at TIMESTAMPTZ NOT NULL DEFAULT now( ),
+ CONSTRAINT delegations_pkey PRIMARY KEY ( ID ),
+ CONSTRAINT delegations_pair UNIQUE ( tenant_id, grantor, grantee )
+);
+CREATE INDEX delegations_grantee_idx ON rsvp.delegations ( tenant_id, grantee );
+ALTER TABLE rsvp.delegations ENABLE ROW LEVEL SECURITY;
+CREATE POLICY delegations_tenant ON rsvp.delegations
+ USING ( tenant_id = current_setting( 'rsvp.tenant_id', TRUE ) );
@@ -0,0 +1,3 @@
+-- a retry should be the same request of the same caller, keys claimed before have no fingerprint
+-- and are never replayed
+ALTER TABLE rsvp.idempotency_keys ADD COLUMN fingerprint VARCHAR ( 64 ) NOT NULL DEFAULT '';
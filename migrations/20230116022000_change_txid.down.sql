@@ -0,0 +1,3 @@
+ALTER TABLE rsvp.outbox_offsets DROP COLUMN last_txid;
+DROP INDEX rsvp.reservation_changes_txid_idx;
+ALTER TABLE rsvp.reservation_changes DROP COLUMN txid;
@@ -0,0 +1,7 @@
+-- the transaction recording each change, changes are read in the order of their transactions
+-- once every older one has ended, so a change committed after a higher id is never skipped
+ALTER TABLE rsvp.reservation_changes ADD COLUMN txid BIGINT NOT NULL DEFAULT pg_current_xact_id( )::TEXT::BIGINT;
+CREATE UNIQUE INDEX reservation_changes_txid_idx ON rsvp.reservation_changes ( txid, ID );
+-- publishers resume after the transaction and id of the last delivered change
+ALTER TABLE rsvp.outbox_offsets ADD COLUMN last_txid BIGINT NOT NULL DEFAULT 0;
+UPDATE rsvp.outbox_offsets o SET last_txid = c.txid FROM rsvp.reservation_changes c WHERE c.id = o.last_id;
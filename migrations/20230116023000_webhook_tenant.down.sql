@@ -0,0 +1,6 @@
+DROP POLICY webhooks_tenant ON rsvp.webhooks;
+ALTER TABLE rsvp.webhooks DISABLE ROW LEVEL SECURITY;
+ALTER TABLE rsvp.webhook_dead_letters DROP COLUMN tenant_id;
+ALTER TABLE rsvp.webhook_deliveries DROP COLUMN tenant_id;
+DROP INDEX rsvp.webhooks_tenant_id_idx;
+ALTER TABLE rsvp.webhooks DROP COLUMN tenant_id;
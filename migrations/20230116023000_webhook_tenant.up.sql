@@ -0,0 +1,9 @@
+-- webhooks belong to a tenant and only receive the changes of it, '' is the default tenant
+ALTER TABLE rsvp.webhooks ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+CREATE INDEX webhooks_tenant_id_idx ON rsvp.webhooks ( tenant_id );
+ALTER TABLE rsvp.webhook_deliveries ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+ALTER TABLE rsvp.webhook_dead_letters ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+-- like the changes, deliveries are made by a role bypassing the policy
+ALTER TABLE rsvp.webhooks ENABLE ROW LEVEL SECURITY;
+CREATE POLICY webhooks_tenant ON rsvp.webhooks
+ USING ( tenant_id = current_setting( 'rsvp.tenant_id', TRUE ) );
@@ -0,0 +1,12 @@
+-- whether the actor of the transaction may change a reservation of the user in the tenant. with
+-- rsvp.check_owner on, only the user or a grantee of a manage delegation of the user may, so
+-- the check and the change happen in the same statement
+CREATE FUNCTION rsvp.may_change ( VARCHAR, VARCHAR ) RETURNS BOOLEAN AS $$
+ SELECT current_setting( 'rsvp.check_owner', TRUE ) IS DISTINCT FROM 'on'
+  OR $2 = COALESCE( current_setting( 'rsvp.actor', TRUE ), '' )
+  OR EXISTS (
+   SELECT 1 FROM rsvp.delegations d
+   WHERE d.tenant_id = $1 AND d.grantor = $2 AND d.grantee = current_setting( 'rsvp.actor', TRUE )
+    AND d.SCOPE = 'manage' AND ( d.expires_at IS NULL OR d.expires_at > now( ) )
+  );
+$$ LANGUAGE SQL STABLE;
@@ -0,0 +1 @@
+DROP FUNCTION rsvp.may_book ( VARCHAR, VARCHAR );
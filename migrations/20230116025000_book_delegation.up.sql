@@ -0,0 +1,13 @@
+-- whether the actor of the transaction may book for the user in the tenant. with
+-- rsvp.check_owner on, only the user or a grantee of any delegation of the user may. the
+-- delegation is locked until the booking commits, so it can't be revoked in between
+CREATE FUNCTION rsvp.may_book ( VARCHAR, VARCHAR ) RETURNS BOOLEAN AS $$
+ SELECT current_setting( 'rsvp.check_owner', TRUE ) IS DISTINCT FROM 'on'
+  OR $2 = COALESCE( current_setting( 'rsvp.actor', TRUE ), '' )
+  OR EXISTS (
+   SELECT 1 FROM rsvp.delegations d
+   WHERE d.tenant_id = $1 AND d.grantor = $2 AND d.grantee = current_setting( 'rsvp.actor', TRUE )
+    AND ( d.expires_at IS NULL OR d.expires_at > now( ) )
+   FOR SHARE
+  );
+$$ LANGUAGE SQL VOLATILE;
@@ -0,0 +1,337 @@
+//! bulk export of reservations to CSV or newline delimited JSON, and import from them.
+
+use crate::{import::ImportReport, ReservationError, Rsvp};
+use abi::{Reservation, ReservationQuery, ReservationSortKey, ReservationStatus};
+use futures::Stream;
+use std::{str::FromStr, sync::Arc};
+
+/// reservations fetched per query while exporting, every page is one chunk of the stream
+const EXPORT_PAGE_SIZE: i64 = 500;
+/// columns of the CSV, in the field order of `Reservation`
+const CSV_HEADER: [&str; 9] = [
+    "id",
+    "user_id",
+    "status",
+    "resource_id",
+    "start",
+    "end",
+    "note",
+    "tenant_id",
+    "booked_by",
+];
+
+/// format of a bulk export or import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkFormat {
+    /// comma separated values with a header row
+    Csv,
+    /// one json object per line
+    Ndjson,
+}
+
+impl BulkFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            BulkFormat::Csv => "text/csv; charset=utf-8",
+            BulkFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+impl FromStr for BulkFormat {
+    type Err = ReservationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(BulkFormat::Csv),
+            "ndjson" | "jsonl" => Ok(BulkFormat::Ndjson),
+            _ => Err(ReservationError::InvalidFilter(format!(
+                "unsupported format: {s}"
+            ))),
+        }
+    }
+}
+
+/// stream every reservation matching the query, one page at a time.
+///
+/// page, page_size and sort key of the query are ignored, reservations are paged by id so
+/// concurrent changes don't repeat or skip any. The CSV header is sent even if nothing matches.
+pub fn export_rows<R: Rsvp + Send + Sync + ?Sized + 'static>(
+    rsvp: Arc<R>,
+    mut query: ReservationQuery,
+    format: BulkFormat,
+) -> impl Stream<Item = Result<String, ReservationError>> + Send {
+    query.sort_by = ReservationSortKey::Id as i32;
+    query.page_size = EXPORT_PAGE_SIZE;
+    // None once the last page is sent, Some(None) before the first one
+    futures::stream::try_unfold(Some(None), move |cursor: Option<Option<String>>| {
+        let rsvp = rsvp.clone();
+        let mut query = query.clone();
+        async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let header = cursor.is_none();
+            query.cursor = cursor;
+            let rsvps = rsvp.query(query).await?;
+            let chunk = render_rows(&rsvps, format, header)?;
+            let next = (rsvps.len() as i64 == EXPORT_PAGE_SIZE)
+                .then(|| rsvps.last().map(|r| r.id.clone()));
+            Ok(Some((chunk, next)))
+        }
+    })
+}
+
+/// render reservations as rows of the format, with the CSV header if `header` is true
+pub fn render_rows(
+    rsvps: &[Reservation],
+    format: BulkFormat,
+    header: bool,
+) -> Result<String, ReservationError> {
+    match format {
+        BulkFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            if header {
+                writer.write_record(CSV_HEADER).map_err(format_error)?;
+            }
+            for rsvp in rsvps {
+                writer.serialize(rsvp).map_err(format_error)?;
+            }
+            let bytes = writer.into_inner().map_err(format_error)?;
+            String::from_utf8(bytes).map_err(format_error)
+        }
+        BulkFormat::Ndjson => {
+            let mut rows = String::new();
+            for rsvp in rsvps {
+                rows.push_str(&serde_json::to_string(rsvp).map_err(format_error)?);
+                rows.push('\n');
+            }
+            Ok(rows)
+        }
+    }
+}
+
+/// validate and reserve every row, report the conflicts and invalid rows instead of stopping at
+/// the first one.
+///
+/// ids, tenant ids and booked_by in the rows are ignored, a missing status means pending. In dry run mode
+/// nothing is reserved, the report tells what would have been.
+pub async fn import_rows<R: Rsvp + ?Sized>(
+    rsvp: &R,
+    data: &str,
+    format: BulkFormat,
+    dry_run: bool,
+) -> Result<ImportReport, ReservationError> {
+    let mut report = ImportReport::default();
+    let mut items = Vec::new();
+    for (line, row) in parse_rows(data, format) {
+        let source = format!("line {line}");
+        match row.and_then(validate) {
+            Ok(rsvp) => items.push((source, rsvp)),
+            Err(e) => report.fail(source, e),
+        }
+    }
+    report.reserve(rsvp, items, dry_run).await?;
+    Ok(report)
+}
+
+/// parse the rows with their line numbers
+fn parse_rows(data: &str, format: BulkFormat) -> Vec<(u64, Result<Reservation, String>)> {
+    match format {
+        BulkFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(data.as_bytes());
+            let mut rows = Vec::new();
+            let mut records = reader.deserialize::<Reservation>();
+            loop {
+                // the position is of the row about to be read
+                let line = records.reader().position().line();
+                let Some(row) = records.next() else {
+                    break;
+                };
+                rows.push((line, row.map_err(|e| e.to_string())));
+            }
+            rows
+        }
+        BulkFormat::Ndjson => data
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let row = serde_json::from_str(line).map_err(|e| e.to_string());
+                (i as u64 + 1, row)
+            })
+            .collect(),
+    }
+}
+
+fn validate(mut rsvp: Reservation) -> Result<Reservation, String> {
+    if rsvp.user_id.is_empty() {
+        return Err("missing user_id".to_string());
+    }
+    if rsvp.resource_id.is_empty() {
+        return Err("missing resource_id".to_string());
+    }
+    if rsvp.start.is_none() || rsvp.end.is_none() {
+        return Err("missing start or end".to_string());
+    }
+    if rsvp.status == ReservationStatus::Unknown as i32 {
+        rsvp.status = ReservationStatus::Pending as i32;
+    }
+    rsvp.id.clear();
+    Ok(rsvp)
+}
+
+fn format_error(e: impl std::fmt::Display) -> ReservationError {
+    ReservationError::FormatError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryReservationManager, ReservationManager};
+    use abi::ReservationQueryBuilder;
+    use futures::TryStreamExt;
+
+    async fn export(manager: &Arc<ReservationManager>, format: BulkFormat) -> String {
+        let query = ReservationQueryBuilder::default().build().unwrap();
+        let chunks: Vec<String> = export_rows(manager.clone(), query, format)
+            .try_collect()
+            .await
+            .unwrap();
+        chunks.concat()
+    }
+
+    #[tokio::test]
+    async fn export_should_not_skip_rows_when_earlier_ones_are_deleted() {
+        let manager = Arc::new(MemoryReservationManager::new());
+        let start: chrono::DateTime<chrono::Utc> = "2022-01-01T04:00:00Z".parse().unwrap();
+        for day in 0..=EXPORT_PAGE_SIZE {
+            let start = start + chrono::Duration::days(day);
+            manager
+                .reserve(Reservation {
+                    user_id: "M4n5ter".to_string(),
+                    resource_id: "room".to_string(),
+                    start: Some(abi::to_timestamp(start)),
+                    end: Some(abi::to_timestamp(start + chrono::Duration::hours(1))),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let query = ReservationQueryBuilder::default().build().unwrap();
+        let mut rows = Box::pin(export_rows(manager.clone(), query, BulkFormat::Ndjson));
+        let first = rows.try_next().await.unwrap().unwrap();
+        assert_eq!(first.lines().count() as i64, EXPORT_PAGE_SIZE);
+        // deleting an exported row doesn't shift the next page
+        let exported: Reservation = serde_json::from_str(first.lines().next().unwrap()).unwrap();
+        manager.delete(exported.id.parse().unwrap()).await.unwrap();
+        let rest: Vec<String> = rows.try_collect().await.unwrap();
+        assert_eq!(rest.concat().lines().count(), 1);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn rows_should_round_trip() {
+        let manager = Arc::new(ReservationManager::new(migrated_pool.clone()));
+        assert_eq!(
+            export(&manager, BulkFormat::Csv).await,
+            "id,user_id,status,resource_id,start,end,note,tenant_id,booked_by\n"
+        );
+        for (i, note) in ["plain", "with, comma", "with \"quotes\"\nand a line"]
+            .iter()
+            .enumerate()
+        {
+            manager
+                .reserve(Reservation {
+                    user_id: "M4n5ter".to_string(),
+                    status: ReservationStatus::Pending as i32,
+                    resource_id: format!("room {i}"),
+                    start: Some("2022-11-18T04:00:00Z".parse().unwrap()),
+                    end: Some("2022-11-18T06:00:00Z".parse().unwrap()),
+                    note: note.to_string(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let csv = export(&manager, BulkFormat::Csv).await;
+        assert!(csv.contains(",pending,room 0,2022-11-18T04:00:00Z,2022-11-18T06:00:00Z,plain,,\n"));
+        assert!(csv.contains("\"with, comma\""));
+        let ndjson = export(&manager, BulkFormat::Ndjson).await;
+        assert_eq!(ndjson.lines().count(), 3);
+
+        // every row conflicts with the exported reservation
+        for (data, format) in [(&csv, BulkFormat::Csv), (&ndjson, BulkFormat::Ndjson)] {
+            let report = import_rows(manager.as_ref(), data, format, false)
+                .await
+                .unwrap();
+            assert!(report.reserved.is_empty());
+            assert_eq!(report.conflicts.len(), 3);
+        }
+
+        // moved to other rooms, the rows are reserved with new ids
+        let moved = csv.replace(",room ", ",new room ");
+        let report = import_rows(manager.as_ref(), &moved, BulkFormat::Csv, true)
+            .await
+            .unwrap();
+        assert_eq!(report.reserved.len(), 3);
+        assert!(report.dry_run);
+        assert_eq!(export(&manager, BulkFormat::Csv).await, csv);
+
+        let report = import_rows(manager.as_ref(), &moved, BulkFormat::Csv, false)
+            .await
+            .unwrap();
+        let quoted = report
+            .reserved
+            .iter()
+            .find(|r| r.resource_id == "new room 2")
+            .unwrap();
+        assert_eq!(quoted.note, "with \"quotes\"\nand a line");
+        assert!(!csv.contains(&report.reserved[0].id));
+        assert_eq!(
+            export(&manager, BulkFormat::Ndjson).await.lines().count(),
+            6
+        );
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn invalid_rows_should_be_reported() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let ndjson = [
+            r#"{"user_id":"M4n5ter","resource_id":"room 1","start":"2022-11-18T04:00:00Z","end":"2022-11-18T06:00:00Z"}"#,
+            "",
+            r#"{"user_id":"M4n5ter","start":"2022-11-18T04:00:00Z","end":"2022-11-18T06:00:00Z"}"#,
+            r#"{"user_id":"M4n5ter","resource_id":"room 1","status":"done"}"#,
+            r#"{"user_id":"Syuu","resource_id":"room 1","start":"2022-11-18T05:00:00Z","end":"2022-11-18T07:00:00Z"}"#,
+            r#"{"user_id":"Syuu","resource_id":"room 2","start":"2022-11-18T07:00:00Z","end":"2022-11-18T05:00:00Z"}"#,
+            "not json",
+        ]
+        .join("\n");
+        let report = import_rows(&manager, &ndjson, BulkFormat::Ndjson, false)
+            .await
+            .unwrap();
+        assert_eq!(report.reserved.len(), 1);
+        assert_eq!(report.reserved[0].status, ReservationStatus::Pending as i32);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].source, "line 5");
+        let failures: Vec<_> = report
+            .failures
+            .iter()
+            .map(|f| (f.source.as_str(), f.error.as_str()))
+            .collect();
+        assert_eq!(failures[0], ("line 3", "missing resource_id"));
+        assert_eq!(failures[1].0, "line 4");
+        assert_eq!(failures[2].0, "line 7");
+        assert_eq!(failures[3], ("line 6", "invalid timespan"));
+
+        let csv = "user_id,resource_id,start,end\nM4n5ter,room 3,2022-11-18T04:00:00Z,yesterday\n";
+        let report = import_rows(&manager, csv, BulkFormat::Csv, false)
+            .await
+            .unwrap();
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].source, "line 2");
+    }
+}
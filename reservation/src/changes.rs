@@ -0,0 +1,299 @@
+use crate::{manager::begin, ReservationError};
+use abi::{ReservationStatus, ReservationUpdateType, RsvpStatus, RsvpUpdateType};
+use futures::{stream, Stream};
+use serde::Serialize;
+use sqlx::{
+    postgres::{PgListener, PgRow},
+    types::Uuid,
+    FromRow, PgExecutor, PgPool, Row,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    pin::Pin,
+    time::Duration,
+};
+
+/// channel notified by the reservations trigger
+pub(crate) const CHANNEL: &str = "reservation_update";
+/// changes are also polled in this interval, in case a notification is lost on reconnecting
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// changes waiting for an older transaction to end are polled in this interval
+pub(crate) const HOLD_BACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// a change of reservation recorded in rsvp.reservation_changes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReservationChange {
+    /// sequence number of the change
+    pub id: i64,
+    pub op: ReservationUpdateType,
+    /// current state of the reservation, only id, tenant_id, user_id, resource_id and status are
+    /// populated if it was deleted
+    pub reservation: abi::Reservation,
+    /// user id of the caller who made the change, empty if unknown
+    pub actor: String,
+    /// whether the actor changed a reservation of another user
+    pub on_behalf_of: bool,
+}
+
+/// which changes a listener receives
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListenFilter {
+    /// only changes of these users, empty means any user
+    pub user_ids: Vec<String>,
+    /// only changes of these resources, empty means any resource
+    pub resource_ids: Vec<String>,
+    /// only changes of reservations in these statuses, empty means any status
+    pub statuses: Vec<ReservationStatus>,
+    /// only changes of these types, empty means any type
+    pub ops: Vec<ReservationUpdateType>,
+    /// resume after this change id, only changes made after listening are streamed if none
+    pub since: Option<i64>,
+}
+
+impl ListenFilter {
+    /// whether the change should be sent to the listener.
+    pub fn matches(&self, change: &ReservationChange) -> bool {
+        let rsvp = &change.reservation;
+        (self.user_ids.is_empty() || self.user_ids.contains(&rsvp.user_id))
+            && (self.resource_ids.is_empty() || self.resource_ids.contains(&rsvp.resource_id))
+            && (self.statuses.is_empty() || self.statuses.iter().any(|s| *s as i32 == rsvp.status))
+            && (self.ops.is_empty() || self.ops.contains(&change.op))
+    }
+}
+
+// unknown statuses and ops in the request are ignored
+impl From<abi::ListenRequest> for ListenFilter {
+    fn from(request: abi::ListenRequest) -> Self {
+        Self {
+            user_ids: request.user_ids,
+            resource_ids: request.resource_ids,
+            statuses: request
+                .statuses
+                .into_iter()
+                .filter_map(ReservationStatus::from_i32)
+                .collect(),
+            ops: request
+                .ops
+                .into_iter()
+                .filter_map(ReservationUpdateType::from_i32)
+                .collect(),
+            since: request.since,
+        }
+    }
+}
+
+pub type ReservationChangeStream =
+    Pin<Box<dyn Stream<Item = Result<ReservationChange, ReservationError>> + Send>>;
+
+impl From<ReservationChange> for abi::ListenResponse {
+    fn from(change: ReservationChange) -> Self {
+        Self {
+            op: change.op as i32,
+            reservation: Some(change.reservation),
+            id: change.id,
+            actor: change.actor,
+            on_behalf_of: change.on_behalf_of,
+        }
+    }
+}
+
+struct ListenState {
+    listener: PgListener,
+    pool: PgPool,
+    tenant: String,
+    filter: ListenFilter,
+    cursor: Cursor,
+    /// changes after the cursor already committed when the stream was created
+    seen: HashSet<i64>,
+    held_back: bool,
+    pending: VecDeque<ReservationChange>,
+}
+
+impl ListenState {
+    /// fetch the changes after the cursor, queue the ones matching the filter.
+    async fn fetch(&mut self) -> Result<(), ReservationError> {
+        let mut tx = begin(&self.pool, &self.tenant).await?;
+        let batch = fetch_batch(&mut tx, Some(&self.tenant), self.cursor, None).await?;
+        tx.commit().await?;
+        for (position, change) in batch.changes {
+            self.cursor = position;
+            if !self.seen.remove(&change.id) && self.filter.matches(&change) {
+                self.pending.push_back(change);
+            }
+        }
+        self.held_back = batch.held_back;
+        Ok(())
+    }
+}
+
+/// stream the changes of the tenant matching the filter, from `filter.since` or the time the
+/// stream is created.
+pub(crate) async fn listen(
+    pool: &PgPool,
+    tenant: &str,
+    filter: ListenFilter,
+) -> Result<ReservationChangeStream, ReservationError> {
+    // listen before reading the cursor, so no change falls in between
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    let mut tx = begin(pool, tenant).await?;
+    let (cursor, seen) = match filter.since {
+        // resume after the position of the change
+        Some(since) => {
+            let txid: Option<i64> = sqlx::query_scalar(
+                "SELECT txid FROM rsvp.reservation_changes WHERE id <= $1 ORDER BY id DESC LIMIT 1",
+            )
+            .bind(since)
+            .fetch_optional(&mut tx)
+            .await?;
+            let cursor = Cursor {
+                txid: txid.unwrap_or_default(),
+                id: since,
+            };
+            (cursor, HashSet::new())
+        }
+        // skip the changes already committed: the ones before the horizon, and the ones after
+        // it visible now
+        None => {
+            let row = sqlx::query(
+                "WITH s AS (SELECT pg_snapshot_xmin(pg_current_snapshot())::TEXT::BIGINT AS horizon) SELECT s.horizon, COALESCE(array_agg(c.id::BIGINT) FILTER (WHERE c.id IS NOT NULL), '{}') AS seen FROM s LEFT JOIN rsvp.reservation_changes c ON c.txid >= s.horizon AND c.tenant_id = $1 GROUP BY s.horizon",
+            )
+            .bind(tenant)
+            .fetch_one(&mut tx)
+            .await?;
+            let cursor = Cursor {
+                txid: row.get::<i64, _>("horizon") - 1,
+                id: i64::MAX,
+            };
+            let seen: Vec<i64> = row.get("seen");
+            (cursor, seen.into_iter().collect())
+        }
+    };
+    tx.commit().await?;
+
+    let mut state = ListenState {
+        listener,
+        pool: pool.clone(),
+        tenant: tenant.to_string(),
+        filter,
+        cursor,
+        seen,
+        held_back: false,
+        pending: VecDeque::new(),
+    };
+    if state.filter.since.is_some() {
+        // catch up with the changes missed since then
+        state.fetch().await?;
+    }
+    let changes = stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        loop {
+            if let Some(change) = state.pending.pop_front() {
+                return Some((Ok(change), Some(state)));
+            }
+            // a timeout means no notification, fetch anyway. changes held back by an older
+            // transaction are fetched again soon, it may end without a notification
+            let interval = if state.held_back {
+                HOLD_BACK_INTERVAL
+            } else {
+                POLL_INTERVAL
+            };
+            if let Ok(Err(e)) = tokio::time::timeout(interval, state.listener.recv()).await {
+                return Some((Err(e.into()), None));
+            }
+            if let Err(e) = state.fetch().await {
+                return Some((Err(e), None));
+            }
+        }
+    });
+    Ok(Box::pin(changes))
+}
+
+/// position in rsvp.reservation_changes.
+///
+/// Change ids are taken when a change is recorded but become visible when its transaction
+/// commits, so a change may show up after a higher id was read. Changes are thus read in the
+/// order of the transactions recording them, then by id, and only once every older transaction
+/// has ended: a change visible later always comes after the cursor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Cursor {
+    pub txid: i64,
+    pub id: i64,
+}
+
+/// changes read after a cursor, each with its own position
+pub(crate) struct ChangeBatch {
+    pub changes: Vec<(Cursor, ReservationChange)>,
+    /// whether committed changes after the batch wait for an older transaction to end
+    pub held_back: bool,
+}
+
+/// fetch the changes after the cursor in order, together with the current state of their
+/// reservations. changes of every tenant are fetched if no tenant is given.
+pub(crate) async fn fetch_batch<'c, E: PgExecutor<'c>>(
+    executor: E,
+    tenant: Option<&str>,
+    cursor: Cursor,
+    limit: Option<i64>,
+) -> Result<ChangeBatch, ReservationError> {
+    // ordered by txid, the changes of transactions older than every running one come first
+    let rows = sqlx::query(
+        "SELECT c.txid AS change_txid, c.txid < pg_snapshot_xmin(pg_current_snapshot())::TEXT::BIGINT AS change_settled, c.id::BIGINT AS change_id, c.reservation_id, c.op, c.user_id AS change_user_id, c.resource_id AS change_resource_id, c.status AS change_status, c.tenant_id AS change_tenant_id, c.actor AS change_actor, c.on_behalf_of AS change_on_behalf_of, r.* FROM rsvp.reservation_changes c LEFT JOIN rsvp.reservations r ON r.id = c.reservation_id WHERE (c.txid, c.id) > ($1, $2) AND ($4::VARCHAR IS NULL OR c.tenant_id = $4) ORDER BY c.txid, c.id LIMIT $3",
+    )
+    .bind(cursor.txid)
+    .bind(cursor.id)
+    .bind(limit)
+    .bind(tenant)
+    .fetch_all(executor)
+    .await?;
+
+    let mut batch = ChangeBatch {
+        changes: Vec::with_capacity(rows.len()),
+        held_back: false,
+    };
+    for row in &rows {
+        if !row.get::<bool, _>("change_settled") {
+            batch.held_back = true;
+            break;
+        }
+        let change = change_from_row(row)?;
+        let position = Cursor {
+            txid: row.get("change_txid"),
+            id: change.id,
+        };
+        batch.changes.push((position, change));
+    }
+    Ok(batch)
+}
+
+/// a change joined with the current state of its reservation
+fn change_from_row(row: &PgRow) -> Result<ReservationChange, ReservationError> {
+    let op: RsvpUpdateType = row.get("op");
+    let exists = row.get::<Option<Uuid>, _>("id").is_some();
+    let reservation = if exists {
+        abi::Reservation::from_row(row)?
+    } else {
+        abi::Reservation {
+            id: row.get::<Uuid, _>("reservation_id").to_string(),
+            user_id: row
+                .get::<Option<String>, _>("change_user_id")
+                .unwrap_or_default(),
+            resource_id: row
+                .get::<Option<String>, _>("change_resource_id")
+                .unwrap_or_default(),
+            status: row
+                .get::<Option<RsvpStatus>, _>("change_status")
+                .map_or(ReservationStatus::Unknown, ReservationStatus::from)
+                as i32,
+            tenant_id: row.get("change_tenant_id"),
+            ..Default::default()
+        }
+    };
+    Ok(ReservationChange {
+        id: row.get("change_id"),
+        op: op.into(),
+        reservation,
+        actor: row.get("change_actor"),
+        on_behalf_of: row.get("change_on_behalf_of"),
+    })
+}
@@ -0,0 +1,853 @@
+//! backend agnostic conformance checks, every `Rsvp` implementation should pass them.
+//!
+//! Each check uses its own user and resource ids, so they can run against a store that already
+//! holds reservations, including the ones of earlier checks. A check panics on the first
+//! difference from the postgres backed ReservationManager.
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn my_backend_should_conform() {
+//!     reservation::conformance::check_all(&MyBackend::new()).await;
+//! }
+//! ```
+//!
+//! Backends scoped to a tenant also check `tenant_isolation` with two handles of one store, and
+//! backends recording the actor of changes check `actor_recording`.
+
+use crate::{ListenFilter, ReservationChangeStream, ReservationError, ReservationId, Rsvp};
+use abi::{
+    to_timestamp, Reservation, ReservationFilter, ReservationMatchMode, ReservationQuery,
+    ReservationSortKey, ReservationStatus, ReservationUpdateType,
+};
+use futures::StreamExt;
+use std::time::Duration;
+
+/// how long to wait for a change before failing
+const CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// run every check against the backend.
+pub async fn check_all<R: Rsvp + ?Sized>(rsvp: &R) {
+    reserve(rsvp).await;
+    conflict(rsvp).await;
+    reserve_batch(rsvp).await;
+    confirm(rsvp).await;
+    cancel(rsvp).await;
+    idempotency(rsvp).await;
+    query_pagination(rsvp).await;
+    query_match_modes(rsvp).await;
+    filter_cursors(rsvp).await;
+    cancel_by_filter(rsvp).await;
+    change_events(rsvp).await;
+}
+
+/// reserve returns the reservation with a new id, invalid timespans are rejected.
+pub async fn reserve<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let reserved = rsvp
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert!(!reserved.id.is_empty());
+    assert_eq!(reserved.note, "conformance");
+    assert_eq!(rsvp.get(id(&reserved)).await.unwrap(), reserved);
+
+    let err = rsvp
+        .reserve(ids.reservation(1, "2022-11-18T06:00:00Z", "2022-11-18T04:00:00Z"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::InvalidTimespan));
+    let mut missing = ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z");
+    missing.end = None;
+    let err = rsvp.reserve(missing).await.unwrap_err();
+    assert!(matches!(err, ReservationError::InvalidTimespan));
+}
+
+/// overlapping reservations on a resource conflict, touching ones and other resources don't.
+pub async fn conflict<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let first = rsvp
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    for (start, end) in [
+        ("2022-11-18T03:00:00Z", "2022-11-18T05:00:00Z"),
+        ("2022-11-18T05:00:00Z", "2022-11-18T07:00:00Z"),
+        ("2022-11-18T04:30:00Z", "2022-11-18T05:00:00Z"),
+        ("2022-11-18T01:00:00Z", "2022-11-18T09:00:00Z"),
+    ] {
+        let err = rsvp
+            .reserve(ids.reservation(0, start, end))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ReservationError::Conflict(_)),
+            "{start} - {end} should conflict: {err}"
+        );
+    }
+    // whatever the status, the period is taken
+    let mut blocked = ids.reservation(0, "2022-11-18T05:00:00Z", "2022-11-18T05:30:00Z");
+    blocked.status = ReservationStatus::Blocked as i32;
+    assert_conflict(&rsvp.reserve(blocked).await.unwrap_err());
+
+    rsvp.reserve(ids.reservation(0, "2022-11-18T06:00:00Z", "2022-11-18T07:00:00Z"))
+        .await
+        .unwrap();
+    rsvp.reserve(ids.reservation(0, "2022-11-18T02:00:00Z", "2022-11-18T04:00:00Z"))
+        .await
+        .unwrap();
+    rsvp.reserve(ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+
+    // the period is free again after the delete
+    rsvp.delete(id(&first)).await.unwrap();
+    rsvp.reserve(ids.reservation(0, "2022-11-18T04:30:00Z", "2022-11-18T05:00:00Z"))
+        .await
+        .unwrap();
+}
+
+/// a failure in a batch doesn't affect the others, a dry run reserves nothing.
+pub async fn reserve_batch<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let batch = vec![
+        ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+        ids.reservation(0, "2022-11-18T05:00:00Z", "2022-11-18T07:00:00Z"),
+        ids.reservation(1, "2022-11-18T07:00:00Z", "2022-11-18T05:00:00Z"),
+        ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+    ];
+    let results = rsvp.reserve_batch(batch.clone(), true).await.unwrap();
+    assert!(results[0].is_ok() && results[3].is_ok());
+    assert_conflict(results[1].as_ref().unwrap_err());
+    assert!(matches!(results[2], Err(ReservationError::InvalidTimespan)));
+    assert!(rsvp.query(ids.query()).await.unwrap().is_empty());
+
+    let results = rsvp.reserve_batch(batch, false).await.unwrap();
+    let reserved: Vec<_> = results.into_iter().filter_map(Result::ok).collect();
+    assert_eq!(reserved.len(), 2);
+    assert_eq!(rsvp.query(ids.query()).await.unwrap().len(), 2);
+}
+
+/// only pending reservations can be confirmed, anything else is not found.
+pub async fn confirm<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let reserved = rsvp
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    let confirmed = rsvp.change_status(id(&reserved)).await.unwrap();
+    assert_eq!(confirmed.status, ReservationStatus::Confirmed as i32);
+    assert_eq!(confirmed.id, reserved.id);
+    assert_not_found(&rsvp.change_status(id(&reserved)).await.unwrap_err());
+    assert_not_found(
+        &rsvp
+            .change_status(ReservationId::new_v4())
+            .await
+            .unwrap_err(),
+    );
+
+    let noted = rsvp
+        .update_note(id(&reserved), "updated".to_string())
+        .await
+        .unwrap();
+    assert_eq!(noted.note, "updated");
+    assert_eq!(noted.status, ReservationStatus::Confirmed as i32);
+    assert_not_found(
+        &rsvp
+            .update_note(ReservationId::new_v4(), "updated".to_string())
+            .await
+            .unwrap_err(),
+    );
+}
+
+/// a deleted reservation is gone, deleting it again is not found.
+pub async fn cancel<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let reserved = rsvp
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(rsvp.delete(id(&reserved)).await.unwrap(), reserved);
+    assert_not_found(&rsvp.get(id(&reserved)).await.unwrap_err());
+    assert_not_found(&rsvp.delete(id(&reserved)).await.unwrap_err());
+}
+
+/// a retry with the same key returns the first response, another request can't reuse the key.
+pub async fn idempotency<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let key = format!("reserve-{}", ids.tag);
+    let request = ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z");
+    let first = rsvp
+        .reserve_idempotent(key.clone(), request.clone())
+        .await
+        .unwrap();
+    let retried = rsvp
+        .reserve_idempotent(key.clone(), request.clone())
+        .await
+        .unwrap();
+    assert_eq!(first, retried);
+    let err = rsvp
+        .delete_idempotent(key.clone(), id(&first))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::IdempotencyKeyReused(k) if k == key));
+    let other = ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z");
+    let err = rsvp
+        .reserve_idempotent(key.clone(), other)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+
+    // failures aren't stored, an empty key is no key
+    let key = format!("confirm-{}", ids.tag);
+    let unknown = ReservationId::new_v4();
+    assert_not_found(
+        &rsvp
+            .change_status_idempotent(key.clone(), unknown)
+            .await
+            .unwrap_err(),
+    );
+    let confirmed = rsvp
+        .change_status_idempotent(key.clone(), id(&first))
+        .await
+        .unwrap();
+    assert_eq!(confirmed.status, ReservationStatus::Confirmed as i32);
+    assert_eq!(
+        rsvp.change_status_idempotent(key.clone(), id(&first))
+            .await
+            .unwrap(),
+        confirmed
+    );
+    let err = rsvp
+        .change_status_idempotent(key, unknown)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+    assert_conflict(
+        &rsvp
+            .reserve_idempotent(String::new(), request)
+            .await
+            .unwrap_err(),
+    );
+
+    let key = format!("cancel-{}", ids.tag);
+    let cancelled = rsvp
+        .delete_idempotent(key.clone(), id(&first))
+        .await
+        .unwrap();
+    assert_eq!(
+        rsvp.delete_idempotent(key, id(&first)).await.unwrap(),
+        cancelled
+    );
+}
+
+/// pages are sorted by the sort key with id breaking ties, page 0 is the first page.
+pub async fn query_pagination<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let mut reserved = Vec::new();
+    for day in 1..=5 {
+        let start = format!("2022-12-0{day}T04:00:00Z");
+        // the later the start, the earlier the end
+        let end = format!("2022-12-{}T06:00:00Z", 20 - day);
+        reserved.push(
+            rsvp.reserve(ids.reservation(day, &start, &end))
+                .await
+                .unwrap(),
+        );
+    }
+    let reserved_ids: Vec<_> = reserved.iter().map(|r| r.id.clone()).collect();
+
+    let mut query = ReservationQuery {
+        page_size: 2,
+        ..ids.query()
+    };
+    let mut pages = Vec::new();
+    for page in 0..=3 {
+        query.page = page;
+        pages.push(ids_of(rsvp.query(query.clone()).await.unwrap()));
+    }
+    assert_eq!(pages[0], reserved_ids[..2]);
+    assert_eq!(pages[1], reserved_ids[..2]);
+    assert_eq!(pages[2], reserved_ids[2..4]);
+    assert_eq!(pages[3], reserved_ids[4..]);
+
+    query.page = 1;
+    query.page_size = 0;
+    let all = ids_of(rsvp.query(query.clone()).await.unwrap());
+    assert_eq!(all, reserved_ids);
+
+    query.desc = true;
+    let desc = ids_of(rsvp.query(query.clone()).await.unwrap());
+    assert_eq!(desc, reserved_ids.iter().rev().cloned().collect::<Vec<_>>());
+
+    query.desc = false;
+    query.sort_by = ReservationSortKey::End as i32;
+    let by_end = ids_of(rsvp.query(query.clone()).await.unwrap());
+    assert_eq!(by_end, desc);
+
+    let mut sorted_ids = reserved_ids.clone();
+    sorted_ids.sort_by_key(|id| id.parse::<ReservationId>().unwrap());
+    query.sort_by = ReservationSortKey::Id as i32;
+    assert_eq!(ids_of(rsvp.query(query.clone()).await.unwrap()), sorted_ids);
+
+    // a cursor continues after the id, whatever the page
+    query.page = 3;
+    query.page_size = 2;
+    query.cursor = Some(sorted_ids[1].clone());
+    assert_eq!(
+        ids_of(rsvp.query(query.clone()).await.unwrap()),
+        sorted_ids[2..4]
+    );
+    query.desc = true;
+    let before = ids_of(rsvp.query(query.clone()).await.unwrap());
+    assert_eq!(before, sorted_ids[..1]);
+    query.desc = false;
+    query.sort_by = ReservationSortKey::Start as i32;
+    assert!(matches!(
+        rsvp.query(query.clone()).await,
+        Err(ReservationError::InvalidFilter(_))
+    ));
+    query.cursor = None;
+    query.page = 1;
+    query.page_size = 0;
+
+    // user, resource and status filters
+    rsvp.change_status(id(&reserved[2])).await.unwrap();
+    query.sort_by = ReservationSortKey::Start as i32;
+    query.status = ReservationStatus::Confirmed as i32;
+    let confirmed = ids_of(rsvp.query(query.clone()).await.unwrap());
+    assert_eq!(confirmed, reserved_ids[2..3]);
+    query.statuses = vec![ReservationStatus::Pending as i32];
+    assert_eq!(rsvp.query(query.clone()).await.unwrap().len(), 5);
+
+    let query = ReservationQuery {
+        resource_ids: vec![ids.resource(1), ids.resource(4)],
+        ..Default::default()
+    };
+    let by_resource = ids_of(rsvp.query(query).await.unwrap());
+    assert_eq!(
+        by_resource,
+        [reserved_ids[0].clone(), reserved_ids[3].clone()]
+    );
+    let query = ReservationQuery {
+        user_id: format!("other {}", ids.tag),
+        resource_id: ids.resource(1),
+        ..Default::default()
+    };
+    assert!(rsvp.query(query).await.unwrap().is_empty());
+}
+
+/// a time range contains, overlaps or has the start of reservations depending on the match mode.
+pub async fn query_match_modes<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let mut reserved = Vec::new();
+    for (start, end) in [
+        ("2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+        ("2022-11-18T08:00:00Z", "2022-11-18T10:00:00Z"),
+        ("2022-11-18T11:00:00Z", "2022-11-18T13:00:00Z"),
+    ] {
+        reserved.push(rsvp.reserve(ids.reservation(0, start, end)).await.unwrap());
+    }
+    let reserved_ids: Vec<_> = reserved.iter().map(|r| r.id.clone()).collect();
+
+    let query = |mode: ReservationMatchMode, start: Option<&str>, end: Option<&str>| {
+        let mut query = ids.query();
+        query.match_mode = mode as i32;
+        query.start = start.map(|s| to_timestamp(s.parse().unwrap()));
+        query.end = end.map(|s| to_timestamp(s.parse().unwrap()));
+        query
+    };
+    let cases = [
+        (
+            ReservationMatchMode::Contains,
+            Some("2022-11-18T04:00:00Z"),
+            Some("2022-11-18T10:00:00Z"),
+            0..2,
+        ),
+        (
+            ReservationMatchMode::Contains,
+            Some("2022-11-18T05:00:00Z"),
+            None,
+            1..3,
+        ),
+        (
+            ReservationMatchMode::Overlaps,
+            Some("2022-11-18T05:00:00Z"),
+            Some("2022-11-18T09:00:00Z"),
+            0..2,
+        ),
+        // touching isn't overlapping
+        (
+            ReservationMatchMode::Overlaps,
+            Some("2022-11-18T06:00:00Z"),
+            Some("2022-11-18T08:00:00Z"),
+            0..0,
+        ),
+        (
+            ReservationMatchMode::Overlaps,
+            None,
+            Some("2022-11-18T08:30:00Z"),
+            0..2,
+        ),
+        (
+            ReservationMatchMode::StartsWithin,
+            Some("2022-11-18T04:00:00Z"),
+            Some("2022-11-18T11:00:00Z"),
+            0..2,
+        ),
+        (
+            ReservationMatchMode::StartsWithin,
+            Some("2022-11-18T05:00:00Z"),
+            None,
+            1..3,
+        ),
+    ];
+    for (mode, start, end, expected) in cases {
+        let found = ids_of(rsvp.query(query(mode, start, end)).await.unwrap());
+        assert_eq!(
+            found, reserved_ids[expected],
+            "{mode:?} {start:?} - {end:?}"
+        );
+    }
+
+    let invalid = query(
+        ReservationMatchMode::Contains,
+        Some("2022-11-18T06:00:00Z"),
+        Some("2022-11-18T04:00:00Z"),
+    );
+    let err = rsvp.query(invalid).await.unwrap_err();
+    assert!(matches!(err, ReservationError::InvalidTimespan));
+}
+
+/// filter pages through the reservations by id with cursors, in both directions.
+pub async fn filter_cursors<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let mut reserved = Vec::new();
+    for day in 1..=5 {
+        let start = format!("2022-12-0{day}T04:00:00Z");
+        let end = format!("2022-12-0{day}T06:00:00Z");
+        reserved.push(
+            rsvp.reserve(ids.reservation(0, &start, &end))
+                .await
+                .unwrap(),
+        );
+    }
+    let confirmed = rsvp.change_status(id(&reserved[2])).await.unwrap();
+    let mut reserved_ids = ids_of(reserved);
+    reserved_ids.sort_by_key(|id| id.parse::<ReservationId>().unwrap());
+
+    let mut filter = ReservationFilter {
+        user_id: ids.user.clone(),
+        page_size: 2,
+        ..Default::default()
+    };
+    let mut pages = Vec::new();
+    let last_pager = loop {
+        let (pager, page) = rsvp.filter(filter.clone()).await.unwrap();
+        assert_eq!(pager.total, Some(5));
+        // the first page has no previous one
+        assert_eq!(pager.prev.is_some(), filter.cursor.is_some());
+        pages.push(ids_of(page));
+        match &pager.next {
+            Some(next) => filter.cursor = Some(next.clone()),
+            None => break pager,
+        }
+    };
+    assert_eq!(
+        pages,
+        [&reserved_ids[..2], &reserved_ids[2..4], &reserved_ids[4..]]
+    );
+
+    // page backward from the last page
+    filter.cursor = last_pager.prev;
+    filter.desc = true;
+    let (pager, page) = rsvp.filter(filter.clone()).await.unwrap();
+    assert_eq!(
+        ids_of(page),
+        [reserved_ids[3].clone(), reserved_ids[2].clone()]
+    );
+    assert_eq!(pager.next.as_ref(), Some(&reserved_ids[2]));
+
+    filter.cursor = None;
+    filter.page_size = 0;
+    let (pager, page) = rsvp.filter(filter.clone()).await.unwrap();
+    let desc: Vec<_> = reserved_ids.iter().rev().cloned().collect();
+    assert_eq!(ids_of(page), desc);
+    assert_eq!((pager.prev, pager.next), (None, None));
+
+    filter.status = ReservationStatus::Confirmed as i32;
+    let (pager, page) = rsvp.filter(filter.clone()).await.unwrap();
+    assert_eq!(page, [confirmed]);
+    assert_eq!(pager.total, Some(1));
+
+    filter.cursor = Some("not a uuid".to_string());
+    let err = rsvp.filter(filter).await.unwrap_err();
+    assert!(matches!(err, ReservationError::InvalidReservationId));
+}
+
+/// an empty filter is refused, a dry run deletes nothing.
+pub async fn cancel_by_filter<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    for day in 1..=3 {
+        let start = format!("2022-12-0{day}T04:00:00Z");
+        let end = format!("2022-12-0{day}T06:00:00Z");
+        rsvp.reserve(ids.reservation(day, &start, &end))
+            .await
+            .unwrap();
+    }
+
+    let err = rsvp
+        .cancel_by_filter(ReservationQuery::default(), true)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::InvalidFilter(_)));
+
+    let query = ReservationQuery {
+        resource_ids: vec![ids.resource(1), ids.resource(2)],
+        ..ids.query()
+    };
+    let cancelled = rsvp.cancel_by_filter(query.clone(), true).await.unwrap();
+    assert_eq!(cancelled.len(), 2);
+    assert_eq!(rsvp.query(ids.query()).await.unwrap().len(), 3);
+
+    let cancelled = rsvp.cancel_by_filter(query, false).await.unwrap();
+    assert_eq!(cancelled.len(), 2);
+    let left = rsvp.query(ids.query()).await.unwrap();
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].resource_id, ids.resource(3));
+}
+
+/// reserve, confirm and cancel are streamed in order, note updates aren't. A listener can resume
+/// after any change id and filter by op.
+pub async fn change_events<R: Rsvp + ?Sized>(rsvp: &R) {
+    let ids = Ids::new();
+    let filter = ListenFilter {
+        user_ids: vec![ids.user.clone()],
+        ..Default::default()
+    };
+    let mut live = rsvp.listen(filter.clone()).await.unwrap();
+
+    let reserved = rsvp
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    rsvp.change_status(id(&reserved)).await.unwrap();
+    rsvp.update_note(id(&reserved), "updated".to_string())
+        .await
+        .unwrap();
+    rsvp.delete(id(&reserved)).await.unwrap();
+    // changes of other users are filtered out
+    let other = Reservation {
+        user_id: format!("other {}", ids.tag),
+        ..ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z")
+    };
+    rsvp.reserve(other).await.unwrap();
+
+    let created = next(&mut live).await;
+    assert_eq!(created.op, ReservationUpdateType::Create);
+    assert_eq!(created.reservation.id, reserved.id);
+    let updated = next(&mut live).await;
+    assert_eq!(updated.op, ReservationUpdateType::Update);
+    assert!(updated.id > created.id);
+    let deleted = next(&mut live).await;
+    assert_eq!(deleted.op, ReservationUpdateType::Delete);
+    assert!(deleted.id > updated.id);
+    // only the snapshot kept by the change is left of a deleted reservation
+    assert_eq!(
+        deleted.reservation,
+        Reservation {
+            id: reserved.id.clone(),
+            user_id: reserved.user_id.clone(),
+            resource_id: reserved.resource_id.clone(),
+            status: ReservationStatus::Confirmed as i32,
+            ..Default::default()
+        }
+    );
+
+    let mut resumed = rsvp
+        .listen(ListenFilter {
+            since: Some(created.id),
+            ..filter.clone()
+        })
+        .await
+        .unwrap();
+    assert_eq!(next(&mut resumed).await, updated);
+    assert_eq!(next(&mut resumed).await, deleted);
+
+    let mut deletes = rsvp
+        .listen(ListenFilter {
+            since: Some(created.id - 1),
+            ops: vec![ReservationUpdateType::Delete],
+            ..filter
+        })
+        .await
+        .unwrap();
+    assert_eq!(next(&mut deletes).await, deleted);
+}
+
+/// `a` and `b` share a store but are scoped to different tenants: neither sees, changes or
+/// conflicts with the reservations of the other, idempotency keys and change events included.
+pub async fn tenant_isolation<R: Rsvp + ?Sized>(a: &R, b: &R) {
+    let ids = Ids::new();
+    let mut changes_of_b = b.listen(ListenFilter::default()).await.unwrap();
+    let of_a = a
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    // the same resource and period is free in the other tenant
+    let of_b = b
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert_ne!(of_a.tenant_id, of_b.tenant_id);
+    assert_eq!(a.get(id(&of_a)).await.unwrap(), of_a);
+
+    assert_not_found(&b.get(id(&of_a)).await.unwrap_err());
+    assert_not_found(&b.change_status(id(&of_a)).await.unwrap_err());
+    let err = b.update_note(id(&of_a), "mine".into()).await.unwrap_err();
+    assert_not_found(&err);
+    assert_not_found(&b.delete(id(&of_a)).await.unwrap_err());
+    assert_eq!(
+        ids_of(b.query(ids.query()).await.unwrap()),
+        vec![of_b.id.clone()]
+    );
+    let query = ReservationQuery {
+        resource_id: ids.resource(0),
+        ..Default::default()
+    };
+    let cancelled = b.cancel_by_filter(query, false).await.unwrap();
+    assert_eq!(ids_of(cancelled), vec![of_b.id.clone()]);
+    assert_eq!(a.get(id(&of_a)).await.unwrap(), of_a);
+
+    // the same key in each tenant
+    let key = format!("tenant {}", ids.tag);
+    let first = a
+        .reserve_idempotent(
+            key.clone(),
+            ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+        )
+        .await
+        .unwrap();
+    let second = b
+        .reserve_idempotent(
+            key,
+            ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+        )
+        .await
+        .unwrap();
+    assert_ne!(first.id, second.id);
+
+    // b only hears about its own reservations
+    let expected = [
+        (ReservationUpdateType::Create, &of_b.id),
+        (ReservationUpdateType::Delete, &of_b.id),
+        (ReservationUpdateType::Create, &second.id),
+    ];
+    for (op, rsvp_id) in expected {
+        let change = next(&mut changes_of_b).await;
+        assert_eq!((change.op, &change.reservation.id), (op, rsvp_id));
+        assert_eq!(change.reservation.tenant_id, of_b.tenant_id);
+    }
+}
+
+/// `as_actor` returns a handle of one store recording the given user id as the actor: a change
+/// records its actor, and whether the actor changed another user's reservation. a reservation
+/// keeps who booked it, and an idempotency key is only honored for the actor who claimed it.
+pub async fn actor_recording<R: Rsvp>(as_actor: impl Fn(&str) -> R) {
+    let ids = Ids::new();
+    let admin_id = format!("admin {}", ids.tag);
+    let owner = as_actor(&ids.user);
+    let admin = as_actor(&admin_id);
+    let anonymous = as_actor("");
+    let filter = ListenFilter {
+        user_ids: vec![ids.user.clone()],
+        ..Default::default()
+    };
+    let mut changes = owner.listen(filter).await.unwrap();
+
+    let own = owner
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(own.booked_by, ids.user);
+    let confirmed = admin.change_status(id(&own)).await.unwrap();
+    assert_eq!(confirmed.booked_by, ids.user);
+    admin.delete(id(&own)).await.unwrap();
+    let booked = anonymous
+        .reserve(ids.reservation(0, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(booked.booked_by, "");
+    let booked = admin
+        .reserve(ids.reservation(1, "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"))
+        .await
+        .unwrap();
+    assert_eq!(owner.get(id(&booked)).await.unwrap().booked_by, admin_id);
+
+    let expected = [
+        (ReservationUpdateType::Create, ids.user.as_str(), false),
+        (ReservationUpdateType::Update, admin_id.as_str(), true),
+        (ReservationUpdateType::Delete, admin_id.as_str(), true),
+        (ReservationUpdateType::Create, "", false),
+        (ReservationUpdateType::Create, admin_id.as_str(), true),
+    ];
+    for (op, actor, on_behalf_of) in expected {
+        let change = next(&mut changes).await;
+        assert_eq!(
+            (change.op, change.actor.as_str(), change.on_behalf_of),
+            (op, actor, on_behalf_of)
+        );
+    }
+
+    // a stored response is only replayed to the actor who claimed the key
+    let key = format!("actor {}", ids.tag);
+    owner
+        .change_status_idempotent(key.clone(), id(&booked))
+        .await
+        .unwrap();
+    let err = admin
+        .change_status_idempotent(key, id(&booked))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+}
+
+/// user and resource ids unique to a check
+struct Ids {
+    tag: String,
+    user: String,
+}
+
+impl Ids {
+    fn new() -> Self {
+        let tag = ReservationId::new_v4().simple().to_string();
+        Self {
+            user: format!("user {tag}"),
+            tag,
+        }
+    }
+
+    fn resource(&self, n: u32) -> String {
+        format!("room {n} {}", self.tag)
+    }
+
+    /// a pending reservation of the user on the n-th resource
+    fn reservation(&self, resource: u32, start: &str, end: &str) -> Reservation {
+        Reservation {
+            user_id: self.user.clone(),
+            status: ReservationStatus::Pending as i32,
+            resource_id: self.resource(resource),
+            start: Some(to_timestamp(start.parse().unwrap())),
+            end: Some(to_timestamp(end.parse().unwrap())),
+            note: "conformance".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// every reservation of the user
+    fn query(&self) -> ReservationQuery {
+        ReservationQuery {
+            user_id: self.user.clone(),
+            page_size: 100,
+            ..Default::default()
+        }
+    }
+}
+
+fn id(rsvp: &Reservation) -> ReservationId {
+    rsvp.id.parse().unwrap()
+}
+
+fn ids_of(rsvps: Vec<Reservation>) -> Vec<String> {
+    rsvps.into_iter().map(|r| r.id).collect()
+}
+
+async fn next(changes: &mut ReservationChangeStream) -> crate::ReservationChange {
+    tokio::time::timeout(CHANGE_TIMEOUT, changes.next())
+        .await
+        .expect("no change received in time")
+        .expect("the change stream ended")
+        .unwrap()
+}
+
+fn assert_conflict(err: &ReservationError) {
+    assert!(
+        matches!(err, ReservationError::Conflict(_)),
+        "should be a conflict: {err:?}"
+    );
+}
+
+fn assert_not_found(err: &ReservationError) {
+    assert!(
+        matches!(err, ReservationError::NotFound),
+        "should be not found: {err:?}"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryReservationManager, ReservationManager, SqliteReservationManager};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn postgres_should_conform() {
+        check_all(&ReservationManager::new(migrated_pool.clone())).await;
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn postgres_should_isolate_tenants() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        tenant_isolation(&manager, &manager.clone().with_tenant("acme")).await;
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn postgres_should_record_actors() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        actor_recording(|actor| manager.clone().with_actor(actor)).await;
+    }
+
+    #[tokio::test]
+    async fn memory_should_conform() {
+        check_all(&MemoryReservationManager::new()).await;
+    }
+
+    #[tokio::test]
+    async fn memory_should_isolate_tenants() {
+        let manager = MemoryReservationManager::new();
+        tenant_isolation(&manager, &manager.clone().with_tenant("acme")).await;
+    }
+
+    #[tokio::test]
+    async fn memory_should_record_actors() {
+        let manager = MemoryReservationManager::new();
+        actor_recording(|actor| manager.clone().with_actor(actor)).await;
+    }
+
+    async fn sqlite() -> SqliteReservationManager {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let manager = SqliteReservationManager::new(pool);
+        manager.migrate().await.unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn sqlite_should_conform() {
+        check_all(&sqlite().await).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_should_isolate_tenants() {
+        let manager = sqlite().await;
+        tenant_isolation(&manager, &manager.clone().with_tenant("acme")).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_should_record_actors() {
+        let manager = sqlite().await;
+        actor_recording(|actor| manager.clone().with_actor(actor)).await;
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{Delegations, ReservationError, ReservationManager};
+use abi::{to_timestamp, to_utc_time, Delegation, DelegationScope};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, types::Uuid, Row};
+
+/// grantor and grantee longer than this don't fit the columns
+const MAX_USER_ID_LEN: usize = 64;
+
+#[async_trait]
+impl Delegations for ReservationManager {
+    /// create a delegation of the manager's tenant, replacing the one of the same grantor and grantee.
+    async fn create_delegation(
+        &self,
+        delegation: Delegation,
+    ) -> Result<Delegation, ReservationError> {
+        let scope = validate(&delegation)?;
+        let expires_at = delegation.expires_at.as_ref().map(to_utc_time);
+        let mut tx = self.begin().await?;
+        let row = sqlx::query(
+            "INSERT INTO rsvp.delegations (tenant_id, grantor, grantee, scope, expires_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (tenant_id, grantor, grantee) DO UPDATE SET scope = EXCLUDED.scope, expires_at = EXCLUDED.expires_at RETURNING *",
+        )
+        .bind(&self.tenant)
+        .bind(&delegation.grantor)
+        .bind(&delegation.grantee)
+        .bind(scope.to_string())
+        .bind(expires_at)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        delegation_from_row(&row)
+    }
+
+    /// list the delegations granted by or to the user, expired ones included.
+    async fn list_delegations(&self, user_id: &str) -> Result<Vec<Delegation>, ReservationError> {
+        let mut tx = self.begin().await?;
+        let rows = sqlx::query(
+            "SELECT * FROM rsvp.delegations WHERE tenant_id = $1 AND (grantor = $2 OR grantee = $2) ORDER BY created_at, id",
+        )
+        .bind(&self.tenant)
+        .bind(user_id)
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        rows.iter().map(delegation_from_row).collect()
+    }
+
+    async fn get_delegation(&self, id: Uuid) -> Result<Delegation, ReservationError> {
+        let mut tx = self.begin().await?;
+        let row = sqlx::query("SELECT * FROM rsvp.delegations WHERE id = $1 AND tenant_id = $2")
+            .bind(id)
+            .bind(&self.tenant)
+            .fetch_one(&mut tx)
+            .await?;
+        tx.commit().await?;
+        delegation_from_row(&row)
+    }
+
+    async fn delete_delegation(&self, id: Uuid) -> Result<Delegation, ReservationError> {
+        let mut tx = self.begin().await?;
+        let row = sqlx::query(
+            "DELETE FROM rsvp.delegations WHERE id = $1 AND tenant_id = $2 RETURNING *",
+        )
+        .bind(id)
+        .bind(&self.tenant)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        delegation_from_row(&row)
+    }
+
+    async fn is_delegated(
+        &self,
+        grantor: &str,
+        grantee: &str,
+        scope: DelegationScope,
+    ) -> Result<bool, ReservationError> {
+        let mut tx = self.begin().await?;
+        let granted: Option<String> = sqlx::query_scalar(
+            "SELECT scope FROM rsvp.delegations WHERE tenant_id = $1 AND grantor = $2 AND grantee = $3 AND (expires_at IS NULL OR expires_at > now())",
+        )
+        .bind(&self.tenant)
+        .bind(grantor)
+        .bind(grantee)
+        .fetch_optional(&mut tx)
+        .await?;
+        tx.commit().await?;
+        Ok(granted
+            .and_then(|granted| granted.parse::<DelegationScope>().ok())
+            .is_some_and(|granted| granted.covers(scope)))
+    }
+}
+
+/// check the users and the expiry, return the scope to grant.
+fn validate(delegation: &Delegation) -> Result<DelegationScope, ReservationError> {
+    for (name, user_id) in [
+        ("grantor", &delegation.grantor),
+        ("grantee", &delegation.grantee),
+    ] {
+        if user_id.is_empty() || user_id.len() > MAX_USER_ID_LEN {
+            return Err(ReservationError::InvalidDelegation(format!(
+                "{name} should have 1 to {MAX_USER_ID_LEN} characters"
+            )));
+        }
+    }
+    if delegation.grantor == delegation.grantee {
+        return Err(ReservationError::InvalidDelegation(
+            "grantor and grantee should differ".to_string(),
+        ));
+    }
+    if delegation
+        .expires_at
+        .as_ref()
+        .is_some_and(|expires_at| to_utc_time(expires_at) <= Utc::now())
+    {
+        return Err(ReservationError::InvalidDelegation(
+            "expires_at should be in the future".to_string(),
+        ));
+    }
+    match DelegationScope::from_i32(delegation.scope) {
+        Some(scope @ (DelegationScope::Book | DelegationScope::Manage)) => Ok(scope),
+        _ => Err(ReservationError::InvalidDelegation(
+            "scope should be book or manage".to_string(),
+        )),
+    }
+}
+
+fn delegation_from_row(row: &PgRow) -> Result<Delegation, ReservationError> {
+    let scope: String = row.get("scope");
+    let scope = scope
+        .parse::<DelegationScope>()
+        .map_err(ReservationError::InvalidDelegation)?;
+    Ok(Delegation {
+        id: row.get::<Uuid, _>("id").to_string(),
+        grantor: row.get("grantor"),
+        grantee: row.get("grantee"),
+        scope: scope as i32,
+        expires_at: row
+            .get::<Option<DateTime<Utc>>, _>("expires_at")
+            .map(to_timestamp),
+        tenant_id: row.get("tenant_id"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn delegation(grantor: &str, grantee: &str, scope: DelegationScope) -> Delegation {
+        Delegation {
+            grantor: grantor.to_string(),
+            grantee: grantee.to_string(),
+            scope: scope as i32,
+            ..Default::default()
+        }
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn delegations_should_be_managed() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        for invalid in [
+            delegation("alice", "alice", DelegationScope::Book),
+            delegation("", "bob", DelegationScope::Book),
+            delegation("alice", "bob", DelegationScope::Unknown),
+        ] {
+            let err = manager.create_delegation(invalid).await.unwrap_err();
+            assert!(matches!(err, ReservationError::InvalidDelegation(_)));
+        }
+
+        let created = manager
+            .create_delegation(delegation("alice", "bob", DelegationScope::Book))
+            .await
+            .unwrap();
+        assert!(manager
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+        assert!(!manager
+            .is_delegated("alice", "bob", DelegationScope::Manage)
+            .await
+            .unwrap());
+        assert!(!manager
+            .is_delegated("bob", "alice", DelegationScope::Book)
+            .await
+            .unwrap());
+
+        // granting the pair again replaces the scope
+        let replaced = manager
+            .create_delegation(delegation("alice", "bob", DelegationScope::Manage))
+            .await
+            .unwrap();
+        assert_eq!(replaced.id, created.id);
+        assert!(manager
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+
+        manager
+            .create_delegation(delegation("carol", "alice", DelegationScope::Book))
+            .await
+            .unwrap();
+        assert_eq!(manager.list_delegations("alice").await.unwrap().len(), 2);
+        assert_eq!(manager.list_delegations("bob").await.unwrap().len(), 1);
+
+        // other tenants see none of them
+        let other = ReservationManager::new(migrated_pool.clone()).with_tenant("acme");
+        assert!(other.list_delegations("alice").await.unwrap().is_empty());
+        assert!(!other
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+
+        let id = created.id.parse().unwrap();
+        assert_eq!(manager.get_delegation(id).await.unwrap().grantee, "bob");
+        manager.delete_delegation(id).await.unwrap();
+        assert!(manager.get_delegation(id).await.unwrap_err().is_not_found());
+        assert!(!manager
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn expired_delegations_should_not_count() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let mut expired = delegation("alice", "bob", DelegationScope::Book);
+        expired.expires_at = Some(to_timestamp(Utc::now() - Duration::hours(1)));
+        assert!(manager.create_delegation(expired.clone()).await.is_err());
+
+        expired.expires_at = Some(to_timestamp(Utc::now() + Duration::hours(1)));
+        let created = manager.create_delegation(expired).await.unwrap();
+        assert!(created.expires_at.is_some());
+        assert!(manager
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+
+        sqlx::query("UPDATE rsvp.delegations SET expires_at = now() - INTERVAL '1 minute'")
+            .execute(&migrated_pool)
+            .await
+            .unwrap();
+        assert!(!manager
+            .is_delegated("alice", "bob", DelegationScope::Book)
+            .await
+            .unwrap());
+        assert_eq!(manager.list_delegations("bob").await.unwrap().len(), 1);
+    }
+}
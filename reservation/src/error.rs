@@ -1,9 +1,12 @@
+use sqlx::postgres::PgDatabaseError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ReservationError {
     #[error("DB error: {0}")]
-    DBError(#[from] sqlx::Error),
+    DBError(sqlx::Error),
+    #[error("migration error: {0}")]
+    MigrateError(#[from] sqlx::migrate::MigrateError),
     #[error("invalid reservation id")]
     InvalidReservationId,
     #[error("invalid timespan")]
@@ -14,6 +17,58 @@ pub enum ReservationError {
     InvalidStatus,
     #[error("invalid resource id: {0}")]
     InvalidResourceId(String),
+    #[error("invalid filter: {0}")]
+    InvalidFilter(String),
+    #[error("invalid tenant id: {0}")]
+    InvalidTenantId(String),
+    #[error("reservation not found")]
+    NotFound,
+    #[error("conflicting reservation: {0}")]
+    Conflict(String),
+    #[error("idempotency key {0} was used by another operation")]
+    IdempotencyKeyReused(String),
+    #[error("invalid webhook: {0}")]
+    InvalidWebhook(String),
+    #[error("invalid delegation: {0}")]
+    InvalidDelegation(String),
+    #[error("format error: {0}")]
+    FormatError(String),
+    #[error("unauthenticated: {0}")]
+    Unauthenticated(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("sink error: {0}")]
+    SinkError(String),
     #[error("unknown error")]
     Unknown,
 }
+
+impl ReservationError {
+    /// whether the reservation conflicts with an existing one on the same resource.
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, ReservationError::Conflict(_))
+    }
+
+    /// whether the reservation doesn't exist, or isn't in the state the operation requires.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, ReservationError::NotFound)
+    }
+}
+
+/// errors with the same meaning in every backend are told apart from other database errors.
+impl From<sqlx::Error> for ReservationError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => ReservationError::NotFound,
+            sqlx::Error::Database(e) if e.constraint() == Some("reservations_conflict") => {
+                // e.g. Key (resource_id, timespan)=(...) conflicts with existing key (...).
+                let detail = e
+                    .try_downcast_ref::<PgDatabaseError>()
+                    .and_then(|e| e.detail())
+                    .unwrap_or_else(|| e.message());
+                ReservationError::Conflict(detail.to_string())
+            }
+            e => ReservationError::DBError(e),
+        }
+    }
+}
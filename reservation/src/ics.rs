@@ -0,0 +1,835 @@
+//! export reservations as an RFC 5545 iCalendar document, and import them from one.
+
+use crate::{import::ImportReport, ReservationError, Rsvp};
+use abi::{
+    to_timestamp, to_utc_time, Reservation, ReservationQuery, ReservationSortKey, ReservationStatus,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use std::collections::HashSet;
+
+/// reservations fetched per query while exporting
+const EXPORT_PAGE_SIZE: i64 = 100;
+/// lines longer than this (in octets) are folded
+const MAX_LINE_LEN: usize = 75;
+/// a recurring event is expanded to at most this many occurrences
+const MAX_OCCURRENCES: usize = 1000;
+/// seconds of a day and a week
+const DAY: i64 = 24 * 3600;
+const WEEK: i64 = 7 * DAY;
+
+/// render every reservation matching the query as a calendar named `name`.
+///
+/// page, page_size and sort of the query are ignored, the whole result is exported by start time.
+/// It is read in pages by id, so concurrent changes don't repeat or skip any reservation.
+pub async fn export_ics<R: Rsvp + ?Sized>(
+    rsvp: &R,
+    name: &str,
+    mut query: ReservationQuery,
+) -> Result<String, ReservationError> {
+    query.sort_by = ReservationSortKey::Id as i32;
+    query.desc = false;
+    query.page_size = EXPORT_PAGE_SIZE;
+    let mut rsvps: Vec<Reservation> = Vec::new();
+    loop {
+        query.cursor = rsvps.last().map(|r| r.id.clone());
+        let found = rsvp.query(query.clone()).await?;
+        let done = (found.len() as i64) < EXPORT_PAGE_SIZE;
+        rsvps.extend(found);
+        if done {
+            break;
+        }
+    }
+    rsvps.sort_by_key(|r| r.start.clone().map(|t| to_utc_time(&t)));
+    Ok(render_ics(name, &rsvps, Utc::now()))
+}
+
+/// render the reservations as a calendar named `name`, one VEVENT per reservation.
+///
+/// `stamp` is the DTSTAMP of the events, i.e. when the calendar was generated.
+pub fn render_ics(name: &str, rsvps: &[Reservation], stamp: DateTime<Utc>) -> String {
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//reservation//reservation service//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", escape(name)));
+    let stamp = format_time(stamp);
+    for rsvp in rsvps {
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@reservation", rsvp.id));
+        push_line(&mut ics, &format!("DTSTAMP:{stamp}"));
+        if let Some(start) = &rsvp.start {
+            push_line(
+                &mut ics,
+                &format!("DTSTART:{}", format_time(to_utc_time(start))),
+            );
+        }
+        if let Some(end) = &rsvp.end {
+            push_line(
+                &mut ics,
+                &format!("DTEND:{}", format_time(to_utc_time(end))),
+            );
+        }
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&rsvp.resource_id)));
+        if !rsvp.note.is_empty() {
+            push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&rsvp.note)));
+        }
+        push_line(&mut ics, &format!("CONTACT:{}", escape(&rsvp.user_id)));
+        push_line(&mut ics, &format!("STATUS:{}", event_status(rsvp.status())));
+        push_line(&mut ics, "END:VEVENT");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// map a reservation status to the VEVENT status
+fn event_status(status: ReservationStatus) -> &'static str {
+    match status {
+        ReservationStatus::Confirmed => "CONFIRMED",
+        ReservationStatus::Blocked => "CANCELLED",
+        ReservationStatus::Pending | ReservationStatus::Unknown => "TENTATIVE",
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// escape a TEXT value
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// append a content line, folded at 75 octets without splitting a character
+fn push_line(ics: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE_LEN {
+            ics.push_str("\r\n ");
+            // the leading space counts
+            len = 1;
+        }
+        ics.push(c);
+        len += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// how imported events are turned into reservations
+#[derive(Debug, Clone, Default)]
+pub struct IcsImportOptions {
+    /// reserve every event for this user instead of its CONTACT
+    pub user_id: Option<String>,
+    /// reserve every event on this resource instead of its SUMMARY
+    pub resource_id: Option<String>,
+    /// only report what would be reserved
+    pub dry_run: bool,
+}
+
+/// reserve every event of the calendar, recurring events are expanded to one reservation per
+/// occurrence.
+///
+/// Events are read the way `export_ics` writes them: SUMMARY is the resource, CONTACT the user,
+/// DESCRIPTION the note, and STATUS CONFIRMED makes a confirmed reservation. Cancelled events
+/// are skipped. An event that can't be parsed or reserved is recorded in the report and the
+/// import goes on.
+pub async fn import_ics<R: Rsvp + ?Sized>(
+    rsvp: &R,
+    ics: &str,
+    options: &IcsImportOptions,
+) -> Result<ImportReport, ReservationError> {
+    let mut report = ImportReport::default();
+    let mut items = Vec::new();
+    let events = parse_events(ics);
+    // occurrences moved or cancelled by another event with the same UID and a RECURRENCE-ID
+    let overridden: HashSet<(String, DateTime<Utc>)> = events
+        .iter()
+        .filter_map(|e| e.as_ref().ok())
+        .filter_map(|e| Some((e.uid.clone(), e.recurrence_id?)))
+        .collect();
+    for event in events {
+        let event = match event {
+            Ok(event) => event,
+            Err((uid, e)) => {
+                report.fail(uid, e);
+                continue;
+            }
+        };
+        let starts = match event.occurrences() {
+            Ok(starts) => starts,
+            Err(e) => {
+                report.fail(&event.uid, e);
+                continue;
+            }
+        };
+        for start in starts {
+            if event.recurrence_id.is_none() && overridden.contains(&(event.uid.clone(), start)) {
+                continue;
+            }
+            if event.cancelled {
+                report.skipped += 1;
+                continue;
+            }
+            let source = if event.rrule.is_some() {
+                format!("{}@{}", event.uid, format_time(start))
+            } else {
+                event.uid.clone()
+            };
+            match event.to_reservation(start, options) {
+                Ok(rsvp) => items.push((source, rsvp)),
+                Err(e) => report.fail(source, e),
+            }
+        }
+    }
+    report.reserve(rsvp, items, options.dry_run).await?;
+    Ok(report)
+}
+
+/// an event of an imported calendar
+#[derive(Debug, Default)]
+struct Event {
+    uid: String,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    duration: Option<Duration>,
+    all_day: bool,
+    summary: String,
+    contact: String,
+    description: String,
+    confirmed: bool,
+    cancelled: bool,
+    rrule: Option<String>,
+    exdates: Vec<DateTime<Utc>>,
+    recurrence_id: Option<DateTime<Utc>>,
+}
+
+impl Event {
+    /// start of every occurrence, only the start itself if it's not recurring
+    fn occurrences(&self) -> Result<Vec<DateTime<Utc>>, String> {
+        let start = self.start.ok_or("missing DTSTART")?;
+        let starts = match &self.rrule {
+            Some(rule) => expand_rrule(start, rule)?,
+            None => vec![start],
+        };
+        Ok(starts
+            .into_iter()
+            .filter(|start| !self.exdates.contains(start))
+            .collect())
+    }
+
+    fn to_reservation(
+        &self,
+        start: DateTime<Utc>,
+        options: &IcsImportOptions,
+    ) -> Result<Reservation, String> {
+        let duration = match (self.start, self.end, self.duration) {
+            (Some(first), Some(end), _) => end - first,
+            (_, None, Some(duration)) => duration,
+            // an all day event without end lasts one day, other events without end are instants
+            (_, None, None) if self.all_day => Duration::days(1),
+            _ => return Err("missing DTEND or DURATION".to_string()),
+        };
+        let user_id = options.user_id.as_ref().unwrap_or(&self.contact);
+        let resource_id = options.resource_id.as_ref().unwrap_or(&self.summary);
+        if user_id.is_empty() {
+            return Err("missing user id, no CONTACT".to_string());
+        }
+        if resource_id.is_empty() {
+            return Err("missing resource id, no SUMMARY".to_string());
+        }
+        let status = if self.confirmed {
+            ReservationStatus::Confirmed
+        } else {
+            ReservationStatus::Pending
+        };
+        let end = start
+            .checked_add_signed(duration)
+            .ok_or("end out of range")?;
+        Ok(Reservation {
+            user_id: user_id.clone(),
+            status: status as i32,
+            resource_id: resource_id.clone(),
+            start: Some(to_timestamp(start)),
+            end: Some(to_timestamp(end)),
+            note: self.description.clone(),
+            ..Default::default()
+        })
+    }
+}
+
+/// a content line, e.g. `DTSTART;TZID=UTC:20221118T040000`
+struct Property<'a> {
+    name: String,
+    params: Vec<(String, &'a str)>,
+    value: &'a str,
+}
+
+impl<'a> Property<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        // the value starts after the first colon outside a quoted parameter
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        })?;
+        let mut parts = line[..colon].split(';');
+        let name = parts.next()?.to_ascii_uppercase();
+        let params = parts
+            .filter_map(|p| p.split_once('='))
+            .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"')))
+            .collect();
+        Some(Self {
+            name,
+            params,
+            value: &line[colon + 1..],
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k == name).map(|(_, v)| *v)
+    }
+
+    /// parse a DATE or DATE-TIME value, return whether it's a date
+    fn time(&self, value: &str) -> Result<(DateTime<Utc>, bool), String> {
+        if let Some(tz) = self.param("TZID") {
+            if !matches!(tz, "UTC" | "Etc/UTC" | "GMT") {
+                return Err(format!("unsupported time zone {tz} in {}", self.name));
+            }
+        }
+        parse_time(value).ok_or_else(|| format!("invalid {}: {value}", self.name))
+    }
+}
+
+/// unfold the lines, then parse every VEVENT, an unparsable event is returned as its UID and
+/// the error
+fn parse_events(ics: &str) -> Vec<Result<Event, (String, String)>> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<(Event, Option<String>)> = None;
+    // components nested in an event, e.g. VALARM, are ignored
+    let mut depth = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let Some(prop) = Property::parse(line) else {
+            continue;
+        };
+        match (prop.name.as_str(), prop.value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") if current.is_none() => {
+                current = Some((Event::default(), None));
+                depth = 0;
+                continue;
+            }
+            ("END", "VEVENT") if depth == 0 => {
+                if let Some((mut event, error)) = current.take() {
+                    if event.uid.is_empty() {
+                        event.uid = format!("line {}", i + 1);
+                    }
+                    events.push(match error {
+                        Some(e) => Err((event.uid, e)),
+                        None => Ok(event),
+                    });
+                }
+                continue;
+            }
+            ("BEGIN", _) if current.is_some() => depth += 1,
+            ("END", _) if current.is_some() => depth -= 1,
+            _ => {}
+        }
+        if let Some((event, error)) = current.as_mut() {
+            if depth == 0 && error.is_none() {
+                *error = set_property(event, &prop).err();
+            }
+        }
+    }
+    events
+}
+
+fn set_property(event: &mut Event, prop: &Property) -> Result<(), String> {
+    match prop.name.as_str() {
+        "UID" => event.uid = prop.value.to_string(),
+        "DTSTART" => {
+            let (start, all_day) = prop.time(prop.value)?;
+            event.start = Some(start);
+            event.all_day = all_day;
+        }
+        "DTEND" => event.end = Some(prop.time(prop.value)?.0),
+        "DURATION" => {
+            let duration = parse_duration(prop.value)
+                .ok_or_else(|| format!("invalid DURATION: {}", prop.value))?;
+            event.duration = Some(duration);
+        }
+        "SUMMARY" => event.summary = unescape(prop.value),
+        "CONTACT" => event.contact = unescape(prop.value),
+        "DESCRIPTION" => event.description = unescape(prop.value),
+        "STATUS" => {
+            let status = prop.value.to_ascii_uppercase();
+            event.confirmed = status == "CONFIRMED";
+            event.cancelled = status == "CANCELLED";
+        }
+        "RRULE" => event.rrule = Some(prop.value.to_string()),
+        "EXDATE" => {
+            for value in prop.value.split(',') {
+                event.exdates.push(prop.time(value)?.0);
+            }
+        }
+        "RECURRENCE-ID" => event.recurrence_id = Some(prop.time(prop.value)?.0),
+        "RDATE" => return Err("RDATE is not supported".to_string()),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// parse a DATE or DATE-TIME in UTC, floating times are taken as UTC
+fn parse_time(value: &str) -> Option<(DateTime<Utc>, bool)> {
+    if value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?), true));
+    }
+    let value = value.strip_suffix('Z').unwrap_or(value);
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((Utc.from_utc_datetime(&time), false))
+}
+
+/// parse a positive duration, e.g. `P1D`, `PT1H30M` or `P2W`
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut time = false;
+    for c in value.chars() {
+        match c {
+            'T' => time = true,
+            '0'..='9' => number.push(c),
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                let secs = match (unit, time) {
+                    ('W', false) => WEEK,
+                    ('D', false) => DAY,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                duration = duration.checked_add(&checked_seconds(n, secs)?)?;
+            }
+        }
+    }
+    number.is_empty().then_some(duration)
+}
+
+/// `n` times `secs` seconds, none if it overflows a Duration
+fn checked_seconds(n: i64, secs: i64) -> Option<Duration> {
+    let secs = n.checked_mul(secs)?;
+    (secs.checked_abs()? <= Duration::max_value().num_seconds()).then(|| Duration::seconds(secs))
+}
+
+/// unescape a TEXT value
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// expand the RRULE of an event starting at `start`, the start itself is the first occurrence.
+///
+/// FREQ, INTERVAL, COUNT, UNTIL and BYDAY (weekly only) are supported, a rule without COUNT
+/// and UNTIL is expanded to at most `MAX_OCCURRENCES` occurrences.
+fn expand_rrule(start: DateTime<Utc>, rule: &str) -> Result<Vec<DateTime<Utc>>, String> {
+    let mut freq = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut until = None;
+    let mut by_day = Vec::new();
+    for part in rule.split(';').filter(|p| !p.is_empty()) {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("invalid RRULE part: {part}"))?;
+        let invalid = || format!("invalid RRULE {key}: {value}");
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => freq = Some(value.to_ascii_uppercase()),
+            "INTERVAL" => interval = value.parse().ok().filter(|i| *i > 0).ok_or_else(invalid)?,
+            "COUNT" => count = Some(value.parse::<usize>().map_err(|_| invalid())?),
+            "UNTIL" => {
+                let (time, is_date) = parse_time(value).ok_or_else(invalid)?;
+                // a date includes the whole day
+                until = Some(if is_date {
+                    time + Duration::days(1) - Duration::seconds(1)
+                } else {
+                    time
+                });
+            }
+            "BYDAY" => {
+                for day in value.split(',') {
+                    by_day.push(parse_weekday(day).ok_or_else(invalid)?);
+                }
+            }
+            "WKST" => {}
+            _ => return Err(format!("unsupported RRULE part: {part}")),
+        }
+    }
+    let freq = freq.ok_or("missing RRULE FREQ")?;
+    if !by_day.is_empty() && freq != "WEEKLY" {
+        return Err("BYDAY is only supported for WEEKLY rules".to_string());
+    }
+    by_day.sort_by_key(|d: &Weekday| d.num_days_from_monday());
+    by_day.dedup();
+
+    let limit = count.unwrap_or(MAX_OCCURRENCES).min(MAX_OCCURRENCES);
+    let overflow = || format!("RRULE {rule} goes out of range");
+    let after = |time: DateTime<Utc>, n: i64, secs: i64| {
+        checked_seconds(n, secs)
+            .and_then(|d| time.checked_add_signed(d))
+            .ok_or_else(overflow)
+    };
+    let mut starts = Vec::new();
+    // a period may have no valid occurrence, e.g. the 31st of a short month
+    for period in 0..(MAX_OCCURRENCES as i64 * 12) {
+        if starts.len() >= limit {
+            break;
+        }
+        let n = period.checked_mul(interval).ok_or_else(overflow)?;
+        let candidates = match freq.as_str() {
+            "DAILY" => vec![Some(after(start, n, DAY)?)],
+            "WEEKLY" if by_day.is_empty() => vec![Some(after(start, n, WEEK)?)],
+            "WEEKLY" => {
+                let weekday = start.weekday().num_days_from_monday() as i64;
+                let monday = after(after(start, -weekday, DAY)?, n, WEEK)?;
+                by_day
+                    .iter()
+                    .map(|d| after(monday, d.num_days_from_monday() as i64, DAY).map(Some))
+                    .collect::<Result<_, _>>()?
+            }
+            "MONTHLY" => vec![add_months(start, n).ok_or_else(overflow)?],
+            "YEARLY" => {
+                let months = n.checked_mul(12).ok_or_else(overflow)?;
+                vec![add_months(start, months).ok_or_else(overflow)?]
+            }
+            _ => return Err(format!("unsupported RRULE FREQ: {freq}")),
+        };
+        for candidate in candidates.into_iter().flatten() {
+            if candidate < start {
+                continue;
+            }
+            if until.is_some_and(|until| candidate > until) || starts.len() >= limit {
+                return Ok(starts);
+            }
+            starts.push(candidate);
+        }
+    }
+    Ok(starts)
+}
+
+/// the same day and time `months` later, `Some(None)` if the month has no such day and none if
+/// the month is out of range
+fn add_months(time: DateTime<Utc>, months: i64) -> Option<Option<DateTime<Utc>>> {
+    let total = (time.year() as i64 * 12 + time.month0() as i64).checked_add(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    NaiveDate::from_ymd_opt(year, month, 1)?;
+    let date = NaiveDate::from_ymd_opt(year, month, time.day());
+    Some(date.map(|date| Utc.from_utc_datetime(&date.and_time(time.time()))))
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReservationManager;
+    use abi::ReservationQueryBuilder;
+
+    fn reservation(id: &str, status: ReservationStatus, note: &str) -> Reservation {
+        Reservation {
+            id: id.to_string(),
+            user_id: "M4n5ter".to_string(),
+            status: status as i32,
+            resource_id: "class room 1".to_string(),
+            start: Some(to_timestamp("2022-11-18T04:00:00Z".parse().unwrap())),
+            end: Some(to_timestamp("2022-11-18T06:00:00Z".parse().unwrap())),
+            note: note.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_should_follow_rfc5545() {
+        let rsvps = [
+            reservation("a", ReservationStatus::Pending, ""),
+            reservation(
+                "b",
+                ReservationStatus::Confirmed,
+                "bring; a laptop,\nplease",
+            ),
+            reservation("c", ReservationStatus::Blocked, &"很长的备注".repeat(10)),
+        ];
+        let ics = render_ics("M4n5ter", &rsvps, "2022-11-01T00:00:00Z".parse().unwrap());
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert!(ics.contains("UID:a@reservation\r\nDTSTAMP:20221101T000000Z\r\n"));
+        assert!(ics.contains("DTSTART:20221118T040000Z\r\nDTEND:20221118T060000Z\r\n"));
+        assert!(ics.contains("CONTACT:M4n5ter\r\n"));
+        assert!(ics.contains("DESCRIPTION:bring\\; a laptop\\,\\nplease\r\n"));
+
+        let statuses: Vec<_> = ics.lines().filter(|l| l.starts_with("STATUS:")).collect();
+        assert_eq!(
+            statuses,
+            vec!["STATUS:TENTATIVE", "STATUS:CONFIRMED", "STATUS:CANCELLED"]
+        );
+
+        // every line fits, folded lines continue with a space
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= MAX_LINE_LEN);
+        }
+        let unfolded = ics.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("DESCRIPTION:{}\r\n", "很长的备注".repeat(10))));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn export_should_include_every_page() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        for day in 1..=(EXPORT_PAGE_SIZE + 5) {
+            let start: DateTime<Utc> = "2022-01-01T04:00:00Z".parse().unwrap();
+            let start = start + chrono::Duration::days(day);
+            manager
+                .reserve(Reservation {
+                    user_id: "M4n5ter".to_string(),
+                    status: ReservationStatus::Pending as i32,
+                    resource_id: "class room 1".to_string(),
+                    start: Some(to_timestamp(start)),
+                    end: Some(to_timestamp(start + chrono::Duration::hours(1))),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let query = ReservationQueryBuilder::default()
+            .user_id("M4n5ter")
+            .build()
+            .unwrap();
+        let ics = export_ics(&manager, "M4n5ter", query).await.unwrap();
+        assert_eq!(
+            ics.matches("BEGIN:VEVENT").count() as i64,
+            EXPORT_PAGE_SIZE + 5
+        );
+        assert!(ics.find("DTSTART:20220102").unwrap() < ics.find("DTSTART:20220103").unwrap());
+    }
+
+    fn times(times: &[&str]) -> Vec<DateTime<Utc>> {
+        times.iter().map(|t| t.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn rrule_should_expand() {
+        let start = "2022-01-31T04:00:00Z".parse().unwrap();
+        let starts = expand_rrule(start, "FREQ=DAILY;INTERVAL=2;COUNT=3").unwrap();
+        let expected = [
+            "2022-01-31T04:00:00Z",
+            "2022-02-02T04:00:00Z",
+            "2022-02-04T04:00:00Z",
+        ];
+        assert_eq!(starts, times(&expected));
+
+        // months without the 31st are skipped
+        let starts = expand_rrule(start, "FREQ=MONTHLY;UNTIL=20220531").unwrap();
+        let expected = [
+            "2022-01-31T04:00:00Z",
+            "2022-03-31T04:00:00Z",
+            "2022-05-31T04:00:00Z",
+        ];
+        assert_eq!(starts, times(&expected));
+
+        // 2022-01-31 is a monday
+        let starts = expand_rrule(start, "FREQ=WEEKLY;BYDAY=FR,MO;COUNT=4").unwrap();
+        let expected = [
+            "2022-01-31T04:00:00Z",
+            "2022-02-04T04:00:00Z",
+            "2022-02-07T04:00:00Z",
+            "2022-02-11T04:00:00Z",
+        ];
+        assert_eq!(starts, times(&expected));
+
+        assert_eq!(
+            expand_rrule(start, "FREQ=DAILY").unwrap().len(),
+            MAX_OCCURRENCES
+        );
+        assert!(expand_rrule(start, "FREQ=MONTHLY;BYDAY=MO").is_err());
+        assert!(expand_rrule(start, "FREQ=HOURLY").is_err());
+
+        // rules going past the supported dates fail instead of panicking
+        for rule in [
+            "FREQ=DAILY;INTERVAL=100000",
+            "FREQ=WEEKLY;BYDAY=MO;INTERVAL=100000",
+            "FREQ=MONTHLY;INTERVAL=9223372036854775807",
+            "FREQ=YEARLY;INTERVAL=1000000000000000000",
+        ] {
+            assert!(expand_rrule(start, rule).is_err(), "{rule}");
+        }
+        // unless they stop before
+        let starts = expand_rrule(start, "FREQ=DAILY;INTERVAL=100000;COUNT=2").unwrap();
+        assert_eq!(starts.len(), 2);
+    }
+
+    #[test]
+    fn values_should_parse() {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Some(Duration::days(9)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("-P1D"), None);
+        assert_eq!(parse_duration("P99999999999999W"), None);
+        assert_eq!(parse_duration("P9223372036854775807D"), None);
+        assert_eq!(unescape(r"a\, b\; c\nd\\"), "a, b; c\nd\\");
+        let (time, is_date) = parse_time("20221118").unwrap();
+        assert!(is_date);
+        assert_eq!(
+            time,
+            "2022-11-18T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(parse_time("2022-11-18").is_none());
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn import_should_report_conflicts_and_failures() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let ics = [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            "BEGIN:VEVENT",
+            "UID:weekly",
+            "DTSTART:20221107T020000Z",
+            "DTEND:20221107T040000Z",
+            "RRULE:FREQ=WEEKLY;COUNT=4",
+            "EXDATE:20221114T020000Z",
+            "SUMMARY:room 1",
+            "CONTACT:M4n5ter",
+            "DESCRIPTION:weekly meeting\\, bring",
+            "  a laptop",
+            "STATUS:CONFIRMED",
+            "BEGIN:VALARM",
+            "TRIGGER:-PT15M",
+            "DESCRIPTION:reminder",
+            "END:VALARM",
+            "END:VEVENT",
+            // moves the third occurrence an hour later
+            "BEGIN:VEVENT",
+            "UID:weekly",
+            "RECURRENCE-ID:20221121T020000Z",
+            "DTSTART:20221121T030000Z",
+            "DURATION:PT2H",
+            "SUMMARY:room 1",
+            "CONTACT:M4n5ter",
+            "END:VEVENT",
+            // conflicts with the last occurrence
+            "BEGIN:VEVENT",
+            "UID:conflict",
+            "DTSTART:20221128T030000Z",
+            "DTEND:20221128T050000Z",
+            "SUMMARY:room 1",
+            "CONTACT:Syuu",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:cancelled",
+            "DTSTART:20221201T030000Z",
+            "DTEND:20221201T050000Z",
+            "SUMMARY:room 1",
+            "CONTACT:Syuu",
+            "STATUS:CANCELLED",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:zoned",
+            "DTSTART;TZID=Asia/Shanghai:20221201T030000",
+            "DTEND;TZID=Asia/Shanghai:20221201T050000",
+            "SUMMARY:room 1",
+            "CONTACT:Syuu",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:nobody",
+            "DTSTART;VALUE=DATE:20221202",
+            "SUMMARY:room 2",
+            "END:VEVENT",
+            "END:VCALENDAR",
+        ]
+        .join("\r\n");
+
+        let report = import_ics(&manager, &ics, &IcsImportOptions::default())
+            .await
+            .unwrap();
+        let starts: Vec<_> = report
+            .reserved
+            .iter()
+            .map(|r| to_utc_time(r.start.as_ref().unwrap()))
+            .collect();
+        let expected = [
+            "2022-11-07T02:00:00Z",
+            "2022-11-28T02:00:00Z",
+            "2022-11-21T03:00:00Z",
+        ];
+        assert_eq!(starts, times(&expected));
+        let first = &report.reserved[0];
+        assert_eq!(first.status, ReservationStatus::Confirmed as i32);
+        assert_eq!(first.note, "weekly meeting, bring a laptop");
+        assert_eq!(report.reserved[2].status, ReservationStatus::Pending as i32);
+
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].source, "conflict");
+        assert_eq!(report.skipped, 1);
+        let failures: Vec<_> = report.failures.iter().map(|f| f.source.as_str()).collect();
+        assert_eq!(failures, vec!["zoned", "nobody"]);
+        assert!(report.failures[0].error.contains("time zone"));
+
+        // the user can be given for events without one
+        let options = IcsImportOptions {
+            user_id: Some("Syuu".to_string()),
+            ..Default::default()
+        };
+        let report = import_ics(&manager, &ics, &options).await.unwrap();
+        assert_eq!(report.conflicts.len(), 4);
+        assert_eq!(report.reserved.len(), 1);
+        assert_eq!(report.reserved[0].user_id, "Syuu");
+    }
+}
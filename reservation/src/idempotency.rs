@@ -0,0 +1,131 @@
+use crate::manager::{confirm_reservation, delete_reservation, insert_reservation};
+use crate::{ReservationError, ReservationId, ReservationManager};
+use chrono::Utc;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+
+/// a mutation that can be guarded by an idempotency key
+pub(crate) enum Mutation {
+    Reserve(abi::Reservation),
+    Confirm(ReservationId),
+    Cancel(ReservationId),
+}
+
+impl Mutation {
+    pub(crate) fn op(&self) -> &'static str {
+        match self {
+            Mutation::Reserve(_) => "reserve",
+            Mutation::Confirm(_) => "confirm",
+            Mutation::Cancel(_) => "cancel",
+        }
+    }
+
+    /// hex sha256 of the op, the actor and the request. a retry with the same key should have
+    /// the same fingerprint, so the stored response is only replayed to the same request of the
+    /// same caller.
+    pub(crate) fn fingerprint(&self, actor: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [self.op().as_bytes(), actor.as_bytes()] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        match self {
+            Mutation::Reserve(rsvp) => hasher.update(rsvp.encode_to_vec()),
+            Mutation::Confirm(id) | Mutation::Cancel(id) => hasher.update(id.as_bytes()),
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+impl ReservationManager {
+    /// run the mutation at most once per idempotency key.
+    ///
+    /// The key is claimed and the mutation is executed in the same transaction, so a concurrent
+    /// retry waits for the first attempt and then returns its response. A failed mutation rolls
+    /// the claim back, thus only successful responses are stored. A retry of another request or
+    /// by another actor is rejected as reusing the key. An empty key disables it.
+    pub(crate) async fn idempotent(
+        &self,
+        key: String,
+        mutation: Mutation,
+    ) -> Result<abi::Reservation, ReservationError> {
+        let tenant = &self.tenant;
+        let mut tx = self.begin().await?;
+        if key.is_empty() {
+            let rsvp = match mutation {
+                Mutation::Reserve(rsvp) => insert_reservation(&mut tx, tenant, rsvp).await?,
+                Mutation::Confirm(id) => confirm_reservation(&mut tx, tenant, id).await?,
+                Mutation::Cancel(id) => delete_reservation(&mut tx, tenant, id).await?,
+            };
+            tx.commit().await?;
+            return Ok(rsvp);
+        }
+
+        // keys are per tenant
+        sqlx::query(
+            "DELETE FROM rsvp.idempotency_keys WHERE tenant_id = $1 AND key = $2 AND expires_at < now()",
+        )
+        .bind(tenant)
+        .bind(&key)
+        .execute(&mut tx)
+        .await?;
+
+        let fingerprint = mutation.fingerprint(&self.actor);
+        let claimed = sqlx::query(
+            "INSERT INTO rsvp.idempotency_keys (tenant_id, key, op, fingerprint, expires_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (tenant_id, key) DO NOTHING",
+        )
+        .bind(tenant)
+        .bind(&key)
+        .bind(mutation.op())
+        .bind(&fingerprint)
+        .bind(Utc::now() + self.idempotency_ttl)
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+            == 1;
+
+        if !claimed {
+            let row = sqlx::query(
+                "SELECT fingerprint, response FROM rsvp.idempotency_keys WHERE tenant_id = $1 AND key = $2",
+            )
+            .bind(tenant)
+            .bind(&key)
+            .fetch_one(&mut tx)
+            .await?;
+            if row.get::<String, _>("fingerprint") != fingerprint {
+                return Err(ReservationError::IdempotencyKeyReused(key));
+            }
+            let response: Vec<u8> = row.get("response");
+            return abi::Reservation::decode(response.as_slice())
+                .map_err(|_| ReservationError::Unknown);
+        }
+
+        let rsvp = match mutation {
+            Mutation::Reserve(rsvp) => insert_reservation(&mut tx, tenant, rsvp).await?,
+            Mutation::Confirm(id) => confirm_reservation(&mut tx, tenant, id).await?,
+            Mutation::Cancel(id) => delete_reservation(&mut tx, tenant, id).await?,
+        };
+
+        sqlx::query(
+            "UPDATE rsvp.idempotency_keys SET response = $1 WHERE tenant_id = $2 AND key = $3",
+        )
+        .bind(rsvp.encode_to_vec())
+        .bind(tenant)
+        .bind(&key)
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(rsvp)
+    }
+
+    /// delete responses whose idempotency key has expired, return the number of deleted keys.
+    pub async fn purge_idempotency_keys(&self) -> Result<u64, ReservationError> {
+        let deleted = sqlx::query("DELETE FROM rsvp.idempotency_keys WHERE expires_at < now()")
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(deleted)
+    }
+}
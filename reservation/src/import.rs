@@ -0,0 +1,82 @@
+use crate::{ReservationError, Rsvp};
+use abi::Reservation;
+use serde::Serialize;
+
+/// reservations reserved per batch while importing
+const IMPORT_BATCH_SIZE: usize = 500;
+
+/// result of an import, one entry for every imported item.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// reserved reservations, with their ids
+    pub reserved: Vec<Reservation>,
+    /// items conflicting with an existing reservation, or an earlier item of the import
+    pub conflicts: Vec<ImportFailure>,
+    /// items that couldn't be parsed or reserved for other reasons
+    pub failures: Vec<ImportFailure>,
+    /// how many items were skipped on purpose, e.g. cancelled events
+    pub skipped: usize,
+    /// nothing was reserved, `reserved` is what would have been
+    pub dry_run: bool,
+}
+
+/// an item that wasn't reserved
+#[derive(Debug, Serialize)]
+pub struct ImportFailure {
+    /// where the item came from, e.g. the UID of an event or the line number
+    pub source: String,
+    /// the reservation made from the item, if it could be parsed
+    pub reservation: Option<Reservation>,
+    pub error: String,
+}
+
+impl ImportReport {
+    pub(crate) fn fail(&mut self, source: impl Into<String>, error: impl ToString) {
+        self.failures.push(ImportFailure {
+            source: source.into(),
+            reservation: None,
+            error: error.to_string(),
+        });
+    }
+
+    /// reserve the parsed items in batches, recording the result of each one.
+    ///
+    /// In dry run mode everything is reserved in one batch and rolled back, so conflicts between
+    /// the items are still found.
+    pub(crate) async fn reserve<R: Rsvp + ?Sized>(
+        &mut self,
+        rsvp: &R,
+        items: Vec<(String, Reservation)>,
+        dry_run: bool,
+    ) -> Result<(), ReservationError> {
+        self.dry_run = dry_run;
+        let batch_size = if dry_run {
+            items.len()
+        } else {
+            IMPORT_BATCH_SIZE
+        };
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            let (sources, rsvps): (Vec<_>, Vec<_>) = items.by_ref().take(batch_size).unzip();
+            let results = rsvp.reserve_batch(rsvps.clone(), dry_run).await?;
+            for ((source, rsvp), result) in sources.into_iter().zip(rsvps).zip(results) {
+                match result {
+                    Ok(rsvp) => self.reserved.push(rsvp),
+                    Err(e) => {
+                        let failure = ImportFailure {
+                            source,
+                            reservation: Some(rsvp),
+                            error: e.to_string(),
+                        };
+                        if e.is_conflict() {
+                            self.conflicts.push(failure);
+                        } else {
+                            self.failures.push(failure);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,24 +1,108 @@
+mod bulk;
+mod changes;
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
+mod delegation;
 mod error;
+mod ics;
+mod idempotency;
+mod import;
 mod manager;
+mod memory;
+mod migrate;
+#[cfg(test)]
+mod model;
+mod outbox;
+mod sqlite;
 mod validator;
+mod webhook;
 mod window;
 
 use abi::Reservation;
 use async_trait::async_trait;
+pub use bulk::{export_rows, import_rows, render_rows, BulkFormat};
+pub use changes::{ListenFilter, ReservationChange, ReservationChangeStream};
+use chrono::Duration;
 pub use error::ReservationError;
+pub use ics::{export_ics, import_ics, render_ics, IcsImportOptions};
+pub use import::{ImportFailure, ImportReport};
+pub use memory::MemoryReservationManager;
+pub use migrate::{DbConfig, MigrationStatus};
+#[cfg(feature = "nats")]
+pub use outbox::NatsSink;
+pub use outbox::{FileSink, MemorySink, Publisher, Sink};
+pub use sqlite::SqliteReservationManager;
 use sqlx::{types::Uuid, PgPool};
 use std::str::FromStr;
 use validator::Validator;
+pub use webhook::{
+    sign, WebhookDispatcher, WebhookSink, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER,
+};
 use window::Window;
 
 pub type ReservationId = Uuid;
 
+#[derive(Clone)]
 pub struct ReservationManager {
     pool: PgPool,
+    idempotency_ttl: Duration,
+    default_page_size: i64,
+    tenant: String,
+    actor: String,
+    check_owner: bool,
 }
 impl ReservationManager {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            idempotency_ttl: Duration::hours(24),
+            default_page_size: manager::DEFAULT_PAGE_SIZE,
+            tenant: String::new(),
+            actor: String::new(),
+            check_owner: false,
+        }
+    }
+
+    /// scope every operation to the tenant, default to the empty tenant.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    /// the tenant the operations are scoped to.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// record the user id as the actor of every change, default to none.
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// the user id recorded as the actor of the changes.
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// only let the actor book for themselves and the users delegating to them, and confirm,
+    /// update or cancel their own reservations and the ones of users delegating `manage` to
+    /// them, checked by the statement making the change. default to off.
+    pub fn with_owner_check(mut self, check_owner: bool) -> Self {
+        self.check_owner = check_owner;
+        self
+    }
+
+    /// set the page size of queries that don't specify one, default to 10.
+    pub fn with_default_page_size(mut self, page_size: i64) -> Self {
+        self.default_page_size = page_size;
+        self
+    }
+
+    /// set how long a response is kept for its idempotency key, default to 24 hours.
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
     }
 }
 
@@ -26,8 +110,26 @@ impl ReservationManager {
 pub trait Rsvp {
     /// 预定资源
     async fn reserve(&self, rsvp: abi::Reservation) -> Result<abi::Reservation, ReservationError>;
+    /// 带幂等键预定资源，重试时返回首次预定的结果（key 为空时等同于 reserve）
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: abi::Reservation,
+    ) -> Result<abi::Reservation, ReservationError>;
+    /// 批量预定资源，在一个事务中逐个预定并返回各自的结果，单个失败不影响其它（dry_run 时回滚事务）
+    async fn reserve_batch(
+        &self,
+        rsvps: Vec<abi::Reservation>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<abi::Reservation, ReservationError>>, ReservationError>;
     /// 改变资源状态（from pending to confirm）
     async fn change_status(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError>;
+    /// 带幂等键改变资源状态（key 为空时等同于 change_status）
+    async fn change_status_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<abi::Reservation, ReservationError>;
     /// 更新 note
     async fn update_note(
         &self,
@@ -38,11 +140,69 @@ pub trait Rsvp {
     async fn get(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError>;
     /// 删除资源
     async fn delete(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError>;
+    /// 带幂等键删除资源（key 为空时等同于 delete）
+    async fn delete_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<abi::Reservation, ReservationError>;
     /// 查询资源
     async fn query(
         &self,
         query: abi::ReservationQuery,
     ) -> Result<Vec<abi::Reservation>, ReservationError>;
+    /// 按 id 顺序游标分页查询资源，返回分页信息和本页资源
+    async fn filter(
+        &self,
+        filter: abi::ReservationFilter,
+    ) -> Result<(abi::FilterPager, Vec<abi::Reservation>), ReservationError>;
+    /// 监听资源变更，按 filter 过滤（未指定 since 时只返回监听开始后的变更）
+    async fn listen(
+        &self,
+        filter: ListenFilter,
+    ) -> Result<ReservationChangeStream, ReservationError>;
+    /// 按条件批量删除资源（dry_run 时只返回将被删除的资源）
+    async fn cancel_by_filter(
+        &self,
+        query: abi::ReservationQuery,
+        dry_run: bool,
+    ) -> Result<Vec<abi::Reservation>, ReservationError>;
+}
+
+#[async_trait]
+pub trait Webhooks {
+    /// 创建租户的 webhook，只接收租户的变更（secret 只在创建时返回）
+    async fn create_webhook(&self, webhook: abi::Webhook)
+        -> Result<abi::Webhook, ReservationError>;
+    /// 列出租户的所有 webhook（不返回 secret）
+    async fn list_webhooks(&self) -> Result<Vec<abi::Webhook>, ReservationError>;
+    /// 删除 webhook 及其未投递的通知
+    async fn delete_webhook(&self, id: Uuid) -> Result<abi::Webhook, ReservationError>;
+}
+
+#[async_trait]
+pub trait Delegations {
+    /// 授权 grantee 代 grantor 预定（同一对用户再次授权时覆盖 scope 和过期时间）
+    async fn create_delegation(
+        &self,
+        delegation: abi::Delegation,
+    ) -> Result<abi::Delegation, ReservationError>;
+    /// 列出用户授出或获得的授权（包括已过期的）
+    async fn list_delegations(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<abi::Delegation>, ReservationError>;
+    /// 获取授权
+    async fn get_delegation(&self, id: Uuid) -> Result<abi::Delegation, ReservationError>;
+    /// 撤销授权
+    async fn delete_delegation(&self, id: Uuid) -> Result<abi::Delegation, ReservationError>;
+    /// grantee 当前是否持有 grantor 授予的 scope 权限（已过期的授权不算）
+    async fn is_delegated(
+        &self,
+        grantor: &str,
+        grantee: &str,
+        scope: abi::DelegationScope,
+    ) -> Result<bool, ReservationError>;
 }
 
 impl Validator for ReservationId {
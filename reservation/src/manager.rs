@@ -1,146 +1,512 @@
-use crate::window::Window;
-use crate::{ReservationError, ReservationId, ReservationManager, Rsvp, Validator};
+use crate::idempotency::Mutation;
+use crate::window::{query_range, Window};
+use crate::{
+    changes, ListenFilter, ReservationChangeStream, ReservationError, ReservationId,
+    ReservationManager, Rsvp, Validator,
+};
+use abi::{ReservationMatchMode, ReservationSortKey};
 use async_trait::async_trait;
-use sqlx::{postgres::types::PgRange, types::Uuid, Row};
+use sqlx::{
+    postgres::types::PgRange, types::Uuid, Acquire, PgConnection, PgPool, Postgres, QueryBuilder,
+    Row, Transaction,
+};
+use std::ops::Bound;
+
+/// page size used when a query doesn't specify one
+pub(crate) const DEFAULT_PAGE_SIZE: i64 = 10;
 
 #[async_trait]
 impl Rsvp for ReservationManager {
     /// Create a new reservation.
-    async fn reserve(
+    async fn reserve(&self, rsvp: abi::Reservation) -> Result<abi::Reservation, ReservationError> {
+        let mut tx = self.begin().await?;
+        let rsvp = insert_reservation(&mut tx, &self.tenant, rsvp).await?;
+        tx.commit().await?;
+        Ok(rsvp)
+    }
+
+    /// create a new reservation, a retry with the same key returns the first result.
+    async fn reserve_idempotent(
         &self,
-        mut rsvp: abi::Reservation,
+        key: String,
+        rsvp: abi::Reservation,
     ) -> Result<abi::Reservation, ReservationError> {
-        let window = Window::from_reservation(&rsvp);
-        window.validate()?;
-
-        let status = rsvp.get_status();
-        let timespan = PgRange::from(window);
-
-        let id: Uuid = sqlx::query(
-            "INSERT INTO rsvp.reservations (user_id, status, resource_id, timespan, note) VALUES ($1, $2::rsvp.reservation_status, $3, $4, $5) RETURNING id",
-        )
-        .bind(rsvp.user_id.clone())
-        .bind(status.to_string())
-        .bind(rsvp.resource_id.clone())
-        .bind(timespan)
-        .bind(rsvp.note.clone())
-        .fetch_one(&self.pool)
-        .await?
-        .get(0);
-        rsvp.id = id.to_string();
+        self.idempotent(key, Mutation::Reserve(rsvp)).await
+    }
 
-        Ok(rsvp)
+    /// create reservations in one transaction, each in its own savepoint so a failure only
+    /// rolls back that one. in dry run mode the transaction is rolled back at the end, so the
+    /// returned ids are never used.
+    async fn reserve_batch(
+        &self,
+        rsvps: Vec<abi::Reservation>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<abi::Reservation, ReservationError>>, ReservationError> {
+        let mut tx = self.begin().await?;
+        let mut results = Vec::with_capacity(rsvps.len());
+        for rsvp in rsvps {
+            let mut savepoint = tx.begin().await?;
+            match insert_reservation(&mut savepoint, &self.tenant, rsvp).await {
+                Ok(rsvp) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(rsvp));
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(e));
+                }
+            }
+        }
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(results)
     }
 
     /// change pending status to confirmed status.
     async fn change_status(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError> {
-        let rsvp = sqlx::query_as(
-            "UPDATE rsvp.reservations SET status = 'confirmed' WHERE id = $1::UUID and status = 'pending' RETURNING *",
-        )
-        .bind(id)
-        .fetch_one(&self.pool)
-        .await?;
-
+        let mut tx = self.begin().await?;
+        let rsvp = confirm_reservation(&mut tx, &self.tenant, id).await?;
+        tx.commit().await?;
         Ok(rsvp)
     }
 
+    /// change pending status to confirmed status, a retry with the same key returns the first result.
+    async fn change_status_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<abi::Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Confirm(id)).await
+    }
+
     /// update reservation's note.
     async fn update_note(
         &self,
         id: ReservationId,
         note: String,
     ) -> Result<abi::Reservation, ReservationError> {
+        let mut tx = self.begin().await?;
         let rsvp = sqlx::query_as(
-            "UPDATE rsvp.reservations SET note = $1 WHERE id = $2::UUID RETURNING *",
+            "UPDATE rsvp.reservations SET note = $1 WHERE id = $2::UUID AND tenant_id = $3 AND rsvp.may_change(tenant_id, user_id) RETURNING *",
         )
         .bind(note)
         .bind(id)
-        .fetch_one(&self.pool)
+        .bind(&self.tenant)
+        .fetch_optional(&mut tx)
         .await?;
+        let rsvp = match rsvp {
+            Some(rsvp) => rsvp,
+            None => return Err(unchanged(&mut tx, &self.tenant, id).await),
+        };
+        tx.commit().await?;
 
         Ok(rsvp)
     }
 
     /// get a reservation by id.
     async fn get(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError> {
-        let rsvp = sqlx::query_as("SELECT * FROM rsvp.reservations WHERE id = $1::UUID")
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
+        let mut tx = self.begin().await?;
+        let rsvp = sqlx::query_as(
+            "SELECT * FROM rsvp.reservations WHERE id = $1::UUID AND tenant_id = $2",
+        )
+        .bind(id)
+        .bind(&self.tenant)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
         Ok(rsvp)
     }
 
     /// delete a reservation by id.
     async fn delete(&self, id: ReservationId) -> Result<abi::Reservation, ReservationError> {
-        let rsvp = sqlx::query_as("DELETE FROM rsvp.reservations WHERE id = $1::UUID RETURNING *")
-            .bind(id)
-            .fetch_one(&self.pool)
-            .await?;
+        let mut tx = self.begin().await?;
+        let rsvp = delete_reservation(&mut tx, &self.tenant, id).await?;
+        tx.commit().await?;
         Ok(rsvp)
     }
 
-    /// query a reservation
+    /// delete a reservation by id, a retry with the same key returns the first result.
+    async fn delete_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<abi::Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Cancel(id)).await
+    }
+
+    /// query reservations, time range is matched by the query's match mode.
     async fn query(
         &self,
         query: abi::ReservationQuery,
     ) -> Result<Vec<abi::Reservation>, ReservationError> {
-        // convert query.start and query.end to PgRange
-        let window = Window::from_query(&query);
-        let timespan = PgRange::from(window);
-        let status = query.get_status();
-
-        let user_id: Option<String> = {
-            if query.user_id.is_empty() {
-                None
-            } else {
-                Some(query.user_id)
-            }
+        let cursor = query_cursor(&query)?;
+        let mut builder = QueryBuilder::new("SELECT * FROM rsvp.reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        if let Some(cursor) = cursor {
+            builder
+                .push(if query.desc {
+                    " AND id < "
+                } else {
+                    " AND id > "
+                })
+                .push_bind(cursor);
+        }
+
+        let direction = if query.desc { "DESC" } else { "ASC" };
+        match query.sort_by() {
+            ReservationSortKey::Start => builder.push(" ORDER BY lower(timespan) "),
+            ReservationSortKey::End => builder.push(" ORDER BY upper(timespan) "),
+            ReservationSortKey::Id => builder.push(" ORDER BY id "),
         };
+        // id breaks ties, so pages are stable
+        builder.push(direction).push(", id ").push(direction);
 
-        let resource_id = {
-            if query.resource_id.is_empty() {
-                None
-            } else {
-                Some(query.resource_id)
-            }
+        let page = query.page.max(1);
+        let page_size = if query.page_size > 0 {
+            query.page_size
+        } else {
+            self.default_page_size
         };
+        builder.push(" LIMIT ").push_bind(page_size);
+        if cursor.is_none() {
+            builder.push(" OFFSET ").push_bind((page - 1) * page_size);
+        }
 
-        let desc: Option<bool> = {
-            if query.desc {
-                Some(true)
-            } else {
-                None
-            }
+        let mut tx = self.begin().await?;
+        let rsvps = builder.build_query_as().fetch_all(&mut tx).await?;
+        tx.commit().await?;
+        Ok(rsvps)
+    }
+
+    /// page through the reservations matching the filter by id, after `filter.cursor` if given.
+    async fn filter(
+        &self,
+        filter: abi::ReservationFilter,
+    ) -> Result<(abi::FilterPager, Vec<abi::Reservation>), ReservationError> {
+        let query = filter.to_query();
+        let page_size = if filter.page_size > 0 {
+            filter.page_size
+        } else {
+            self.default_page_size
         };
+        let mut builder = QueryBuilder::new("SELECT * FROM rsvp.reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        if let Some(cursor) = filter_cursor(&filter)? {
+            builder
+                .push(if filter.desc {
+                    " AND id < "
+                } else {
+                    " AND id > "
+                })
+                .push_bind(cursor);
+        }
+        let direction = if filter.desc { "DESC" } else { "ASC" };
+        // one more to tell if there is a next page
+        builder
+            .push(" ORDER BY id ")
+            .push(direction)
+            .push(" LIMIT ")
+            .push_bind(page_size + 1);
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM rsvp.reservations");
+        push_conditions(&mut count, &self.tenant, &query)?;
+
+        let mut tx = self.begin().await?;
+        let rsvps = builder.build_query_as().fetch_all(&mut tx).await?;
+        let total: i64 = count.build().fetch_one(&mut tx).await?.get(0);
+        tx.commit().await?;
+        Ok(filter_page(&filter, page_size, rsvps, total))
+    }
+
+    /// stream changes matching the filter, resuming after `filter.since` if given.
+    async fn listen(
+        &self,
+        filter: ListenFilter,
+    ) -> Result<ReservationChangeStream, ReservationError> {
+        changes::listen(&self.pool, &self.tenant, filter).await
+    }
+
+    /// delete all reservations matching the query in one transaction.
+    /// in dry run mode the transaction is rolled back, so nothing is deleted and no change is recorded.
+    async fn cancel_by_filter(
+        &self,
+        query: abi::ReservationQuery,
+        dry_run: bool,
+    ) -> Result<Vec<abi::Reservation>, ReservationError> {
+        if query.get_user_ids().is_empty()
+            && query.get_resource_ids().is_empty()
+            && query.get_statuses().is_empty()
+            && query.start.is_none()
+            && query.end.is_none()
+        {
+            return Err(ReservationError::InvalidFilter(
+                "refuse to cancel all reservations, at least one condition is required".into(),
+            ));
+        }
+
+        let mut builder = QueryBuilder::new("DELETE FROM rsvp.reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        builder.push(" RETURNING *");
+
+        let mut tx = self.begin().await?;
+        // the trigger records a change event for every deleted row
+        let rsvps = builder.build_query_as().fetch_all(&mut tx).await?;
 
-        let page: Option<i64> = {
-            if query.page > 0 {
-                Some(query.page)
-            } else {
-                None
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(rsvps)
+    }
+}
+
+impl ReservationManager {
+    /// begin a transaction of the manager's tenant, with `rsvp.actor` set for the change trigger.
+    pub(crate) async fn begin(&self) -> Result<Transaction<'static, Postgres>, ReservationError> {
+        let mut tx = begin(&self.pool, &self.tenant).await?;
+        if !self.actor.is_empty() {
+            sqlx::query("SELECT set_config('rsvp.actor', $1, TRUE)")
+                .bind(&self.actor)
+                .execute(&mut tx)
+                .await?;
+        }
+        if self.check_owner {
+            sqlx::query("SELECT set_config('rsvp.check_owner', 'on', TRUE)")
+                .execute(&mut tx)
+                .await?;
+        }
+        Ok(tx)
+    }
+}
+
+/// begin a transaction with `rsvp.tenant_id` set to the tenant, for the row level security
+/// policies. queries still filter by tenant, the policies only apply to roles not owning the
+/// tables.
+pub(crate) async fn begin(
+    pool: &PgPool,
+    tenant: &str,
+) -> Result<Transaction<'static, Postgres>, ReservationError> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT set_config('rsvp.tenant_id', $1, TRUE)")
+        .bind(tenant)
+        .execute(&mut tx)
+        .await?;
+    Ok(tx)
+}
+
+/// the reservation id to continue a filter after, if any.
+pub(crate) fn filter_cursor(
+    filter: &abi::ReservationFilter,
+) -> Result<Option<ReservationId>, ReservationError> {
+    parse_cursor(filter.cursor.as_deref())
+}
+
+/// the reservation id to continue a query after, if any. only a query sorted by id has one.
+pub(crate) fn query_cursor(
+    query: &abi::ReservationQuery,
+) -> Result<Option<ReservationId>, ReservationError> {
+    if query.cursor.is_some() && query.sort_by() != ReservationSortKey::Id {
+        return Err(ReservationError::InvalidFilter(
+            "a cursor requires sorting by id".into(),
+        ));
+    }
+    parse_cursor(query.cursor.as_deref())
+}
+
+fn parse_cursor(cursor: Option<&str>) -> Result<Option<ReservationId>, ReservationError> {
+    cursor
+        .map(|cursor| {
+            cursor
+                .parse()
+                .map_err(|_| ReservationError::InvalidReservationId)
+        })
+        .transpose()
+}
+
+/// make a page of the reservations after the cursor, fetched with one more than the page size to
+/// tell if there is a next page.
+pub(crate) fn filter_page(
+    filter: &abi::ReservationFilter,
+    page_size: i64,
+    mut rsvps: Vec<abi::Reservation>,
+    total: i64,
+) -> (abi::FilterPager, Vec<abi::Reservation>) {
+    let has_next = rsvps.len() as i64 > page_size;
+    rsvps.truncate(page_size as usize);
+    let pager = abi::FilterPager {
+        prev: filter.cursor.as_ref().map(|cursor| {
+            rsvps
+                .first()
+                .map_or_else(|| cursor.clone(), |r| r.id.clone())
+        }),
+        next: rsvps.last().filter(|_| has_next).map(|r| r.id.clone()),
+        total: Some(total),
+    };
+    (pager, rsvps)
+}
+
+/// push the WHERE clause of a query: tenant, time range, user ids, resource ids and statuses.
+fn push_conditions(
+    builder: &mut QueryBuilder<Postgres>,
+    tenant: &str,
+    query: &abi::ReservationQuery,
+) -> Result<(), ReservationError> {
+    builder
+        .push(" WHERE tenant_id = ")
+        .push_bind(tenant.to_string());
+
+    let timespan = query_range(query)?;
+    match query.match_mode() {
+        _ if timespan.start == Bound::Unbounded && timespan.end == Bound::Unbounded => {}
+        ReservationMatchMode::Contains => {
+            builder.push(" AND timespan <@ ").push_bind(timespan);
+        }
+        ReservationMatchMode::Overlaps => {
+            builder.push(" AND timespan && ").push_bind(timespan);
+        }
+        ReservationMatchMode::StartsWithin => {
+            if let Bound::Excluded(start) = timespan.start {
+                builder.push(" AND lower(timespan) >= ").push_bind(start);
             }
-        };
-        let page_size: Option<i64> = {
-            if query.page_size > 0 {
-                Some(query.page_size)
-            } else {
-                None
+            if let Bound::Excluded(end) = timespan.end {
+                builder.push(" AND lower(timespan) < ").push_bind(end);
             }
-        };
+        }
+    }
 
-        let rsvp: Vec<abi::Reservation> = sqlx::query_as(
-            "SELECT * FROM rsvp.query ($1, $2, $3::TSTZRANGE, $4::rsvp.reservation_status, $5::INTEGER, $6, $7::INTEGER)"
-        )
-        .bind(user_id)
-        .bind(resource_id)
-        .bind(timespan)
-        .bind(status)
-        .bind(page)
-        .bind(desc)
-        .bind(page_size)
-        .fetch_all(&self.pool)
-        .await?;
-        Ok(rsvp)
+    let user_ids = query.get_user_ids();
+    if !user_ids.is_empty() {
+        builder
+            .push(" AND user_id = ANY(")
+            .push_bind(user_ids)
+            .push(")");
+    }
+
+    let resource_ids = query.get_resource_ids();
+    if !resource_ids.is_empty() {
+        builder
+            .push(" AND resource_id = ANY(")
+            .push_bind(resource_ids)
+            .push(")");
+    }
+
+    let statuses = query.get_statuses();
+    if !statuses.is_empty() {
+        builder
+            .push(" AND status = ANY(")
+            .push_bind(statuses)
+            .push("::rsvp.reservation_status[])");
+    }
+    Ok(())
+}
+
+/// insert a reservation of the tenant and fill its id.
+pub(crate) async fn insert_reservation(
+    conn: &mut PgConnection,
+    tenant: &str,
+    mut rsvp: abi::Reservation,
+) -> Result<abi::Reservation, ReservationError> {
+    if rsvp.start.is_none() || rsvp.end.is_none() {
+        return Err(ReservationError::InvalidTimespan);
+    }
+    let window = Window::from_reservation(&rsvp);
+    window.validate()?;
+
+    let status = rsvp.get_status();
+    let timespan = PgRange::from(window);
+    rsvp.tenant_id = tenant.to_string();
+
+    // booked by the actor of the transaction, if the actor may book for the user
+    let row = sqlx::query(
+        "INSERT INTO rsvp.reservations (user_id, status, resource_id, timespan, note, tenant_id, booked_by) SELECT $1, $2::rsvp.reservation_status, $3, $4, $5, $6, COALESCE(current_setting('rsvp.actor', TRUE), '') WHERE rsvp.may_book($6, $1) RETURNING id, booked_by",
+    )
+    .bind(rsvp.user_id.clone())
+    .bind(status.to_string())
+    .bind(rsvp.resource_id.clone())
+    .bind(timespan)
+    .bind(rsvp.note.clone())
+    .bind(tenant)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(row) = row else {
+        let actor: String =
+            sqlx::query_scalar("SELECT COALESCE(current_setting('rsvp.actor', TRUE), '')")
+                .fetch_one(conn)
+                .await?;
+        return Err(ReservationError::PermissionDenied(format!(
+            "caller {actor:?} can't book for {}",
+            rsvp.user_id
+        )));
+    };
+    rsvp.id = row.get::<Uuid, _>("id").to_string();
+    rsvp.booked_by = row.get("booked_by");
+
+    Ok(rsvp)
+}
+
+/// change a pending reservation of the tenant to confirmed, if the actor may change it.
+pub(crate) async fn confirm_reservation(
+    conn: &mut PgConnection,
+    tenant: &str,
+    id: ReservationId,
+) -> Result<abi::Reservation, ReservationError> {
+    let rsvp = sqlx::query_as(
+        "UPDATE rsvp.reservations SET status = 'confirmed' WHERE id = $1::UUID AND tenant_id = $2 AND status = 'pending' AND rsvp.may_change(tenant_id, user_id) RETURNING *",
+    )
+    .bind(id)
+    .bind(tenant)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match rsvp {
+        Some(rsvp) => Ok(rsvp),
+        None => Err(unchanged(conn, tenant, id).await),
+    }
+}
+
+/// delete a reservation of the tenant by id, if the actor may change it.
+pub(crate) async fn delete_reservation(
+    conn: &mut PgConnection,
+    tenant: &str,
+    id: ReservationId,
+) -> Result<abi::Reservation, ReservationError> {
+    let rsvp = sqlx::query_as(
+        "DELETE FROM rsvp.reservations WHERE id = $1::UUID AND tenant_id = $2 AND rsvp.may_change(tenant_id, user_id) RETURNING *",
+    )
+    .bind(id)
+    .bind(tenant)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match rsvp {
+        Some(rsvp) => Ok(rsvp),
+        None => Err(unchanged(conn, tenant, id).await),
+    }
+}
+
+/// why a change matched no reservation of the tenant: it's missing, not in the state the change
+/// requires, or of a user the actor may not change.
+async fn unchanged(conn: &mut PgConnection, tenant: &str, id: ReservationId) -> ReservationError {
+    let row = sqlx::query(
+        "SELECT user_id, rsvp.may_change(tenant_id, user_id) AS allowed, COALESCE(current_setting('rsvp.actor', TRUE), '') AS actor FROM rsvp.reservations WHERE id = $1 AND tenant_id = $2",
+    )
+    .bind(id)
+    .bind(tenant)
+    .fetch_optional(conn)
+    .await;
+    match row {
+        Ok(Some(row)) if !row.get::<bool, _>("allowed") => {
+            ReservationError::PermissionDenied(format!(
+                "caller {:?} can't change the reservations of {}",
+                row.get::<String, _>("actor"),
+                row.get::<String, _>("user_id")
+            ))
+        }
+        Ok(_) => ReservationError::NotFound,
+        Err(e) => e.into(),
     }
 }
 
@@ -150,6 +516,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::Delegations;
     use abi::{to_timestamp, ReservationQueryBuilder, ReservationStatus};
     use chrono::{DateTime, FixedOffset, Utc};
     use prost_types::Timestamp;
@@ -180,6 +547,7 @@ mod tests {
             start: Some(to_timestamp(start_dt.with_timezone(&Utc))),
             end: Some(to_timestamp(end_dt.with_timezone(&Utc))),
             note: note.to_string(),
+            ..Default::default()
         }
     }
 
@@ -224,6 +592,45 @@ mod tests {
         assert!(err.is_err());
     }
 
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn reserve_batch_should_keep_going_after_failure() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvps = vec![
+            generate_resource(
+                "M4n5ter",
+                "hotel room 1",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-20T14:00:00+0800",
+                "first",
+            ),
+            generate_resource(
+                "Syuu",
+                "hotel room 1",
+                "2022-11-19T12:00:00+0800",
+                "2022-11-21T14:00:00+0800",
+                "conflict",
+            ),
+            generate_resource(
+                "Syuu",
+                "hotel room 2",
+                "2022-11-19T12:00:00+0800",
+                "2022-11-21T14:00:00+0800",
+                "other room",
+            ),
+        ];
+        let results = manager.reserve_batch(rsvps.clone(), true).await.unwrap();
+        assert!(results[1].as_ref().unwrap_err().is_conflict());
+        let id = results[0].as_ref().unwrap().id.parse().unwrap();
+        assert!(manager.get(id).await.is_err());
+
+        let results = manager.reserve_batch(rsvps, false).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].as_ref().unwrap_err().is_conflict());
+        let rsvp = results[2].as_ref().unwrap();
+        assert_eq!(manager.get(rsvp.id.parse().unwrap()).await.unwrap(), *rsvp);
+    }
+
     /// change status should work for pending reservation
     #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
     async fn change_status_should_work_for_pending_reservation() {
@@ -473,8 +880,11 @@ mod tests {
             .unwrap();
         let rsvps = manager.query(query).await.unwrap();
         assert_eq!(rsvps.len(), 4);
-        assert_eq!(rsvps[0].id, rsvp_2.id);
-        assert_eq!(rsvps[1].id, rsvp_3.id);
+        // rsvp_2 and rsvp_3 start at the same time, ties are sorted by id
+        let tied = vec![rsvps[0].id.clone(), rsvps[1].id.clone()];
+        let mut expected = vec![rsvp_2.id.clone(), rsvp_3.id.clone()];
+        expected.sort_by_key(|id| Uuid::from_str(id).unwrap());
+        assert_eq!(tied, expected);
         assert_eq!(rsvps[2].id, rsvp_4.id);
         assert_eq!(rsvps[3].id, rsvp_5.id);
 
@@ -488,8 +898,11 @@ mod tests {
         let rsvp = manager.query(query).await.unwrap();
         assert_eq!(rsvp.len(), 3);
         assert_eq!(rsvp[0].id, rsvp_1.id);
-        assert_eq!(rsvp[1].id, rsvp_6.id);
-        assert_eq!(rsvp[2].id, rsvp_7.id);
+        // rsvp_6 and rsvp_7 start at the same time, ties are sorted by id
+        let tied = vec![rsvp[1].id.clone(), rsvp[2].id.clone()];
+        let mut expected = vec![rsvp_6.id.clone(), rsvp_7.id.clone()];
+        expected.sort_by_key(|id| Uuid::from_str(id).unwrap());
+        assert_eq!(tied, expected);
     }
 
     /// update_note should work
@@ -519,6 +932,114 @@ mod tests {
         );
     }
 
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn owner_check_should_only_book_for_delegating_users() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let bob = manager.clone().with_actor("bob").with_owner_check(true);
+        let for_alice = |start: &str, end: &str| generate_resource("alice", "room", start, end, "");
+
+        let err = bob
+            .reserve(for_alice(
+                "2022-12-01T12:00:00-0700",
+                "2022-12-01T13:00:00-0700",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "permission denied: caller \"bob\" can't book for alice"
+        );
+
+        let delegation = manager
+            .create_delegation(abi::Delegation {
+                grantor: "alice".into(),
+                grantee: "bob".into(),
+                scope: abi::DelegationScope::Book as i32,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rsvp = bob
+            .reserve(for_alice(
+                "2022-12-01T12:00:00-0700",
+                "2022-12-01T13:00:00-0700",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(rsvp.booked_by, "bob");
+
+        // revoking waits for a booking relying on the delegation
+        let mut tx = bob.begin().await.unwrap();
+        insert_reservation(
+            &mut tx,
+            "",
+            for_alice("2022-12-02T12:00:00-0700", "2022-12-02T13:00:00-0700"),
+        )
+        .await
+        .unwrap();
+        let id = delegation.id.parse().unwrap();
+        let revoking = tokio::spawn(async move { manager.delete_delegation(id).await });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!revoking.is_finished());
+        tx.commit().await.unwrap();
+        revoking.await.unwrap().unwrap();
+
+        let err = bob
+            .reserve(for_alice(
+                "2022-12-03T12:00:00-0700",
+                "2022-12-03T13:00:00-0700",
+            ))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReservationError::PermissionDenied(_)));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn owner_check_should_only_change_own_or_managed_reservations() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp = manager
+            .reserve(generate_resource("alice", "ixia-3230", "", "", "hello"))
+            .await
+            .unwrap();
+        let id = Uuid::from_str(&rsvp.id).unwrap();
+        let as_user = |user: &str| manager.clone().with_actor(user).with_owner_check(true);
+
+        // another user can't change it, even by retrying with an idempotency key
+        let bob = as_user("bob");
+        for err in [
+            bob.change_status(id).await.unwrap_err(),
+            bob.update_note(id, "bob".into()).await.unwrap_err(),
+            bob.delete_idempotent("k1".into(), id).await.unwrap_err(),
+        ] {
+            assert_eq!(
+                err.to_string(),
+                "permission denied: caller \"bob\" can't change the reservations of alice"
+            );
+        }
+
+        // unless alice lets bob manage her reservations
+        manager
+            .create_delegation(abi::Delegation {
+                grantor: "alice".into(),
+                grantee: "bob".into(),
+                scope: abi::DelegationScope::Manage as i32,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let rsvp = bob.update_note(id, "bob".into()).await.unwrap();
+        assert_eq!(rsvp.note, "bob");
+
+        // the owner can, and a missing one is not found
+        let alice = as_user("alice");
+        alice.change_status(id).await.unwrap();
+        alice.delete(id).await.unwrap();
+        assert!(matches!(
+            alice.delete(id).await.unwrap_err(),
+            ReservationError::NotFound
+        ));
+    }
+
     /// get should work
     #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
     async fn get_should_work() {
@@ -580,10 +1101,414 @@ mod tests {
             .get(Uuid::from_str(&rsvp_10.id).unwrap())
             .await
             .unwrap_err();
-        //TODO: parse error
+        assert!(matches!(err, ReservationError::NotFound));
+    }
+
+    /// cancel_by_filter should delete matching reservations and record a change for each of them
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn cancel_by_filter_should_work() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp_1 = generate_resource(
+            "M4n5ter",
+            "class room 1",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 1",
+        );
+        let rsvp_2 = generate_resource(
+            "M4n5ter",
+            "class room 2",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 2",
+        );
+        let rsvp_3 = generate_resource(
+            "Syuu",
+            "class room 3",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 3",
+        );
+        let rsvp_1 = manager.reserve(rsvp_1).await.unwrap();
+        let rsvp_2 = manager.reserve(rsvp_2).await.unwrap();
+        let rsvp_3 = manager.reserve(rsvp_3).await.unwrap();
+
+        // an empty filter would cancel everything, so it is rejected
+        let err = manager
+            .cancel_by_filter(ReservationQueryBuilder::default().build().unwrap(), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReservationError::InvalidFilter(_)));
+
+        // dry run returns the affected reservations but keeps them
+        let query = ReservationQueryBuilder::default()
+            .user_id("M4n5ter")
+            .build()
+            .unwrap();
+        let rsvps = manager.cancel_by_filter(query.clone(), true).await.unwrap();
+        assert_eq!(rsvps.len(), 2);
+        manager
+            .get(Uuid::from_str(&rsvp_1.id).unwrap())
+            .await
+            .unwrap();
+
+        let rsvps = manager.cancel_by_filter(query, false).await.unwrap();
+        let mut ids: Vec<_> = rsvps.into_iter().map(|r| r.id).collect();
+        ids.sort();
+        let mut expected = vec![rsvp_1.id.clone(), rsvp_2.id.clone()];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        assert!(manager
+            .get(Uuid::from_str(&rsvp_1.id).unwrap())
+            .await
+            .is_err());
+        assert!(manager
+            .get(Uuid::from_str(&rsvp_2.id).unwrap())
+            .await
+            .is_err());
+        manager
+            .get(Uuid::from_str(&rsvp_3.id).unwrap())
+            .await
+            .unwrap();
+
+        let deleted: i64 =
+            sqlx::query("SELECT COUNT(*) FROM rsvp.reservation_changes WHERE op = 'delete'")
+                .fetch_one(&migrated_pool)
+                .await
+                .unwrap()
+                .get(0);
+        assert_eq!(deleted, 2);
+    }
+
+    /// a retried request with the same idempotency key should return the original reservation
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn idempotent_mutations_should_run_once() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp = generate_resource(
+            "M4n5ter",
+            "class room 1",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 1",
+        );
+
+        let first = manager
+            .reserve_idempotent("reserve-1".into(), rsvp.clone())
+            .await
+            .unwrap();
+        // without the key the retry would conflict with the first attempt
+        let retried = manager
+            .reserve_idempotent("reserve-1".into(), rsvp.clone())
+            .await
+            .unwrap();
+        assert_eq!(first, retried);
+
+        let id = Uuid::from_str(&first.id).unwrap();
+        let confirmed = manager
+            .change_status_idempotent("confirm-1".into(), id)
+            .await
+            .unwrap();
+        // the reservation is no longer pending, but the stored response is returned
+        let retried = manager
+            .change_status_idempotent("confirm-1".into(), id)
+            .await
+            .unwrap();
+        assert_eq!(confirmed, retried);
+        assert_eq!(retried.status, ReservationStatus::Confirmed as i32);
+
+        let err = manager
+            .delete_idempotent("confirm-1".into(), id)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+
+        let cancelled = manager
+            .delete_idempotent("cancel-1".into(), id)
+            .await
+            .unwrap();
+        let retried = manager
+            .delete_idempotent("cancel-1".into(), id)
+            .await
+            .unwrap();
+        assert_eq!(cancelled, retried);
+    }
+
+    /// an expired idempotency key should not be honored
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn expired_idempotency_key_should_run_again() {
+        let manager = ReservationManager::new(migrated_pool.clone())
+            .with_idempotency_ttl(chrono::Duration::zero());
+        let rsvp = generate_resource(
+            "M4n5ter",
+            "class room 1",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 1",
+        );
+
+        manager
+            .reserve_idempotent("reserve-1".into(), rsvp.clone())
+            .await
+            .unwrap();
+        assert!(manager
+            .reserve_idempotent("reserve-1".into(), rsvp)
+            .await
+            .is_err());
+        assert_eq!(manager.purge_idempotency_keys().await.unwrap(), 1);
+    }
+
+    /// query should support overlap mode, multiple values and sort keys
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn query_should_support_overlap_and_multiple_values() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        // starts before the query window and ends inside it
+        let rsvp_1 = generate_resource(
+            "M4n5ter",
+            "class room 1",
+            "2022-11-17T22:00:00+0800",
+            "2022-11-18T08:00:00+0800",
+            "overnight",
+        );
+        let rsvp_2 = generate_resource(
+            "Syuu",
+            "class room 2",
+            "2022-11-18T09:00:00+0800",
+            "2022-11-18T12:00:00+0800",
+            "morning",
+        );
+        let rsvp_3 = generate_resource(
+            "Tyr",
+            "class room 3",
+            "2022-11-18T13:00:00+0800",
+            "2022-11-18T15:00:00+0800",
+            "afternoon",
+        );
+        let rsvp_1 = manager.reserve(rsvp_1).await.unwrap();
+        let rsvp_2 = manager.reserve(rsvp_2).await.unwrap();
+        let rsvp_3 = manager.reserve(rsvp_3).await.unwrap();
+        manager
+            .change_status(rsvp_2.id.parse().unwrap())
+            .await
+            .unwrap();
+
+        let builder = || {
+            let mut builder = ReservationQueryBuilder::default();
+            builder
+                .start("2022-11-18T00:00:00+0800".parse::<Timestamp>().unwrap())
+                .end("2022-11-19T00:00:00+0800".parse::<Timestamp>().unwrap());
+            builder
+        };
+
+        // the overnight reservation isn't contained in the day
+        let rsvps = manager.query(builder().build().unwrap()).await.unwrap();
+        let ids: Vec<_> = rsvps.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec![rsvp_2.id.as_str(), rsvp_3.id.as_str()]);
+
+        // but it overlaps the day
+        let query = builder()
+            .match_mode(ReservationMatchMode::Overlaps as i32)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query).await.unwrap();
+        let ids: Vec<_> = rsvps.iter().map(|r| r.id.as_str()).collect();
         assert_eq!(
-            err.to_string(),
-            "DB error: no rows returned by a query that expected to return at least one row"
+            ids,
+            vec![rsvp_1.id.as_str(), rsvp_2.id.as_str(), rsvp_3.id.as_str()]
+        );
+
+        // multiple user ids and statuses, sort by end time desc
+        let query = builder()
+            .match_mode(ReservationMatchMode::Overlaps as i32)
+            .user_id("M4n5ter")
+            .user_ids(vec!["Syuu".to_string(), "Tyr".to_string()])
+            .statuses(vec![
+                ReservationStatus::Pending as i32,
+                ReservationStatus::Confirmed as i32,
+            ])
+            .sort_by(ReservationSortKey::End as i32)
+            .desc(true)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query).await.unwrap();
+        let ids: Vec<_> = rsvps.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![rsvp_3.id.as_str(), rsvp_2.id.as_str(), rsvp_1.id.as_str()]
+        );
+
+        // multiple resource ids with a single status
+        let query = builder()
+            .match_mode(ReservationMatchMode::Overlaps as i32)
+            .resource_ids(vec!["class room 1".to_string(), "class room 2".to_string()])
+            .status(ReservationStatus::Pending as i32)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query).await.unwrap();
+        assert_eq!(rsvps.len(), 1);
+        assert_eq!(rsvps[0].id, rsvp_1.id);
+
+        // only the afternoon reservation starts within 10:00 ~ 14:00
+        let query = ReservationQueryBuilder::default()
+            .start("2022-11-18T10:00:00+0800".parse::<Timestamp>().unwrap())
+            .end("2022-11-18T14:00:00+0800".parse::<Timestamp>().unwrap())
+            .match_mode(ReservationMatchMode::StartsWithin as i32)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query).await.unwrap();
+        assert_eq!(rsvps.len(), 1);
+        assert_eq!(rsvps[0].id, rsvp_3.id);
+
+        // pagination
+        let query = builder()
+            .match_mode(ReservationMatchMode::Overlaps as i32)
+            .page(2)
+            .page_size(2)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query).await.unwrap();
+        assert_eq!(rsvps.len(), 1);
+        assert_eq!(rsvps[0].id, rsvp_3.id);
+    }
+
+    /// listen should stream changes made after it was called
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn listen_should_stream_changes() {
+        use abi::ReservationUpdateType;
+        use futures::StreamExt;
+
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp = generate_resource(
+            "M4n5ter",
+            "class room 1",
+            "2022-11-18T12:00:00+0800",
+            "2022-11-20T14:00:00+0800",
+            "note 1",
+        );
+        // changes before listening are not streamed
+        let old = manager.reserve(rsvp.clone()).await.unwrap();
+        manager.delete(old.id.parse().unwrap()).await.unwrap();
+
+        let mut changes = manager.listen(ListenFilter::default()).await.unwrap();
+        let rsvp = manager.reserve(rsvp).await.unwrap();
+        let id: ReservationId = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        manager.delete(id).await.unwrap();
+
+        let mut ops = vec![];
+        for _ in 0..3 {
+            let change = changes.next().await.unwrap().unwrap();
+            assert_eq!(change.reservation.id, rsvp.id);
+            ops.push(change.op);
+        }
+        assert_eq!(
+            ops,
+            vec![
+                ReservationUpdateType::Create,
+                ReservationUpdateType::Update,
+                ReservationUpdateType::Delete
+            ]
         );
     }
+
+    /// listen should filter changes by user or resource and resume after a change id
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn listen_should_filter_and_resume() {
+        use abi::ReservationUpdateType;
+        use futures::StreamExt;
+
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let mut all = manager.listen(ListenFilter::default()).await.unwrap();
+        let rsvp_1 = manager
+            .reserve(generate_resource(
+                "M4n5ter",
+                "class room 1",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+                "",
+            ))
+            .await
+            .unwrap();
+        let rsvp_2 = manager
+            .reserve(generate_resource(
+                "Syuu",
+                "class room 2",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+                "",
+            ))
+            .await
+            .unwrap();
+        manager.delete(rsvp_2.id.parse().unwrap()).await.unwrap();
+        let first = all.next().await.unwrap().unwrap();
+        assert_eq!(first.reservation.id, rsvp_1.id);
+
+        // resume after the first change, only the changes of class room 2
+        let filter = ListenFilter {
+            resource_ids: vec!["class room 2".to_string()],
+            since: Some(first.id),
+            ..Default::default()
+        };
+        let mut changes = manager.listen(filter).await.unwrap();
+        let created = changes.next().await.unwrap().unwrap();
+        assert_eq!(created.op, ReservationUpdateType::Create);
+        // changes carry the current state, rsvp_2 is already deleted
+        assert_eq!(created.reservation.id, rsvp_2.id);
+        assert_eq!(created.reservation.resource_id, "class room 2");
+        let deleted = changes.next().await.unwrap().unwrap();
+        assert_eq!(deleted.op, ReservationUpdateType::Delete);
+        assert_eq!(deleted.reservation.id, rsvp_2.id);
+        // a deleted reservation keeps its owner
+        assert_eq!(deleted.reservation.user_id, "Syuu");
+        assert_eq!(
+            deleted.reservation.status,
+            abi::ReservationStatus::Pending as i32
+        );
+        assert!(deleted.id > created.id);
+
+        let filter = ListenFilter {
+            user_ids: vec!["M4n5ter".to_string()],
+            since: Some(first.id),
+            ..Default::default()
+        };
+        let mut changes = manager.listen(filter).await.unwrap();
+        manager
+            .change_status(rsvp_1.id.parse().unwrap())
+            .await
+            .unwrap();
+        let updated = changes.next().await.unwrap().unwrap();
+        assert_eq!(updated.op, ReservationUpdateType::Update);
+        assert_eq!(updated.reservation.id, rsvp_1.id);
+    }
+
+    /// listen should deliver changes committed after it started, even with a lower position
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn listen_should_not_skip_late_commits() {
+        use futures::StreamExt;
+
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp = |resource: &str| {
+            generate_resource(
+                "M4n5ter",
+                resource,
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+                "",
+            )
+        };
+        // room 1 is recorded first but commits last, room 2 is committed before listening
+        let mut tx = migrated_pool.begin().await.unwrap();
+        insert_reservation(&mut tx, "", rsvp("room 1"))
+            .await
+            .unwrap();
+        manager.reserve(rsvp("room 2")).await.unwrap();
+        let mut changes = manager.listen(ListenFilter::default()).await.unwrap();
+        manager.reserve(rsvp("room 3")).await.unwrap();
+        tx.commit().await.unwrap();
+
+        for resource in ["room 1", "room 3"] {
+            let change = changes.next().await.unwrap().unwrap();
+            assert_eq!(change.reservation.resource_id, resource);
+        }
+    }
 }
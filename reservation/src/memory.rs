@@ -0,0 +1,817 @@
+use crate::{
+    idempotency::Mutation,
+    manager::{filter_cursor, filter_page, query_cursor, DEFAULT_PAGE_SIZE},
+    validator::Validator,
+    window::Window,
+    ListenFilter, ReservationChange, ReservationChangeStream, ReservationError, ReservationId,
+    Rsvp,
+};
+use abi::{
+    to_utc_time, FilterPager, Reservation, ReservationFilter, ReservationMatchMode,
+    ReservationQuery, ReservationSortKey, ReservationStatus, ReservationUpdateType,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::watch;
+
+/// how many of the latest changes are kept by default
+const DEFAULT_CHANGE_RETENTION: usize = 10_000;
+
+/// keep reservations in memory, with the same semantics as the postgres backed
+/// ReservationManager: conflicting reservations on a resource of a tenant are rejected, every
+/// reserve, status change and delete is recorded in the change feed, and idempotency keys are
+/// honored.
+///
+/// Only the latest changes are kept, listening since an older change is rejected.
+///
+/// Clones share the same reservations.
+#[derive(Clone)]
+pub struct MemoryReservationManager {
+    state: Arc<Mutex<State>>,
+    /// id of the last change, listeners wait for it to move
+    last_change: Arc<watch::Sender<i64>>,
+    idempotency_ttl: Duration,
+    change_retention: usize,
+    tenant: String,
+    actor: String,
+}
+
+impl Default for MemoryReservationManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryReservationManager {
+    pub fn new() -> Self {
+        Self {
+            state: Default::default(),
+            last_change: Arc::new(watch::channel(0).0),
+            idempotency_ttl: Duration::hours(24),
+            change_retention: DEFAULT_CHANGE_RETENTION,
+            tenant: String::new(),
+            actor: String::new(),
+        }
+    }
+
+    /// set how long a response is kept for its idempotency key, default to 24 hours.
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    /// set how many of the latest changes are kept for listeners, default to 10000.
+    pub fn with_change_retention(mut self, retention: usize) -> Self {
+        // the last change is always kept, its id numbers the next one
+        self.change_retention = retention.max(1);
+        self
+    }
+
+    /// scope every operation to the tenant, default to the empty tenant. the clone still shares
+    /// the reservations of every tenant.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    /// the tenant the operations are scoped to.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// record the user id as the actor of every change, default to none.
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// the user id recorded as the actor of the changes.
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// run f on the state, notify the listeners if it recorded changes
+    fn mutate<T>(&self, f: impl FnOnce(&mut State) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        state.actor.clone_from(&self.actor);
+        let result = f(&mut state);
+        let excess = state.changes.len().saturating_sub(self.change_retention);
+        state.changes.drain(..excess);
+        let last = state.changes.back().map_or(0, |c| c.id);
+        self.last_change.send_if_modified(|id| {
+            let modified = *id != last;
+            *id = last;
+            modified
+        });
+        result
+    }
+
+    /// run the mutation at most once per idempotency key, see ReservationManager::idempotent.
+    fn idempotent(&self, key: String, mutation: Mutation) -> Result<Reservation, ReservationError> {
+        let ttl = self.idempotency_ttl;
+        // keys are per tenant
+        let key = (self.tenant.clone(), key);
+        let fingerprint = mutation.fingerprint(&self.actor);
+        let run = |state: &mut State| match mutation {
+            Mutation::Reserve(rsvp) => state.insert(&self.tenant, rsvp),
+            Mutation::Confirm(id) => state.confirm(&self.tenant, id),
+            Mutation::Cancel(id) => state.delete(&self.tenant, id),
+        };
+        self.mutate(|state| {
+            if key.1.is_empty() {
+                return run(state);
+            }
+            let now = Utc::now();
+            state
+                .idempotency
+                .retain(|_, (_, _, expires_at)| *expires_at >= now);
+            if let Some((claimed, rsvp, _)) = state.idempotency.get(&key) {
+                if *claimed != fingerprint {
+                    return Err(ReservationError::IdempotencyKeyReused(key.1));
+                }
+                return Ok(rsvp.clone());
+            }
+            // only successful responses are stored
+            let rsvp = run(state)?;
+            state
+                .idempotency
+                .insert(key, (fingerprint, rsvp.clone(), now + ttl));
+            Ok(rsvp)
+        })
+    }
+}
+
+#[async_trait]
+impl Rsvp for MemoryReservationManager {
+    async fn reserve(&self, rsvp: Reservation) -> Result<Reservation, ReservationError> {
+        self.mutate(|state| state.insert(&self.tenant, rsvp))
+    }
+
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: Reservation,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Reserve(rsvp))
+    }
+
+    /// reserve each one on its own, in dry run mode on a copy of the reservations that is
+    /// thrown away.
+    async fn reserve_batch(
+        &self,
+        rsvps: Vec<Reservation>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<Reservation, ReservationError>>, ReservationError> {
+        Ok(self.mutate(|state| {
+            let mut copy;
+            let state = if dry_run {
+                copy = state.clone();
+                &mut copy
+            } else {
+                state
+            };
+            rsvps
+                .into_iter()
+                .map(|rsvp| state.insert(&self.tenant, rsvp))
+                .collect()
+        }))
+    }
+
+    async fn change_status(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        self.mutate(|state| state.confirm(&self.tenant, id))
+    }
+
+    async fn change_status_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Confirm(id))
+    }
+
+    /// update the note, like the database trigger it records no change.
+    async fn update_note(
+        &self,
+        id: ReservationId,
+        note: String,
+    ) -> Result<Reservation, ReservationError> {
+        self.mutate(|state| {
+            let rsvp = state
+                .reservations
+                .get_mut(&id)
+                .filter(|rsvp| rsvp.tenant_id == self.tenant)
+                .ok_or(ReservationError::NotFound)?;
+            rsvp.note = note;
+            Ok(rsvp.clone())
+        })
+    }
+
+    async fn get(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        let state = self.state.lock().unwrap();
+        state
+            .reservations
+            .get(&id)
+            .filter(|rsvp| rsvp.tenant_id == self.tenant)
+            .cloned()
+            .ok_or(ReservationError::NotFound)
+    }
+
+    async fn delete(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        self.mutate(|state| state.delete(&self.tenant, id))
+    }
+
+    async fn delete_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Cancel(id))
+    }
+
+    async fn query(&self, query: ReservationQuery) -> Result<Vec<Reservation>, ReservationError> {
+        let cursor = query_cursor(&query)?;
+        let state = self.state.lock().unwrap();
+        let mut rsvps = state.query(&self.tenant, &query)?;
+
+        let key = |rsvp: &Reservation| match query.sort_by() {
+            ReservationSortKey::Start => rsvp.start.clone().map(|t| to_utc_time(&t)),
+            ReservationSortKey::End => rsvp.end.clone().map(|t| to_utc_time(&t)),
+            ReservationSortKey::Id => None,
+        };
+        // id breaks ties, so pages are stable
+        rsvps.sort_by(|a, b| {
+            let ordering = key(a).cmp(&key(b)).then_with(|| cmp_id(a, b));
+            if query.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        let page = query.page.max(1);
+        let page_size = if query.page_size > 0 {
+            query.page_size
+        } else {
+            DEFAULT_PAGE_SIZE
+        };
+        let skip = match cursor {
+            Some(_) => 0,
+            None => ((page - 1) * page_size) as usize,
+        };
+        let after = |rsvp: &Reservation| match cursor {
+            Some(cursor) if query.desc => parse_id(&rsvp.id) < cursor,
+            Some(cursor) => parse_id(&rsvp.id) > cursor,
+            None => true,
+        };
+        Ok(rsvps
+            .into_iter()
+            .filter(after)
+            .skip(skip)
+            .take(page_size as usize)
+            .collect())
+    }
+
+    async fn filter(
+        &self,
+        filter: ReservationFilter,
+    ) -> Result<(FilterPager, Vec<Reservation>), ReservationError> {
+        let cursor = filter_cursor(&filter)?;
+        let mut rsvps = self
+            .state
+            .lock()
+            .unwrap()
+            .query(&self.tenant, &filter.to_query())?;
+        rsvps.sort_by(|a, b| {
+            let ordering = cmp_id(a, b);
+            if filter.desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        let page_size = if filter.page_size > 0 {
+            filter.page_size
+        } else {
+            DEFAULT_PAGE_SIZE
+        };
+        let total = rsvps.len() as i64;
+        let after = |rsvp: &Reservation| match cursor {
+            Some(cursor) if filter.desc => parse_id(&rsvp.id) < cursor,
+            Some(cursor) => parse_id(&rsvp.id) > cursor,
+            None => true,
+        };
+        // one more to tell if there is a next page
+        let rsvps = rsvps
+            .into_iter()
+            .filter(after)
+            .take(page_size as usize + 1)
+            .collect();
+        Ok(filter_page(&filter, page_size, rsvps, total))
+    }
+
+    async fn listen(
+        &self,
+        filter: ListenFilter,
+    ) -> Result<ReservationChangeStream, ReservationError> {
+        let since = filter.since;
+        // only changes of the tenant
+        let tenant = self.tenant.clone();
+        let filter =
+            move |c: &ReservationChange| c.reservation.tenant_id == tenant && filter.matches(c);
+        // subscribe while holding the lock, so no change falls in between
+        let state = self.state.lock().unwrap();
+        let mut receiver = self.last_change.subscribe();
+        receiver.borrow_and_update();
+        let (cursor, pending) = match since {
+            Some(since) => {
+                let changes = state.changes_after(since)?;
+                let cursor = changes.last().map_or(since, |c| c.id);
+                let pending = changes.into_iter().filter(|c| filter(c)).collect();
+                (cursor, pending)
+            }
+            None => (state.changes.back().map_or(0, |c| c.id), VecDeque::new()),
+        };
+        drop(state);
+
+        let shared = self.state.clone();
+        let listen = Some((receiver, cursor, pending));
+        let changes = stream::unfold(listen, move |listen| {
+            let shared = shared.clone();
+            let filter = filter.clone();
+            async move {
+                let (mut receiver, mut cursor, mut pending) = listen?;
+                loop {
+                    if let Some(change) = pending.pop_front() {
+                        return Some((Ok(change), Some((receiver, cursor, pending))));
+                    }
+                    // the manager is gone, no more changes
+                    receiver.changed().await.ok()?;
+                    // a listener falling behind the kept changes has missed some, it ends
+                    let changes = match shared.lock().unwrap().changes_after(cursor) {
+                        Ok(changes) => changes,
+                        Err(e) => return Some((Err(e), None)),
+                    };
+                    if let Some(last) = changes.last() {
+                        cursor = last.id;
+                    }
+                    pending.extend(changes.into_iter().filter(|c| filter(c)));
+                }
+            }
+        });
+        Ok(Box::pin(changes))
+    }
+
+    async fn cancel_by_filter(
+        &self,
+        query: ReservationQuery,
+        dry_run: bool,
+    ) -> Result<Vec<Reservation>, ReservationError> {
+        if query.get_user_ids().is_empty()
+            && query.get_resource_ids().is_empty()
+            && query.get_statuses().is_empty()
+            && query.start.is_none()
+            && query.end.is_none()
+        {
+            return Err(ReservationError::InvalidFilter(
+                "refuse to cancel all reservations, at least one condition is required".into(),
+            ));
+        }
+        self.mutate(|state| {
+            let rsvps = state.query(&self.tenant, &query)?;
+            if !dry_run {
+                for rsvp in &rsvps {
+                    state.delete(&self.tenant, parse_id(&rsvp.id))?;
+                }
+            }
+            Ok(rsvps)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    reservations: HashMap<ReservationId, Reservation>,
+    /// reservations of every resource, by tenant and resource id
+    resources: HashMap<(String, String), IntervalIndex>,
+    /// the latest changes, in order
+    changes: VecDeque<ReservationChange>,
+    /// request fingerprint, response and expiry of the idempotency keys, by tenant and key
+    idempotency: HashMap<(String, String), (String, Reservation, DateTime<Utc>)>,
+    /// actor of the changes of the running mutation
+    actor: String,
+}
+
+impl State {
+    fn insert(
+        &mut self,
+        tenant: &str,
+        mut rsvp: Reservation,
+    ) -> Result<Reservation, ReservationError> {
+        if rsvp.start.is_none() || rsvp.end.is_none() {
+            return Err(ReservationError::InvalidTimespan);
+        }
+        Window::from_reservation(&rsvp).validate()?;
+        if ReservationStatus::from_i32(rsvp.status).is_none() {
+            return Err(ReservationError::InvalidStatus);
+        }
+
+        rsvp.tenant_id = tenant.to_string();
+        rsvp.booked_by.clone_from(&self.actor);
+        let (start, end) = timespan(&rsvp);
+        let index = self.resources.entry(resource_key(&rsvp)).or_default();
+        if let Some(id) = index.overlapping(start, end) {
+            return Err(ReservationError::Conflict(format!(
+                "{} is reserved by {id} in this period",
+                rsvp.resource_id
+            )));
+        }
+        let id = ReservationId::new_v4();
+        index.insert(start, end, id);
+        rsvp.id = id.to_string();
+        self.reservations.insert(id, rsvp.clone());
+        self.record(ReservationUpdateType::Create, &rsvp);
+        Ok(rsvp)
+    }
+
+    /// change a pending reservation to confirmed, like the database any other is not found
+    fn confirm(
+        &mut self,
+        tenant: &str,
+        id: ReservationId,
+    ) -> Result<Reservation, ReservationError> {
+        let rsvp = self
+            .reservations
+            .get_mut(&id)
+            .filter(|rsvp| rsvp.tenant_id == tenant)
+            .filter(|rsvp| rsvp.status == ReservationStatus::Pending as i32)
+            .ok_or(ReservationError::NotFound)?;
+        rsvp.status = ReservationStatus::Confirmed as i32;
+        let rsvp = rsvp.clone();
+        self.record(ReservationUpdateType::Update, &rsvp);
+        Ok(rsvp)
+    }
+
+    fn delete(&mut self, tenant: &str, id: ReservationId) -> Result<Reservation, ReservationError> {
+        if self
+            .reservations
+            .get(&id)
+            .map(|rsvp| rsvp.tenant_id.as_str())
+            != Some(tenant)
+        {
+            return Err(ReservationError::NotFound);
+        }
+        let rsvp = self.reservations.remove(&id).unwrap();
+        if let Some(index) = self.resources.get_mut(&resource_key(&rsvp)) {
+            index.remove(timespan(&rsvp).0, id);
+        }
+        self.record(ReservationUpdateType::Delete, &rsvp);
+        Ok(rsvp)
+    }
+
+    /// record a change, keeping only what the database keeps for a deleted reservation
+    fn record(&mut self, op: ReservationUpdateType, rsvp: &Reservation) {
+        let id = self.changes.back().map_or(0, |c| c.id) + 1;
+        self.changes.push_back(ReservationChange {
+            id,
+            op,
+            reservation: Reservation {
+                id: rsvp.id.clone(),
+                user_id: rsvp.user_id.clone(),
+                resource_id: rsvp.resource_id.clone(),
+                status: rsvp.status,
+                tenant_id: rsvp.tenant_id.clone(),
+                ..Default::default()
+            },
+            actor: self.actor.clone(),
+            on_behalf_of: !self.actor.is_empty() && self.actor != rsvp.user_id,
+        });
+    }
+
+    /// changes after the cursor with the current state of their reservations, the cursor
+    /// should not be before the kept changes
+    fn changes_after(&self, cursor: i64) -> Result<Vec<ReservationChange>, ReservationError> {
+        // change ids start from 1 without gaps
+        let first = self.changes.front().map_or(1, |c| c.id);
+        if cursor < first - 1 {
+            return Err(ReservationError::InvalidFilter(format!(
+                "changes before {first} are no longer kept"
+            )));
+        }
+        let start = ((cursor - first + 1) as usize).min(self.changes.len());
+        Ok(self
+            .changes
+            .range(start..)
+            .map(|change| {
+                let current = self.reservations.get(&parse_id(&change.reservation.id));
+                ReservationChange {
+                    reservation: current
+                        .cloned()
+                        .unwrap_or_else(|| change.reservation.clone()),
+                    ..change.clone()
+                }
+            })
+            .collect())
+    }
+
+    /// reservations of the tenant matching the conditions of the query, unsorted
+    fn query(
+        &self,
+        tenant: &str,
+        query: &ReservationQuery,
+    ) -> Result<Vec<Reservation>, ReservationError> {
+        let start = query.start.as_ref().map(to_utc_time);
+        let end = query.end.as_ref().map(to_utc_time);
+        if let (Some(start), Some(end)) = (start, end) {
+            Window::new(start, end).validate()?;
+        }
+        let user_ids = query.get_user_ids();
+        let resource_ids = query.get_resource_ids();
+        let statuses = query.get_statuses();
+        let after = |t: DateTime<Utc>| start.is_none_or(|start| t >= start);
+        let before = |t: DateTime<Utc>| end.is_none_or(|end| t <= end);
+        let in_time = |rsvp: &Reservation| {
+            let (s, e) = timespan(rsvp);
+            match query.match_mode() {
+                ReservationMatchMode::Contains => after(s) && before(e),
+                ReservationMatchMode::Overlaps => {
+                    // an empty reservation overlaps nothing
+                    s < e && start.is_none_or(|start| e > start) && end.is_none_or(|end| s < end)
+                }
+                ReservationMatchMode::StartsWithin => after(s) && end.is_none_or(|end| s < end),
+            }
+        };
+        Ok(self
+            .reservations
+            .values()
+            .filter(|rsvp| rsvp.tenant_id == tenant)
+            .filter(|rsvp| (start.is_none() && end.is_none()) || in_time(rsvp))
+            .filter(|rsvp| user_ids.is_empty() || user_ids.contains(&rsvp.user_id))
+            .filter(|rsvp| resource_ids.is_empty() || resource_ids.contains(&rsvp.resource_id))
+            .filter(|rsvp| statuses.is_empty() || statuses.contains(&rsvp.get_status()))
+            .cloned()
+            .collect())
+    }
+}
+
+/// reservations of a resource by start time.
+///
+/// Reservations of a resource never overlap, so only the last one starting before the end of
+/// a new reservation can overlap it.
+#[derive(Debug, Clone, Default)]
+struct IntervalIndex {
+    ends: BTreeMap<(DateTime<Utc>, ReservationId), DateTime<Utc>>,
+}
+
+impl IntervalIndex {
+    /// id of the reservation overlapping the open interval (start, end)
+    fn overlapping(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<ReservationId> {
+        // like an empty range in postgres, an empty interval overlaps nothing
+        if start >= end {
+            return None;
+        }
+        let (&(_, id), &last_end) = self.ends.range(..(end, ReservationId::nil())).next_back()?;
+        (last_end > start).then_some(id)
+    }
+
+    fn insert(&mut self, start: DateTime<Utc>, end: DateTime<Utc>, id: ReservationId) {
+        if start < end {
+            self.ends.insert((start, id), end);
+        }
+    }
+
+    fn remove(&mut self, start: DateTime<Utc>, id: ReservationId) {
+        self.ends.remove(&(start, id));
+    }
+}
+
+/// start and end of a stored reservation, both are checked on insert
+fn timespan(rsvp: &Reservation) -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        to_utc_time(rsvp.start.as_ref().unwrap()),
+        to_utc_time(rsvp.end.as_ref().unwrap()),
+    )
+}
+
+/// key of the interval index of a reservation's resource
+fn resource_key(rsvp: &Reservation) -> (String, String) {
+    (rsvp.tenant_id.clone(), rsvp.resource_id.clone())
+}
+
+/// ids are generated here, so they always parse
+fn parse_id(id: &str) -> ReservationId {
+    id.parse().unwrap_or_default()
+}
+
+fn cmp_id(a: &Reservation, b: &Reservation) -> Ordering {
+    parse_id(&a.id).cmp(&parse_id(&b.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::{to_timestamp, ReservationQueryBuilder};
+    use futures::StreamExt;
+
+    fn reservation(user_id: &str, resource_id: &str, start: &str, end: &str) -> Reservation {
+        Reservation {
+            user_id: user_id.to_string(),
+            status: ReservationStatus::Pending as i32,
+            resource_id: resource_id.to_string(),
+            start: Some(to_timestamp(start.parse().unwrap())),
+            end: Some(to_timestamp(end.parse().unwrap())),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn conflicts_should_be_detected_per_resource() {
+        let manager = MemoryReservationManager::new();
+        let first = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        for (start, end) in [
+            ("2022-11-18T03:00:00Z", "2022-11-18T05:00:00Z"),
+            ("2022-11-18T05:00:00Z", "2022-11-18T07:00:00Z"),
+            ("2022-11-18T04:30:00Z", "2022-11-18T05:00:00Z"),
+            ("2022-11-18T01:00:00Z", "2022-11-18T09:00:00Z"),
+        ] {
+            let err = manager
+                .reserve(reservation("Syuu", "room 1", start, end))
+                .await
+                .unwrap_err();
+            assert!(err.is_conflict());
+            assert!(err.to_string().contains(&first.id));
+        }
+        // touching is fine, so is another resource
+        for (resource, start, end) in [
+            ("room 1", "2022-11-18T06:00:00Z", "2022-11-18T07:00:00Z"),
+            ("room 1", "2022-11-18T02:00:00Z", "2022-11-18T04:00:00Z"),
+            ("room 2", "2022-11-18T04:00:00Z", "2022-11-18T06:00:00Z"),
+        ] {
+            manager
+                .reserve(reservation("Syuu", resource, start, end))
+                .await
+                .unwrap();
+        }
+
+        // the period is free again after the delete
+        manager.delete(first.id.parse().unwrap()).await.unwrap();
+        manager
+            .reserve(reservation(
+                "Syuu",
+                "room 1",
+                "2022-11-18T04:30:00Z",
+                "2022-11-18T05:00:00Z",
+            ))
+            .await
+            .unwrap();
+
+        let err = manager
+            .reserve(reservation(
+                "Syuu",
+                "room 3",
+                "2022-11-18T05:00:00Z",
+                "2022-11-18T04:00:00Z",
+            ))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReservationError::InvalidTimespan));
+    }
+
+    #[tokio::test]
+    async fn errors_should_match_the_database() {
+        let manager = MemoryReservationManager::new();
+        let rsvp = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        let id = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        // only pending reservations can be confirmed
+        assert!(manager.change_status(id).await.unwrap_err().is_not_found());
+        assert!(manager
+            .get(ReservationId::nil())
+            .await
+            .unwrap_err()
+            .is_not_found());
+
+        let key = "retry".to_string();
+        let first = manager.delete_idempotent(key.clone(), id).await.unwrap();
+        let again = manager.delete_idempotent(key.clone(), id).await.unwrap();
+        assert_eq!(first, again);
+        let err = manager.change_status_idempotent(key, id).await.unwrap_err();
+        assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+
+        let query = ReservationQueryBuilder::default().build().unwrap();
+        let err = manager.cancel_by_filter(query, true).await.unwrap_err();
+        assert!(matches!(err, ReservationError::InvalidFilter(_)));
+    }
+
+    #[tokio::test]
+    async fn listen_should_stream_changes() {
+        let manager = MemoryReservationManager::new();
+        let rsvp = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        let mut live = manager.listen(ListenFilter::default()).await.unwrap();
+        let filter = ListenFilter {
+            since: Some(0),
+            ops: vec![ReservationUpdateType::Create, ReservationUpdateType::Delete],
+            ..Default::default()
+        };
+        let mut replay = manager.listen(filter).await.unwrap();
+
+        let id = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        manager.update_note(id, "note".into()).await.unwrap();
+        manager.delete(id).await.unwrap();
+
+        let change = live.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (2, ReservationUpdateType::Update));
+        let change = live.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (3, ReservationUpdateType::Delete));
+        assert_eq!(change.reservation.user_id, "M4n5ter");
+        assert_eq!(
+            change.reservation.status,
+            ReservationStatus::Confirmed as i32
+        );
+        assert!(change.reservation.start.is_none());
+
+        let change = replay.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (1, ReservationUpdateType::Create));
+        let change = replay.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (3, ReservationUpdateType::Delete));
+    }
+
+    #[tokio::test]
+    async fn listen_should_reject_since_before_kept_changes() {
+        let manager = MemoryReservationManager::new().with_change_retention(2);
+        let rsvp = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        let mut live = manager.listen(ListenFilter::default()).await.unwrap();
+        let id = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        manager.delete(id).await.unwrap();
+
+        let since = |since| ListenFilter {
+            since: Some(since),
+            ..Default::default()
+        };
+        let err = manager.listen(since(0)).await.err().unwrap();
+        assert!(matches!(err, ReservationError::InvalidFilter(_)));
+        let mut replay = manager.listen(since(1)).await.unwrap();
+        for id in [2, 3] {
+            assert_eq!(replay.next().await.unwrap().unwrap().id, id);
+        }
+
+        for id in [2, 3] {
+            assert_eq!(live.next().await.unwrap().unwrap().id, id);
+        }
+        // a listener left behind the kept changes fails instead of skipping some
+        for resource in ["room 2", "room 3", "room 4"] {
+            manager
+                .reserve(reservation(
+                    "M4n5ter",
+                    resource,
+                    "2022-11-18T04:00:00Z",
+                    "2022-11-18T06:00:00Z",
+                ))
+                .await
+                .unwrap();
+        }
+        let err = live.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, ReservationError::InvalidFilter(_)));
+        assert!(live.next().await.is_none());
+    }
+}
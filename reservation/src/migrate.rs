@@ -0,0 +1,146 @@
+//! migrations of the rsvp schema, embedded in the binary.
+
+use crate::{ReservationError, ReservationManager};
+use serde::Deserialize;
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    postgres::PgPoolOptions,
+    Acquire,
+};
+use std::time::Duration;
+
+/// the `migrations` directory at build time
+static MIGRATOR: Migrator = sqlx::migrate!("../migrations");
+
+/// a migration and whether it was applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    /// the applied migration differs from the embedded one
+    pub modified: bool,
+}
+
+/// database config of a ReservationManager.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DbConfig {
+    /// env: DATABASE_URL
+    pub url: String,
+    /// env: RSVP_DB_MAX_CONNECTIONS
+    pub max_connections: u32,
+    /// env: RSVP_DB_MIN_CONNECTIONS
+    pub min_connections: u32,
+    /// seconds to wait for a connection, env: RSVP_DB_CONNECT_TIMEOUT
+    pub connect_timeout: u64,
+    /// seconds an idle connection is kept, 0 keeps it forever
+    pub idle_timeout: u64,
+    /// apply pending migrations on connecting, env: RSVP_DB_MIGRATE
+    pub migrate: bool,
+}
+
+impl Default for DbConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            min_connections: 0,
+            connect_timeout: 30,
+            idle_timeout: 600,
+            migrate: true,
+        }
+    }
+}
+
+impl DbConfig {
+    /// pool options of the database connections.
+    pub fn pool_options(&self) -> PgPoolOptions {
+        let idle_timeout = match self.idle_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(Duration::from_secs(self.connect_timeout))
+            .idle_timeout(idle_timeout)
+    }
+}
+
+impl ReservationManager {
+    /// connect with the config, and migrate the schema if `config.migrate` is set.
+    pub async fn from_config(config: &DbConfig) -> Result<Self, ReservationError> {
+        let pool = config.pool_options().connect(&config.url).await?;
+        let manager = Self::new(pool);
+        if config.migrate {
+            manager.migrate().await?;
+        }
+        Ok(manager)
+    }
+
+    /// apply the pending migrations.
+    pub async fn migrate(&self) -> Result<(), ReservationError> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// revert the applied migrations newer than `target`, 0 reverts all of them.
+    pub async fn migrate_down(&self, target: i64) -> Result<(), ReservationError> {
+        MIGRATOR.undo(&self.pool, target).await?;
+        Ok(())
+    }
+
+    /// every embedded migration in order, and whether it was applied.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>, ReservationError> {
+        let mut conn = self.pool.acquire().await?;
+        let conn = conn.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied = conn.list_applied_migrations().await?;
+
+        Ok(MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+            .map(|m| {
+                let found = applied.iter().find(|a| a.version == m.version);
+                MigrationStatus {
+                    version: m.version,
+                    description: m.description.to_string(),
+                    applied: found.is_some(),
+                    modified: found.is_some_and(|a| a.checksum != m.checksum),
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn migrations_should_go_down_and_up() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let status = manager.migration_status().await.unwrap();
+        assert!(status.len() > 1);
+        assert!(status.iter().all(|s| s.applied && !s.modified));
+
+        // revert the latest one
+        let target = status[status.len() - 2].version;
+        manager.migrate_down(target).await.unwrap();
+        let reverted = manager.migration_status().await.unwrap();
+        assert!(!reverted.last().unwrap().applied);
+        assert!(reverted[..reverted.len() - 1].iter().all(|s| s.applied));
+
+        manager.migrate().await.unwrap();
+        assert_eq!(manager.migration_status().await.unwrap(), status);
+
+        // nothing left after reverting all, then everything is back
+        manager.migrate_down(0).await.unwrap();
+        let status = manager.migration_status().await.unwrap();
+        assert!(status.iter().all(|s| !s.applied));
+        manager.migrate().await.unwrap();
+        let status = manager.migration_status().await.unwrap();
+        assert!(status.iter().all(|s| s.applied));
+    }
+}
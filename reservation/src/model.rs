@@ -0,0 +1,218 @@
+//! random sequences of reserve, cancel and move operations, checked against a reference model of
+//! the conflict rules.
+
+use crate::{MemoryReservationManager, ReservationId, ReservationManager, Rsvp};
+use abi::{to_timestamp, to_utc_time, Reservation, ReservationQuery, ReservationStatus};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use proptest::{
+    collection::vec,
+    prelude::*,
+    test_runner::{Config, TestRunner},
+};
+use tokio::runtime::Handle;
+
+/// resources the operations pick from, few enough to make conflicts common
+const RESOURCES: u32 = 3;
+/// reservations start at one of these half hour slots
+const SLOTS: u32 = 48;
+/// and last up to this many slots
+const MAX_LENGTH: u32 = 8;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Reserve {
+        resource: u32,
+        start: u32,
+        length: u32,
+    },
+    /// cancel an active reservation, picked by index modulo their number
+    Cancel { index: usize },
+    /// move an active reservation to another period of its resource
+    Move {
+        index: usize,
+        start: u32,
+        length: u32,
+    },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (0..RESOURCES, 0..SLOTS, 1..=MAX_LENGTH)
+            .prop_map(|(resource, start, length)| Op::Reserve { resource, start, length }),
+        1 => any::<usize>().prop_map(|index| Op::Cancel { index }),
+        2 => (any::<usize>(), 0..SLOTS, 1..=MAX_LENGTH)
+            .prop_map(|(index, start, length)| Op::Move { index, start, length }),
+    ]
+}
+
+/// an active reservation of the model, start and end are slots
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Active {
+    id: String,
+    resource: u32,
+    start: u32,
+    end: u32,
+}
+
+/// the reference model: the active reservations, a new one conflicts if it overlaps any of them
+#[derive(Debug, Default)]
+struct Model {
+    active: Vec<Active>,
+}
+
+impl Model {
+    /// whether (start, end) overlaps an active reservation of the resource other than `except`
+    fn conflicts(&self, resource: u32, start: u32, end: u32, except: Option<&str>) -> bool {
+        self.active.iter().any(|a| {
+            a.resource == resource
+                && Some(a.id.as_str()) != except
+                && a.start < end
+                && start < a.end
+        })
+    }
+}
+
+/// apply the operations to the backend and the model, panic on the first difference
+async fn run<R: Rsvp + ?Sized>(rsvp: &R, ops: Vec<Op>) {
+    // resources unique to this run, so runs can share a store
+    let tag = ReservationId::new_v4().simple().to_string();
+    let resource_id = |resource: u32| format!("room {resource} {tag}");
+    let reservation = |resource: u32, start: u32, end: u32| Reservation {
+        user_id: "proptest".to_string(),
+        status: ReservationStatus::Pending as i32,
+        resource_id: resource_id(resource),
+        start: Some(to_timestamp(slot(start))),
+        end: Some(to_timestamp(slot(end))),
+        ..Default::default()
+    };
+
+    let mut model = Model::default();
+    for op in ops {
+        match op {
+            Op::Reserve {
+                resource,
+                start,
+                length,
+            } => {
+                let end = start + length;
+                let conflicts = model.conflicts(resource, start, end, None);
+                match rsvp.reserve(reservation(resource, start, end)).await {
+                    Ok(reserved) => {
+                        assert!(!conflicts, "{op:?} should conflict");
+                        model.active.push(Active {
+                            id: reserved.id,
+                            resource,
+                            start,
+                            end,
+                        });
+                    }
+                    Err(e) => assert!(conflicts && e.is_conflict(), "{op:?} failed: {e}"),
+                }
+            }
+            Op::Cancel { index } => {
+                if model.active.is_empty() {
+                    continue;
+                }
+                let active = model.active.remove(index % model.active.len());
+                rsvp.delete(active.id.parse().unwrap()).await.unwrap();
+            }
+            Op::Move {
+                index,
+                start,
+                length,
+            } => {
+                if model.active.is_empty() {
+                    continue;
+                }
+                let index = index % model.active.len();
+                let active = model.active[index].clone();
+                let end = start + length;
+                let conflicts = model.conflicts(active.resource, start, end, Some(&active.id));
+
+                // there is no move, cancel and reserve again, back to where it was on conflict
+                rsvp.delete(active.id.parse().unwrap()).await.unwrap();
+                let (start, end) =
+                    match rsvp.reserve(reservation(active.resource, start, end)).await {
+                        Ok(moved) => {
+                            assert!(!conflicts, "{op:?} should conflict");
+                            model.active[index].id = moved.id;
+                            (start, end)
+                        }
+                        Err(e) => {
+                            assert!(conflicts && e.is_conflict(), "{op:?} failed: {e}");
+                            let restored = reservation(active.resource, active.start, active.end);
+                            model.active[index].id = rsvp.reserve(restored).await.unwrap().id;
+                            (active.start, active.end)
+                        }
+                    };
+                model.active[index].start = start;
+                model.active[index].end = end;
+            }
+        }
+    }
+
+    // the backend holds exactly the active reservations of the model, none overlapping
+    let query = ReservationQuery {
+        resource_ids: (0..RESOURCES).map(resource_id).collect(),
+        page_size: 1000,
+        ..Default::default()
+    };
+    let rsvps = rsvp.query(query).await.unwrap();
+    let mut stored: Vec<_> = rsvps.iter().map(|r| r.id.clone()).collect();
+    let mut expected: Vec<_> = model.active.iter().map(|a| a.id.clone()).collect();
+    stored.sort();
+    expected.sort();
+    assert_eq!(stored, expected);
+    for (i, a) in rsvps.iter().enumerate() {
+        for b in &rsvps[i + 1..] {
+            let (a_start, a_end) = timespan(a);
+            let (b_start, b_end) = timespan(b);
+            let overlaps = a_start < b_end && b_start < a_end;
+            assert!(
+                a.resource_id != b.resource_id || !overlaps,
+                "{a:?} overlaps {b:?}"
+            );
+        }
+    }
+}
+
+/// check random operation sequences, shrinking a failing one to a minimal sequence.
+///
+/// Must not be called from within the runtime of the handle.
+fn check<R: Rsvp + ?Sized>(rsvp: &R, handle: &Handle, cases: u32) {
+    let mut runner = TestRunner::new(Config::with_cases(cases));
+    let result = runner.run(&vec(op(), 1..40), |ops| {
+        handle.block_on(run(rsvp, ops));
+        Ok(())
+    });
+    if let Err(e) = result {
+        panic!("{e}");
+    }
+}
+
+fn timespan(rsvp: &Reservation) -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        to_utc_time(rsvp.start.as_ref().unwrap()),
+        to_utc_time(rsvp.end.as_ref().unwrap()),
+    )
+}
+
+fn slot(n: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2022, 11, 18, 0, 0, 0).unwrap() + Duration::minutes(30 * n as i64)
+}
+
+#[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+async fn postgres_conflicts_should_match_the_model() {
+    let manager = ReservationManager::new(migrated_pool.clone());
+    let handle = Handle::current();
+    // the test runs on a current thread runtime, keep driving it while the cases block
+    tokio::task::spawn_blocking(move || check(&manager, &handle, 64))
+        .await
+        .unwrap();
+}
+
+#[test]
+fn memory_conflicts_should_match_the_model() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    check(&MemoryReservationManager::new(), runtime.handle(), 256);
+}
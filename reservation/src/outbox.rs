@@ -0,0 +1,385 @@
+use crate::{
+    changes::{fetch_batch, Cursor, CHANNEL, HOLD_BACK_INTERVAL},
+    ReservationChange, ReservationError,
+};
+use async_trait::async_trait;
+use sqlx::{postgres::PgListener, PgPool, Row};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+
+/// changes are also polled in this interval, in case a notification is lost
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// how many changes are published in one batch by default
+const DEFAULT_BATCH_SIZE: i64 = 100;
+
+/// destination of published reservation changes.
+///
+/// A change may be published more than once, e.g. if the publisher crashes before recording the
+/// delivery, so consumers should dedupe by the change id.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// publish a change, only return after the destination has accepted it.
+    async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError>;
+}
+
+/// publish the changes in rsvp.reservation_changes to a sink in order, at least once.
+///
+/// The position of the last delivered change is kept in rsvp.outbox_offsets by the publisher
+/// name, so a restarted publisher continues where it stopped. Publishers with different names
+/// deliver independently, publishers with the same name may deliver a change more than once.
+pub struct Publisher<S> {
+    pool: PgPool,
+    name: String,
+    sink: S,
+    batch_size: i64,
+}
+
+impl<S: Sink> Publisher<S> {
+    pub fn new(pool: PgPool, name: impl Into<String>, sink: S) -> Self {
+        Self {
+            pool,
+            name: name.into(),
+            sink,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// set how many changes are published in one batch, values below 1 mean 1.
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// publish one batch of undelivered changes, return how many were delivered.
+    ///
+    /// If the sink fails, the changes delivered before it are still recorded.
+    pub async fn publish_batch(&self) -> Result<usize, ReservationError> {
+        self.deliver().await.map(|(count, _)| count)
+    }
+
+    /// publish all undelivered changes, return how many were delivered.
+    ///
+    /// Changes committed after an older transaction that is still running wait for it.
+    pub async fn publish_pending(&self) -> Result<usize, ReservationError> {
+        let mut total = 0;
+        loop {
+            let (count, held_back) = self.deliver().await?;
+            total += count;
+            if held_back {
+                tokio::time::sleep(HOLD_BACK_INTERVAL).await;
+            } else if count < self.batch_size as usize {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// keep publishing changes as they are recorded, only return on error.
+    pub async fn run(&self) -> Result<(), ReservationError> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANNEL).await?;
+        loop {
+            let (count, held_back) = self.deliver().await?;
+            if held_back {
+                // the older transaction ends without a notification if it records no change
+                tokio::time::sleep(HOLD_BACK_INTERVAL).await;
+                continue;
+            }
+            if count == self.batch_size as usize {
+                continue;
+            }
+            // a timeout means no notification, publish anyway
+            if let Ok(Err(e)) = tokio::time::timeout(POLL_INTERVAL, listener.recv()).await {
+                return Err(e.into());
+            }
+        }
+    }
+
+    /// deliver one batch, return how many were delivered and whether more are held back.
+    ///
+    /// The sink is called outside of any transaction, the offset only moves forward after it.
+    async fn deliver(&self) -> Result<(usize, bool), ReservationError> {
+        sqlx::query(
+            "INSERT INTO rsvp.outbox_offsets (name) VALUES ($1) ON CONFLICT (name) DO NOTHING",
+        )
+        .bind(&self.name)
+        .execute(&self.pool)
+        .await?;
+        let row = sqlx::query("SELECT last_txid, last_id FROM rsvp.outbox_offsets WHERE name = $1")
+            .bind(&self.name)
+            .fetch_one(&self.pool)
+            .await?;
+        let cursor = Cursor {
+            txid: row.get("last_txid"),
+            id: row.get("last_id"),
+        };
+
+        let batch = fetch_batch(&self.pool, None, cursor, Some(self.batch_size)).await?;
+        let mut delivered = None;
+        let mut count = 0;
+        let mut result = Ok(());
+        for (position, change) in &batch.changes {
+            if let Err(e) = self.sink.publish(change).await {
+                result = Err(e);
+                break;
+            }
+            delivered = Some(*position);
+            count += 1;
+        }
+
+        if let Some(delivered) = delivered {
+            sqlx::query(
+                "UPDATE rsvp.outbox_offsets SET last_txid = $2, last_id = $3, updated_at = now() WHERE name = $1 AND (last_txid, last_id) < ($2, $3)",
+            )
+            .bind(&self.name)
+            .bind(delivered.txid)
+            .bind(delivered.id)
+            .execute(&self.pool)
+            .await?;
+        }
+        result.map(|_| (count, batch.held_back))
+    }
+}
+
+/// keep published changes in memory, for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    changes: Arc<Mutex<Vec<ReservationChange>>>,
+}
+
+impl MemorySink {
+    pub fn changes(&self) -> Vec<ReservationChange> {
+        self.changes.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Sink for MemorySink {
+    async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError> {
+        self.changes.lock().unwrap().push(change.clone());
+        Ok(())
+    }
+}
+
+/// append published changes to a file as json lines.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError> {
+        let mut line = serde_json::to_vec(change).map_err(sink_error)?;
+        line.push(b'\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(sink_error)?;
+        file.write_all(&line).await.map_err(sink_error)?;
+        file.sync_data().await.map_err(sink_error)
+    }
+}
+
+/// publish changes to a NATS JetStream subject, `<prefix>.<op>`, as json.
+#[cfg(feature = "nats")]
+pub struct NatsSink {
+    context: async_nats::jetstream::Context,
+    prefix: String,
+}
+
+#[cfg(feature = "nats")]
+impl NatsSink {
+    /// connect to the server, e.g. `nats://127.0.0.1:4222`. The stream for the subjects must
+    /// exist.
+    pub async fn connect(url: &str, prefix: impl Into<String>) -> Result<Self, ReservationError> {
+        let client = async_nats::connect(url).await.map_err(sink_error)?;
+        Ok(Self {
+            context: async_nats::jetstream::new(client),
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl Sink for NatsSink {
+    async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError> {
+        let subject = format!("{}.{}", self.prefix, change.op);
+        let payload = serde_json::to_vec(change).map_err(sink_error)?;
+        // wait for the ack of the stream, so the change is persisted before it's marked delivered
+        self.context
+            .publish(subject, payload.into())
+            .await
+            .map_err(sink_error)?
+            .await
+            .map_err(sink_error)?;
+        Ok(())
+    }
+}
+
+fn sink_error(e: impl std::fmt::Display) -> ReservationError {
+    ReservationError::SinkError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manager::insert_reservation, ReservationManager, Rsvp};
+    use abi::{ReservationStatus, ReservationUpdateType};
+
+    /// fail after delivering `ok` changes
+    struct FlakySink {
+        inner: MemorySink,
+        ok: usize,
+    }
+
+    #[async_trait]
+    impl Sink for FlakySink {
+        async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError> {
+            if self.inner.changes().len() >= self.ok {
+                return Err(ReservationError::SinkError("broker is down".to_string()));
+            }
+            self.inner.publish(change).await
+        }
+    }
+
+    async fn make_changes(manager: &ReservationManager) {
+        for (i, resource) in ["room 1", "room 2", "room 3"].iter().enumerate() {
+            let rsvp = manager
+                .reserve(abi::Reservation {
+                    user_id: format!("user {i}"),
+                    status: ReservationStatus::Pending as i32,
+                    resource_id: resource.to_string(),
+                    start: Some("2022-11-18T04:00:00Z".parse().unwrap()),
+                    end: Some("2022-11-18T06:00:00Z".parse().unwrap()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            manager
+                .change_status(rsvp.id.parse().unwrap())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn publisher_should_deliver_in_order_once_recorded() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        make_changes(&manager).await;
+
+        let sink = MemorySink::default();
+        let publisher =
+            Publisher::new(migrated_pool.clone(), "test", sink.clone()).with_batch_size(4);
+        assert_eq!(publisher.publish_pending().await.unwrap(), 6);
+        let changes = sink.changes();
+        let ids: Vec<_> = changes.iter().map(|c| c.id).collect();
+        assert_eq!(ids, (1..=6).collect::<Vec<_>>());
+        assert_eq!(changes[0].op, ReservationUpdateType::Create);
+        assert_eq!(changes[1].op, ReservationUpdateType::Update);
+
+        // a restarted publisher continues after the delivered ones
+        let publisher = Publisher::new(migrated_pool.clone(), "test", sink.clone());
+        assert_eq!(publisher.publish_pending().await.unwrap(), 0);
+        manager
+            .delete(changes[0].reservation.id.parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(publisher.publish_pending().await.unwrap(), 1);
+        assert_eq!(sink.changes()[6].op, ReservationUpdateType::Delete);
+
+        // another publisher delivers independently
+        let other = MemorySink::default();
+        let publisher = Publisher::new(migrated_pool.clone(), "other", other.clone());
+        assert_eq!(publisher.publish_pending().await.unwrap(), 7);
+
+        // a batch size below 1 still makes progress
+        let publisher =
+            Publisher::new(migrated_pool.clone(), "zero", MemorySink::default()).with_batch_size(0);
+        assert_eq!(publisher.publish_pending().await.unwrap(), 7);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn publisher_should_retry_after_sink_failure() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        make_changes(&manager).await;
+
+        let flaky = FlakySink {
+            inner: MemorySink::default(),
+            ok: 2,
+        };
+        let publisher = Publisher::new(migrated_pool.clone(), "test", flaky);
+        let err = publisher.publish_pending().await.unwrap_err();
+        assert!(matches!(err, ReservationError::SinkError(_)));
+
+        // the two delivered changes are not published again
+        let sink = MemorySink::default();
+        let publisher = Publisher::new(migrated_pool.clone(), "test", sink.clone());
+        assert_eq!(publisher.publish_pending().await.unwrap(), 4);
+        assert_eq!(sink.changes()[0].id, 3);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn publisher_should_wait_for_late_commits() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let rsvp = |resource: &str| abi::Reservation {
+            user_id: "user".to_string(),
+            resource_id: resource.to_string(),
+            start: Some("2022-11-18T04:00:00Z".parse().unwrap()),
+            end: Some("2022-11-18T06:00:00Z".parse().unwrap()),
+            ..Default::default()
+        };
+        // the first change commits after the second one
+        let mut tx = migrated_pool.begin().await.unwrap();
+        insert_reservation(&mut tx, "", rsvp("room 1"))
+            .await
+            .unwrap();
+        manager.reserve(rsvp("room 2")).await.unwrap();
+
+        let sink = MemorySink::default();
+        let publisher = Publisher::new(migrated_pool.clone(), "test", sink.clone());
+        assert_eq!(publisher.publish_batch().await.unwrap(), 0);
+
+        tx.commit().await.unwrap();
+        assert_eq!(publisher.publish_pending().await.unwrap(), 2);
+        let resources: Vec<_> = sink
+            .changes()
+            .into_iter()
+            .map(|c| c.reservation.resource_id)
+            .collect();
+        assert_eq!(resources, ["room 1", "room 2"]);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn file_sink_should_append_json_lines() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        make_changes(&manager).await;
+
+        let path = std::env::temp_dir().join(format!("outbox-{}.jsonl", std::process::id()));
+        let publisher = Publisher::new(migrated_pool.clone(), "file", FileSink::new(&path));
+        publisher.publish_pending().await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0]["id"], 1);
+        assert_eq!(lines[0]["op"], "create");
+        assert_eq!(lines[1]["reservation"]["status"], "confirmed");
+    }
+}
@@ -0,0 +1,926 @@
+//! reservations stored in SQLite, for deployments without Postgres.
+//!
+//! The schema in `sqlite-migrations` emulates the postgres one with triggers: overlapping
+//! reservations on a resource of a tenant are rejected as `reservations_conflict`, and every
+//! reserve, status change and delete is recorded in `reservation_changes`.
+
+use crate::idempotency::Mutation;
+use crate::{
+    manager::{filter_cursor, filter_page, query_cursor, DEFAULT_PAGE_SIZE},
+    validator::Validator,
+    window::Window,
+    ListenFilter, ReservationChange, ReservationChangeStream, ReservationError, ReservationId,
+    Rsvp,
+};
+use abi::{
+    to_utc_time, FilterPager, Reservation, ReservationFilter, ReservationMatchMode,
+    ReservationQuery, ReservationSortKey, ReservationStatus, ReservationUpdateType,
+};
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use futures::stream;
+use prost::Message;
+use prost_types::Timestamp;
+use sqlx::{
+    sqlite::SqliteRow, Acquire, QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool,
+    Transaction,
+};
+use std::{collections::VecDeque, sync::Arc};
+use tokio::sync::watch;
+
+/// changes made by other processes are polled in this interval
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+/// message of the trigger emulating the exclusion constraint
+const CONFLICT: &str = "reservations_conflict";
+
+/// an `Rsvp` backed by SQLite, with the same semantics as the postgres backed ReservationManager.
+#[derive(Clone)]
+pub struct SqliteReservationManager {
+    pool: SqlitePool,
+    idempotency_ttl: Duration,
+    tenant: String,
+    actor: String,
+    /// wakes up the listeners of this process after a change
+    notify: Arc<watch::Sender<()>>,
+}
+
+impl SqliteReservationManager {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            idempotency_ttl: Duration::hours(24),
+            tenant: String::new(),
+            actor: String::new(),
+            notify: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    /// set how long a response is kept for its idempotency key, default to 24 hours.
+    pub fn with_idempotency_ttl(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = ttl;
+        self
+    }
+
+    /// scope every operation to the tenant, default to the empty tenant.
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    /// the tenant the operations are scoped to.
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// record the user id as the actor of every change, default to none.
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// the user id recorded as the actor of the changes.
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// create or upgrade the schema.
+    pub async fn migrate(&self) -> Result<(), ReservationError> {
+        sqlx::migrate!("../sqlite-migrations")
+            .run(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// delete responses whose idempotency key has expired, return the number of deleted keys.
+    pub async fn purge_idempotency_keys(&self) -> Result<u64, ReservationError> {
+        let deleted = sqlx::query("DELETE FROM idempotency_keys WHERE expires_at < ?")
+            .bind(Utc::now().timestamp_micros())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(deleted)
+    }
+
+    /// begin a write transaction, the actor is written first for the change triggers, which
+    /// also takes the database lock until the transaction ends.
+    async fn begin(&self) -> Result<Transaction<'static, Sqlite>, ReservationError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE change_actor SET actor = ?")
+            .bind(&self.actor)
+            .execute(&mut tx)
+            .await?;
+        Ok(tx)
+    }
+
+    fn notify(&self) {
+        self.notify.send_modify(|_| {});
+    }
+
+    /// run the mutation at most once per idempotency key, see ReservationManager::idempotent.
+    async fn idempotent(
+        &self,
+        key: String,
+        mutation: Mutation,
+    ) -> Result<Reservation, ReservationError> {
+        let mut tx = self.begin().await?;
+        if key.is_empty() {
+            let rsvp = mutation.run(&mut tx, &self.tenant).await?;
+            tx.commit().await?;
+            self.notify();
+            return Ok(rsvp);
+        }
+
+        // a write first, so the transaction holds the write lock until it ends
+        let now = Utc::now();
+        sqlx::query(
+            "DELETE FROM idempotency_keys WHERE tenant_id = ? AND key = ? AND expires_at < ?",
+        )
+        .bind(&self.tenant)
+        .bind(&key)
+        .bind(now.timestamp_micros())
+        .execute(&mut tx)
+        .await?;
+
+        let fingerprint = mutation.fingerprint(&self.actor);
+        let claimed = sqlx::query(
+            "INSERT INTO idempotency_keys (tenant_id, key, op, fingerprint, expires_at) VALUES (?, ?, ?, ?, ?) ON CONFLICT (tenant_id, key) DO NOTHING",
+        )
+        .bind(&self.tenant)
+        .bind(&key)
+        .bind(mutation.op())
+        .bind(&fingerprint)
+        .bind((now + self.idempotency_ttl).timestamp_micros())
+        .execute(&mut tx)
+        .await?
+        .rows_affected()
+            == 1;
+
+        if !claimed {
+            let row = sqlx::query(
+                "SELECT fingerprint, response FROM idempotency_keys WHERE tenant_id = ? AND key = ?",
+            )
+            .bind(&self.tenant)
+            .bind(&key)
+            .fetch_one(&mut tx)
+            .await?;
+            if row.get::<String, _>("fingerprint") != fingerprint {
+                return Err(ReservationError::IdempotencyKeyReused(key));
+            }
+            let response: Vec<u8> = row.get("response");
+            return Reservation::decode(response.as_slice()).map_err(|_| ReservationError::Unknown);
+        }
+
+        let rsvp = mutation.run(&mut tx, &self.tenant).await?;
+        sqlx::query("UPDATE idempotency_keys SET response = ? WHERE tenant_id = ? AND key = ?")
+            .bind(rsvp.encode_to_vec())
+            .bind(&self.tenant)
+            .bind(&key)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        self.notify();
+
+        Ok(rsvp)
+    }
+}
+
+impl Mutation {
+    async fn run(
+        self,
+        conn: &mut SqliteConnection,
+        tenant: &str,
+    ) -> Result<Reservation, ReservationError> {
+        match self {
+            Mutation::Reserve(rsvp) => insert_reservation(conn, tenant, rsvp).await,
+            Mutation::Confirm(id) => confirm_reservation(conn, tenant, id).await,
+            Mutation::Cancel(id) => delete_reservation(conn, tenant, id).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Rsvp for SqliteReservationManager {
+    async fn reserve(&self, rsvp: Reservation) -> Result<Reservation, ReservationError> {
+        self.idempotent(String::new(), Mutation::Reserve(rsvp))
+            .await
+    }
+
+    async fn reserve_idempotent(
+        &self,
+        key: String,
+        rsvp: Reservation,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Reserve(rsvp)).await
+    }
+
+    /// reserve in one transaction, each in its own savepoint so a failure only rolls back that one.
+    async fn reserve_batch(
+        &self,
+        rsvps: Vec<Reservation>,
+        dry_run: bool,
+    ) -> Result<Vec<Result<Reservation, ReservationError>>, ReservationError> {
+        let mut tx = self.begin().await?;
+        let mut results = Vec::with_capacity(rsvps.len());
+        for rsvp in rsvps {
+            let mut savepoint = tx.begin().await?;
+            match insert_reservation(&mut savepoint, &self.tenant, rsvp).await {
+                Ok(rsvp) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(rsvp));
+                }
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    results.push(Err(e));
+                }
+            }
+        }
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+            self.notify();
+        }
+        Ok(results)
+    }
+
+    async fn change_status(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        self.idempotent(String::new(), Mutation::Confirm(id)).await
+    }
+
+    async fn change_status_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Confirm(id)).await
+    }
+
+    async fn update_note(
+        &self,
+        id: ReservationId,
+        note: String,
+    ) -> Result<Reservation, ReservationError> {
+        let row = sqlx::query(
+            "UPDATE reservations SET note = ? WHERE id = ? AND tenant_id = ? RETURNING *",
+        )
+        .bind(note)
+        .bind(id)
+        .bind(&self.tenant)
+        .fetch_one(&self.pool)
+        .await?;
+        from_row(&row)
+    }
+
+    async fn get(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        let row = sqlx::query("SELECT * FROM reservations WHERE id = ? AND tenant_id = ?")
+            .bind(id)
+            .bind(&self.tenant)
+            .fetch_one(&self.pool)
+            .await?;
+        from_row(&row)
+    }
+
+    async fn delete(&self, id: ReservationId) -> Result<Reservation, ReservationError> {
+        self.idempotent(String::new(), Mutation::Cancel(id)).await
+    }
+
+    async fn delete_idempotent(
+        &self,
+        key: String,
+        id: ReservationId,
+    ) -> Result<Reservation, ReservationError> {
+        self.idempotent(key, Mutation::Cancel(id)).await
+    }
+
+    async fn query(&self, query: ReservationQuery) -> Result<Vec<Reservation>, ReservationError> {
+        let cursor = query_cursor(&query)?;
+        let mut builder = QueryBuilder::new("SELECT * FROM reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        if let Some(cursor) = cursor {
+            builder
+                .push(if query.desc {
+                    " AND id < "
+                } else {
+                    " AND id > "
+                })
+                .push_bind(cursor);
+        }
+
+        let direction = if query.desc { "DESC" } else { "ASC" };
+        match query.sort_by() {
+            ReservationSortKey::Start => builder.push(" ORDER BY start_at "),
+            ReservationSortKey::End => builder.push(" ORDER BY end_at "),
+            ReservationSortKey::Id => builder.push(" ORDER BY id "),
+        };
+        // id breaks ties, so pages are stable
+        builder.push(direction).push(", id ").push(direction);
+
+        let page = query.page.max(1);
+        let page_size = if query.page_size > 0 {
+            query.page_size
+        } else {
+            DEFAULT_PAGE_SIZE
+        };
+        builder.push(" LIMIT ").push_bind(page_size);
+        if cursor.is_none() {
+            builder.push(" OFFSET ").push_bind((page - 1) * page_size);
+        }
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        rows.iter().map(from_row).collect()
+    }
+
+    async fn filter(
+        &self,
+        filter: ReservationFilter,
+    ) -> Result<(FilterPager, Vec<Reservation>), ReservationError> {
+        let query = filter.to_query();
+        let page_size = if filter.page_size > 0 {
+            filter.page_size
+        } else {
+            DEFAULT_PAGE_SIZE
+        };
+        let mut builder = QueryBuilder::new("SELECT * FROM reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        if let Some(cursor) = filter_cursor(&filter)? {
+            builder
+                .push(if filter.desc {
+                    " AND id < "
+                } else {
+                    " AND id > "
+                })
+                .push_bind(cursor);
+        }
+        let direction = if filter.desc { "DESC" } else { "ASC" };
+        // one more to tell if there is a next page
+        builder
+            .push(" ORDER BY id ")
+            .push(direction)
+            .push(" LIMIT ")
+            .push_bind(page_size + 1);
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM reservations");
+        push_conditions(&mut count, &self.tenant, &query)?;
+
+        let mut tx = self.pool.begin().await?;
+        let rows = builder.build().fetch_all(&mut tx).await?;
+        let total: i64 = count.build().fetch_one(&mut tx).await?.get(0);
+        tx.commit().await?;
+        let rsvps = rows.iter().map(from_row).collect::<Result<_, _>>()?;
+        Ok(filter_page(&filter, page_size, rsvps, total))
+    }
+
+    /// stream changes matching the filter. Changes of this process are sent right away, the
+    /// ones of other processes sharing the database within a second.
+    async fn listen(
+        &self,
+        filter: ListenFilter,
+    ) -> Result<ReservationChangeStream, ReservationError> {
+        // subscribe before reading the cursor, so no change falls in between
+        let mut receiver = self.notify.subscribe();
+        receiver.borrow_and_update();
+        let (cursor, pending) = match filter.since {
+            Some(since) => {
+                let changes = fetch_changes(&self.pool, &self.tenant, since).await?;
+                let cursor = changes.last().map_or(since, |c| c.id);
+                let pending = changes.into_iter().filter(|c| filter.matches(c)).collect();
+                (cursor, pending)
+            }
+            None => {
+                let cursor: i64 =
+                    sqlx::query("SELECT COALESCE(MAX(id), 0) FROM reservation_changes")
+                        .fetch_one(&self.pool)
+                        .await?
+                        .get(0);
+                (cursor, VecDeque::new())
+            }
+        };
+
+        let state = ListenState {
+            receiver,
+            // keeps the sender, and thus the receiver, alive as long as the stream
+            _notify: self.notify.clone(),
+            pool: self.pool.clone(),
+            tenant: self.tenant.clone(),
+            filter,
+            cursor,
+            pending,
+        };
+        let changes = stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                if let Some(change) = state.pending.pop_front() {
+                    return Some((Ok(change), Some(state)));
+                }
+                // a timeout means no change in this process, fetch anyway
+                let _ = tokio::time::timeout(POLL_INTERVAL, state.receiver.changed()).await;
+                match fetch_changes(&state.pool, &state.tenant, state.cursor).await {
+                    Ok(changes) => {
+                        if let Some(last) = changes.last() {
+                            state.cursor = last.id;
+                        }
+                        let filter = &state.filter;
+                        let changes = changes.into_iter().filter(|c| filter.matches(c));
+                        state.pending.extend(changes);
+                    }
+                    Err(e) => return Some((Err(e), None)),
+                }
+            }
+        });
+        Ok(Box::pin(changes))
+    }
+
+    /// delete all reservations matching the query in one transaction, rolled back in dry run mode.
+    async fn cancel_by_filter(
+        &self,
+        query: ReservationQuery,
+        dry_run: bool,
+    ) -> Result<Vec<Reservation>, ReservationError> {
+        if query.get_user_ids().is_empty()
+            && query.get_resource_ids().is_empty()
+            && query.get_statuses().is_empty()
+            && query.start.is_none()
+            && query.end.is_none()
+        {
+            return Err(ReservationError::InvalidFilter(
+                "refuse to cancel all reservations, at least one condition is required".into(),
+            ));
+        }
+
+        let mut builder = QueryBuilder::new("DELETE FROM reservations");
+        push_conditions(&mut builder, &self.tenant, &query)?;
+        builder.push(" RETURNING *");
+
+        let mut tx = self.begin().await?;
+        // the trigger records a change event for every deleted row
+        let rows = builder.build().fetch_all(&mut tx).await?;
+        let rsvps = rows.iter().map(from_row).collect::<Result<_, _>>()?;
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+            self.notify();
+        }
+        Ok(rsvps)
+    }
+}
+
+struct ListenState {
+    receiver: watch::Receiver<()>,
+    _notify: Arc<watch::Sender<()>>,
+    pool: SqlitePool,
+    tenant: String,
+    filter: ListenFilter,
+    cursor: i64,
+    pending: VecDeque<ReservationChange>,
+}
+
+/// push the WHERE clause of a query, with the semantics of the postgres range operators.
+fn push_conditions(
+    builder: &mut QueryBuilder<Sqlite>,
+    tenant: &str,
+    query: &ReservationQuery,
+) -> Result<(), ReservationError> {
+    builder
+        .push(" WHERE tenant_id = ")
+        .push_bind(tenant.to_string());
+
+    let start = query.start.as_ref().map(to_utc_time);
+    let end = query.end.as_ref().map(to_utc_time);
+    if let (Some(start), Some(end)) = (start, end) {
+        Window::new(start, end).validate()?;
+    }
+    let start = start.map(|t| t.timestamp_micros());
+    let end = end.map(|t| t.timestamp_micros());
+    match query.match_mode() {
+        _ if start.is_none() && end.is_none() => {}
+        // an empty timespan is contained by any range
+        ReservationMatchMode::Contains => {
+            builder.push(" AND (start_at = end_at OR (TRUE");
+            if let Some(start) = start {
+                builder.push(" AND start_at >= ").push_bind(start);
+            }
+            if let Some(end) = end {
+                builder.push(" AND end_at <= ").push_bind(end);
+            }
+            builder.push("))");
+        }
+        // an empty timespan or range overlaps nothing
+        ReservationMatchMode::Overlaps => {
+            builder.push(" AND start_at < end_at");
+            if let Some(start) = start {
+                builder.push(" AND end_at > ").push_bind(start);
+            }
+            if let Some(end) = end {
+                builder.push(" AND start_at < ").push_bind(end);
+            }
+            if let (Some(start), Some(end)) = (start, end) {
+                if start == end {
+                    builder.push(" AND FALSE");
+                }
+            }
+        }
+        ReservationMatchMode::StartsWithin => {
+            if let Some(start) = start {
+                builder.push(" AND start_at >= ").push_bind(start);
+            }
+            if let Some(end) = end {
+                builder.push(" AND start_at < ").push_bind(end);
+            }
+        }
+    }
+
+    push_in(builder, "user_id", query.get_user_ids());
+    push_in(builder, "resource_id", query.get_resource_ids());
+    push_in(builder, "status", query.get_statuses());
+    Ok(())
+}
+
+/// push `AND column IN (values)`, nothing if values is empty
+fn push_in(builder: &mut QueryBuilder<Sqlite>, column: &str, values: Vec<String>) {
+    if values.is_empty() {
+        return;
+    }
+    builder.push(" AND ").push(column).push(" IN (");
+    let mut separated = builder.separated(", ");
+    for value in values {
+        separated.push_bind(value);
+    }
+    builder.push(")");
+}
+
+/// insert a reservation of the tenant and fill its id.
+async fn insert_reservation(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    mut rsvp: Reservation,
+) -> Result<Reservation, ReservationError> {
+    if rsvp.start.is_none() || rsvp.end.is_none() {
+        return Err(ReservationError::InvalidTimespan);
+    }
+    Window::from_reservation(&rsvp).validate()?;
+    let status = ReservationStatus::from_i32(rsvp.status).ok_or(ReservationError::InvalidStatus)?;
+    let start = micros(rsvp.start.as_ref().unwrap());
+    let end = micros(rsvp.end.as_ref().unwrap());
+
+    let id = ReservationId::new_v4();
+    // booked by the actor the transaction wrote first
+    let inserted = sqlx::query(
+        "INSERT INTO reservations (id, user_id, status, resource_id, start_at, end_at, note, tenant_id, booked_by) VALUES (?, ?, ?, ?, ?, ?, ?, ?, (SELECT actor FROM change_actor)) RETURNING booked_by",
+    )
+    .bind(id)
+    .bind(&rsvp.user_id)
+    .bind(status.to_string())
+    .bind(&rsvp.resource_id)
+    .bind(start)
+    .bind(end)
+    .bind(&rsvp.note)
+    .bind(tenant)
+    .fetch_one(&mut *conn)
+    .await;
+
+    match inserted {
+        Ok(row) => {
+            rsvp.id = id.to_string();
+            rsvp.tenant_id = tenant.to_string();
+            rsvp.booked_by = row.get("booked_by");
+            Ok(rsvp)
+        }
+        Err(sqlx::Error::Database(e)) if e.message() == CONFLICT => {
+            let existing: ReservationId = sqlx::query(
+                "SELECT id FROM reservations WHERE tenant_id = ? AND resource_id = ? AND start_at < ? AND ? < end_at AND start_at < end_at",
+            )
+            .bind(tenant)
+            .bind(&rsvp.resource_id)
+            .bind(end)
+            .bind(start)
+            .fetch_one(&mut *conn)
+            .await?
+            .get(0);
+            Err(ReservationError::Conflict(format!(
+                "{} is reserved by {existing} in this period",
+                rsvp.resource_id
+            )))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// change a pending reservation of the tenant to confirmed.
+async fn confirm_reservation(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    id: ReservationId,
+) -> Result<Reservation, ReservationError> {
+    let row = sqlx::query(
+        "UPDATE reservations SET status = 'confirmed' WHERE id = ? AND tenant_id = ? AND status = 'pending' RETURNING *",
+    )
+    .bind(id)
+    .bind(tenant)
+    .fetch_one(conn)
+    .await?;
+    from_row(&row)
+}
+
+/// delete a reservation of the tenant by id.
+async fn delete_reservation(
+    conn: &mut SqliteConnection,
+    tenant: &str,
+    id: ReservationId,
+) -> Result<Reservation, ReservationError> {
+    let row = sqlx::query("DELETE FROM reservations WHERE id = ? AND tenant_id = ? RETURNING *")
+        .bind(id)
+        .bind(tenant)
+        .fetch_one(conn)
+        .await?;
+    from_row(&row)
+}
+
+/// fetch changes of the tenant after the cursor in order, together with the current state of their
+/// reservations.
+async fn fetch_changes(
+    pool: &SqlitePool,
+    tenant: &str,
+    cursor: i64,
+) -> Result<Vec<ReservationChange>, ReservationError> {
+    let rows = sqlx::query(
+        "SELECT c.id AS change_id, c.reservation_id, c.op, c.user_id AS change_user_id, c.resource_id AS change_resource_id, c.status AS change_status, c.tenant_id AS change_tenant_id, c.actor AS change_actor, c.on_behalf_of AS change_on_behalf_of, r.* FROM reservation_changes c LEFT JOIN reservations r ON r.id = c.reservation_id WHERE c.id > ? AND c.tenant_id = ? ORDER BY c.id",
+    )
+    .bind(cursor)
+    .bind(tenant)
+    .fetch_all(pool)
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            let op: String = row.get("op");
+            let op = op.parse().unwrap_or(ReservationUpdateType::Unknown);
+            let exists = row.get::<Option<ReservationId>, _>("id").is_some();
+            let reservation = if exists {
+                from_row(row)?
+            } else {
+                Reservation {
+                    id: row.get::<ReservationId, _>("reservation_id").to_string(),
+                    user_id: row
+                        .get::<Option<String>, _>("change_user_id")
+                        .unwrap_or_default(),
+                    resource_id: row
+                        .get::<Option<String>, _>("change_resource_id")
+                        .unwrap_or_default(),
+                    status: row
+                        .get::<Option<String>, _>("change_status")
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(ReservationStatus::Unknown) as i32,
+                    tenant_id: row.get("change_tenant_id"),
+                    ..Default::default()
+                }
+            };
+            Ok(ReservationChange {
+                id: row.get("change_id"),
+                op,
+                reservation,
+                actor: row.get("change_actor"),
+                on_behalf_of: row.get("change_on_behalf_of"),
+            })
+        })
+        .collect()
+}
+
+// map a row to a reservation
+fn from_row(row: &SqliteRow) -> Result<Reservation, ReservationError> {
+    let status: String = row.get("status");
+    let status: ReservationStatus = status
+        .parse()
+        .map_err(|_| ReservationError::InvalidStatus)?;
+    Ok(Reservation {
+        id: row.get::<ReservationId, _>("id").to_string(),
+        user_id: row.get("user_id"),
+        status: status as i32,
+        resource_id: row.get("resource_id"),
+        start: Some(timestamp(row.get("start_at"))),
+        end: Some(timestamp(row.get("end_at"))),
+        note: row.get::<Option<String>, _>("note").unwrap_or_default(),
+        tenant_id: row.get("tenant_id"),
+        booked_by: row.get("booked_by"),
+    })
+}
+
+/// microseconds since the unix epoch, the precision of postgres timestamps
+fn micros(ts: &Timestamp) -> i64 {
+    to_utc_time(ts).timestamp_micros()
+}
+
+fn timestamp(micros: i64) -> Timestamp {
+    Timestamp {
+        seconds: micros.div_euclid(1_000_000),
+        nanos: (micros.rem_euclid(1_000_000) * 1000) as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::{to_timestamp, ReservationQueryBuilder};
+    use futures::StreamExt;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    /// a migrated in-memory database, one connection so every query sees the same database
+    async fn manager() -> SqliteReservationManager {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let manager = SqliteReservationManager::new(pool);
+        manager.migrate().await.unwrap();
+        manager
+    }
+
+    fn reservation(user_id: &str, resource_id: &str, start: &str, end: &str) -> Reservation {
+        Reservation {
+            user_id: user_id.to_string(),
+            status: ReservationStatus::Pending as i32,
+            resource_id: resource_id.to_string(),
+            start: Some(to_timestamp(start.parse().unwrap())),
+            end: Some(to_timestamp(end.parse().unwrap())),
+            note: "note".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reserve_conflict_confirm_and_delete_should_work() {
+        let manager = manager().await;
+        let rsvp = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        let id = rsvp.id.parse().unwrap();
+        assert_eq!(manager.get(id).await.unwrap(), rsvp);
+
+        let err = manager
+            .reserve(reservation(
+                "Syuu",
+                "room 1",
+                "2022-11-18T05:00:00Z",
+                "2022-11-18T07:00:00Z",
+            ))
+            .await
+            .unwrap_err();
+        assert!(err.is_conflict());
+        assert!(err.to_string().contains(&rsvp.id));
+        // touching is fine
+        manager
+            .reserve(reservation(
+                "Syuu",
+                "room 1",
+                "2022-11-18T06:00:00Z",
+                "2022-11-18T07:00:00Z",
+            ))
+            .await
+            .unwrap();
+
+        let confirmed = manager.change_status(id).await.unwrap();
+        assert_eq!(confirmed.status, ReservationStatus::Confirmed as i32);
+        assert!(manager.change_status(id).await.unwrap_err().is_not_found());
+        let noted = manager.update_note(id, "moved".into()).await.unwrap();
+        assert_eq!(noted.note, "moved");
+
+        manager.delete(id).await.unwrap();
+        assert!(manager.get(id).await.unwrap_err().is_not_found());
+        assert!(manager.delete(id).await.unwrap_err().is_not_found());
+    }
+
+    #[tokio::test]
+    async fn query_and_cancel_by_filter_should_work() {
+        let manager = manager().await;
+        for (user, resource, start, end) in [
+            (
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ),
+            (
+                "M4n5ter",
+                "room 2",
+                "2022-11-18T05:00:00Z",
+                "2022-11-18T09:00:00Z",
+            ),
+            (
+                "Syuu",
+                "room 1",
+                "2022-11-19T04:00:00Z",
+                "2022-11-19T06:00:00Z",
+            ),
+        ] {
+            manager
+                .reserve(reservation(user, resource, start, end))
+                .await
+                .unwrap();
+        }
+
+        let query = ReservationQueryBuilder::default()
+            .user_id("M4n5ter")
+            .start(to_timestamp("2022-11-18T03:00:00Z".parse().unwrap()))
+            .end(to_timestamp("2022-11-18T08:00:00Z".parse().unwrap()))
+            .build()
+            .unwrap();
+        let rsvps = manager.query(query.clone()).await.unwrap();
+        assert_eq!(rsvps.len(), 1);
+        assert_eq!(rsvps[0].resource_id, "room 1");
+
+        let mut overlaps = query.clone();
+        overlaps.match_mode = ReservationMatchMode::Overlaps as i32;
+        overlaps.desc = true;
+        let rsvps = manager.query(overlaps).await.unwrap();
+        let resources: Vec<_> = rsvps.iter().map(|r| r.resource_id.as_str()).collect();
+        assert_eq!(resources, ["room 2", "room 1"]);
+
+        let mut paged = ReservationQueryBuilder::default()
+            .page_size(2)
+            .page(2)
+            .build()
+            .unwrap();
+        let rsvps = manager.query(paged.clone()).await.unwrap();
+        assert_eq!(rsvps.len(), 1);
+        assert_eq!(rsvps[0].user_id, "Syuu");
+
+        let cancelled = manager.cancel_by_filter(query.clone(), true).await.unwrap();
+        assert_eq!(cancelled.len(), 1);
+        let cancelled = manager.cancel_by_filter(query, false).await.unwrap();
+        assert_eq!(cancelled.len(), 1);
+        paged.page = 1;
+        assert_eq!(manager.query(paged).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_should_be_honored() {
+        let manager = manager().await;
+        let key = "retry".to_string();
+        let rsvp = reservation(
+            "M4n5ter",
+            "room 1",
+            "2022-11-18T04:00:00Z",
+            "2022-11-18T06:00:00Z",
+        );
+        let first = manager
+            .reserve_idempotent(key.clone(), rsvp.clone())
+            .await
+            .unwrap();
+        let again = manager.reserve_idempotent(key.clone(), rsvp).await.unwrap();
+        assert_eq!(first, again);
+
+        let id = first.id.parse().unwrap();
+        let err = manager.delete_idempotent(key, id).await.unwrap_err();
+        assert!(matches!(err, ReservationError::IdempotencyKeyReused(_)));
+        assert_eq!(manager.purge_idempotency_keys().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn listen_should_stream_changes() {
+        let manager = manager().await;
+        let rsvp = manager
+            .reserve(reservation(
+                "M4n5ter",
+                "room 1",
+                "2022-11-18T04:00:00Z",
+                "2022-11-18T06:00:00Z",
+            ))
+            .await
+            .unwrap();
+        let mut live = manager.listen(ListenFilter::default()).await.unwrap();
+        let filter = ListenFilter {
+            since: Some(0),
+            ops: vec![ReservationUpdateType::Create, ReservationUpdateType::Delete],
+            ..Default::default()
+        };
+        let mut replay = manager.listen(filter).await.unwrap();
+
+        let id = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        manager.update_note(id, "moved".into()).await.unwrap();
+        manager.delete(id).await.unwrap();
+
+        let change = live.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (2, ReservationUpdateType::Update));
+        let change = live.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (3, ReservationUpdateType::Delete));
+        assert_eq!(change.reservation.user_id, "M4n5ter");
+        assert_eq!(
+            change.reservation.status,
+            ReservationStatus::Confirmed as i32
+        );
+        assert!(change.reservation.start.is_none());
+
+        let change = replay.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (1, ReservationUpdateType::Create));
+        let change = replay.next().await.unwrap().unwrap();
+        assert_eq!((change.id, change.op), (3, ReservationUpdateType::Delete));
+    }
+}
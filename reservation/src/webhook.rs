@@ -0,0 +1,522 @@
+use crate::{outbox::Sink, ReservationChange, ReservationError, ReservationManager, Webhooks};
+use abi::{ReservationUpdateType, Webhook};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool, Row};
+use std::time::Duration;
+
+/// header carrying the signature of the payload, `sha256=<hex of HMAC-SHA256>`
+pub const SIGNATURE_HEADER: &str = "x-rsvp-signature";
+/// header carrying the op of the change
+pub const EVENT_HEADER: &str = "x-rsvp-event";
+/// header carrying the change id, the same change may be delivered more than once
+pub const DELIVERY_HEADER: &str = "x-rsvp-delivery";
+
+/// secrets shorter than this are rejected
+const MIN_SECRET_LEN: usize = 16;
+/// due deliveries are polled in this interval
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// how long a receiver may take to answer a delivery
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// sign the payload with the webhook secret, receivers compare it with the signature header.
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key size works");
+    mac.update(payload);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait]
+impl Webhooks for ReservationManager {
+    /// create a webhook of the manager's tenant, the secret is returned only here.
+    async fn create_webhook(&self, webhook: Webhook) -> Result<Webhook, ReservationError> {
+        if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+            return Err(ReservationError::InvalidWebhook(
+                "url should be http or https".to_string(),
+            ));
+        }
+        if webhook.secret.len() < MIN_SECRET_LEN {
+            return Err(ReservationError::InvalidWebhook(format!(
+                "secret should have at least {MIN_SECRET_LEN} characters"
+            )));
+        }
+        let mut tx = self.begin().await?;
+        let row = sqlx::query(
+            "INSERT INTO rsvp.webhooks (url, secret, ops, resource_ids, tenant_id) VALUES ($1, $2, $3::rsvp.reservation_update_type[], $4, $5) RETURNING id, url, secret, ops::TEXT[], resource_ids, tenant_id",
+        )
+        .bind(&webhook.url)
+        .bind(&webhook.secret)
+        .bind(op_names(&webhook.ops))
+        .bind(&webhook.resource_ids)
+        .bind(&self.tenant)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        webhook_from_row(&row)
+    }
+
+    /// list the webhooks of the manager's tenant, secrets are left empty.
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, ReservationError> {
+        let mut tx = self.begin().await?;
+        let rows = sqlx::query(
+            "SELECT id, url, '' AS secret, ops::TEXT[], resource_ids, tenant_id FROM rsvp.webhooks WHERE tenant_id = $1 ORDER BY created_at, id",
+        )
+        .bind(&self.tenant)
+        .fetch_all(&mut tx)
+        .await?;
+        tx.commit().await?;
+        rows.iter().map(webhook_from_row).collect()
+    }
+
+    /// delete a webhook of the manager's tenant together with its pending deliveries.
+    async fn delete_webhook(&self, id: Uuid) -> Result<Webhook, ReservationError> {
+        let mut tx = self.begin().await?;
+        let row = sqlx::query(
+            "DELETE FROM rsvp.webhooks WHERE id = $1 AND tenant_id = $2 RETURNING id, url, '' AS secret, ops::TEXT[], resource_ids, tenant_id",
+        )
+        .bind(id)
+        .bind(&self.tenant)
+        .fetch_one(&mut tx)
+        .await?;
+        tx.commit().await?;
+        webhook_from_row(&row)
+    }
+}
+
+fn op_names(ops: &[i32]) -> Vec<String> {
+    ops.iter()
+        .filter_map(|op| ReservationUpdateType::from_i32(*op))
+        .filter(|op| *op != ReservationUpdateType::Unknown)
+        .map(|op| op.to_string())
+        .collect()
+}
+
+fn webhook_from_row(row: &PgRow) -> Result<Webhook, ReservationError> {
+    let ops: Vec<String> = row.get("ops");
+    Ok(Webhook {
+        id: row.get::<Uuid, _>("id").to_string(),
+        url: row.get("url"),
+        secret: row.get("secret"),
+        ops: ops
+            .iter()
+            .filter_map(|op| op.parse::<ReservationUpdateType>().ok())
+            .map(|op| op as i32)
+            .collect(),
+        resource_ids: row.get("resource_ids"),
+        tenant_id: row.get("tenant_id"),
+    })
+}
+
+/// a sink queueing a delivery of the change for every matching webhook of its tenant.
+///
+/// Run it with a `Publisher`, so every change is queued at least once, and send the queued
+/// deliveries with a `WebhookDispatcher`.
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    pool: PgPool,
+}
+
+impl WebhookSink {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn publish(&self, change: &ReservationChange) -> Result<(), ReservationError> {
+        let payload = serde_json::to_string(change)
+            .map_err(|e| ReservationError::SinkError(e.to_string()))?;
+        // a change queued again after a publisher restart is ignored
+        sqlx::query(
+            "INSERT INTO rsvp.webhook_deliveries (webhook_id, change_id, op, payload, tenant_id) SELECT id, $1, $2::rsvp.reservation_update_type, $3, tenant_id FROM rsvp.webhooks WHERE tenant_id = $5 AND (cardinality(ops) = 0 OR $2::rsvp.reservation_update_type = ANY(ops)) AND (cardinality(resource_ids) = 0 OR $4 = ANY(resource_ids)) ON CONFLICT (webhook_id, change_id) DO NOTHING",
+        )
+        .bind(change.id)
+        .bind(change.op.to_string())
+        .bind(payload)
+        .bind(&change.reservation.resource_id)
+        .bind(&change.reservation.tenant_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// send the queued webhook deliveries, retry the failed ones with exponential backoff.
+///
+/// A delivery failing `max_attempts` times is moved to rsvp.webhook_dead_letters.
+pub struct WebhookDispatcher {
+    pool: PgPool,
+    client: reqwest::Client,
+    max_attempts: i32,
+    backoff: Duration,
+    batch_size: i64,
+}
+
+impl WebhookDispatcher {
+    pub fn new(pool: PgPool) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("tls backend should be available");
+        Self {
+            pool,
+            client,
+            max_attempts: 8,
+            backoff: Duration::from_secs(10),
+            batch_size: 100,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: i32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// delay after the first failure, doubled for every following one
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// send the due deliveries, return how many succeeded.
+    ///
+    /// The deliveries are claimed by pushing their next attempt past the time sending the batch
+    /// may take, so no transaction stays open while the receivers answer. A dispatcher stopping
+    /// midway leaves the rest of its batch to be retried once the claim expires.
+    pub async fn dispatch_due(&self) -> Result<usize, ReservationError> {
+        let lease = REQUEST_TIMEOUT * (self.batch_size as u32 + 1);
+        // skip the deliveries another dispatcher is claiming
+        let mut rows = sqlx::query(
+            "WITH due AS (SELECT id FROM rsvp.webhook_deliveries WHERE next_attempt_at <= now() ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED) UPDATE rsvp.webhook_deliveries d SET next_attempt_at = now() + make_interval(secs => $2) FROM due, rsvp.webhooks w WHERE d.id = due.id AND w.id = d.webhook_id RETURNING d.id, d.change_id, d.op::TEXT AS op, d.payload, d.attempts, w.url, w.secret",
+        )
+        .bind(self.batch_size)
+        .bind(lease.as_secs_f64())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.sort_by_key(|row| row.get::<i64, _>("id"));
+
+        let mut delivered = 0;
+        for row in rows {
+            let id: i64 = row.get("id");
+            let attempts: i32 = row.get::<i32, _>("attempts") + 1;
+            match self.send(&row).await {
+                Ok(()) => {
+                    sqlx::query("DELETE FROM rsvp.webhook_deliveries WHERE id = $1")
+                        .bind(id)
+                        .execute(&self.pool)
+                        .await?;
+                    delivered += 1;
+                }
+                Err(e) if attempts >= self.max_attempts => {
+                    sqlx::query(
+                        "WITH d AS (DELETE FROM rsvp.webhook_deliveries WHERE id = $1 RETURNING *) INSERT INTO rsvp.webhook_dead_letters (id, webhook_id, change_id, op, payload, attempts, last_error, tenant_id) SELECT id, webhook_id, change_id, op, payload, $2, $3, tenant_id FROM d",
+                    )
+                    .bind(id)
+                    .bind(attempts)
+                    .bind(e)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                Err(e) => {
+                    let delay = self.backoff * 2u32.pow((attempts - 1).min(16) as u32);
+                    sqlx::query(
+                        "UPDATE rsvp.webhook_deliveries SET attempts = $2, last_error = $3, next_attempt_at = now() + make_interval(secs => $4) WHERE id = $1",
+                    )
+                    .bind(id)
+                    .bind(attempts)
+                    .bind(e)
+                    .bind(delay.as_secs_f64())
+                    .execute(&self.pool)
+                    .await?;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// keep sending deliveries as they are due, only return on error.
+    pub async fn run(&self) -> Result<(), ReservationError> {
+        loop {
+            self.dispatch_due().await?;
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// post the payload, any response other than 2xx is a failure
+    async fn send(&self, row: &PgRow) -> Result<(), String> {
+        let payload: String = row.get("payload");
+        let secret: String = row.get("secret");
+        let url: String = row.get("url");
+        let res = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .header(EVENT_HEADER, row.get::<String, _>("op"))
+            .header(DELIVERY_HEADER, row.get::<i64, _>("change_id").to_string())
+            .header(SIGNATURE_HEADER, sign(&secret, payload.as_bytes()))
+            .body(payload)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("unexpected status {}", res.status()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemorySink, Publisher, Rsvp};
+    use abi::ReservationStatus;
+    use axum::{
+        body::Bytes,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Extension, Router,
+    };
+    use std::sync::{Arc, Mutex};
+
+    const SECRET: &str = "0123456789abcdef";
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    /// start a receiver answering with the status, return its url
+    async fn receiver(status: StatusCode) -> (String, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |Extension(received): Extension<Received>,
+                          headers: HeaderMap,
+                          body: Bytes| async move {
+                        received.lock().unwrap().push((headers, body));
+                        status
+                    },
+                ),
+            )
+            .layer(Extension(received.clone()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        (format!("http://{addr}/hook"), received)
+    }
+
+    fn webhook(url: &str, ops: &[ReservationUpdateType]) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            secret: SECRET.to_string(),
+            ops: ops.iter().map(|op| *op as i32).collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn reserve(manager: &ReservationManager) -> abi::Reservation {
+        manager
+            .reserve(abi::Reservation {
+                user_id: "M4n5ter".to_string(),
+                status: ReservationStatus::Pending as i32,
+                resource_id: "class room 1".to_string(),
+                start: Some("2022-11-18T04:00:00Z".parse().unwrap()),
+                end: Some("2022-11-18T06:00:00Z".parse().unwrap()),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn webhooks_should_be_managed() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let err = manager
+            .create_webhook(webhook("ftp://example.com", &[]))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ReservationError::InvalidWebhook(_)));
+        let mut short = webhook("https://example.com", &[]);
+        short.secret = "short".to_string();
+        assert!(manager.create_webhook(short).await.is_err());
+
+        let created = manager
+            .create_webhook(webhook(
+                "https://example.com",
+                &[ReservationUpdateType::Delete],
+            ))
+            .await
+            .unwrap();
+        assert_eq!(created.secret, SECRET);
+        assert_eq!(created.ops, vec![ReservationUpdateType::Delete as i32]);
+
+        let webhooks = manager.list_webhooks().await.unwrap();
+        assert_eq!(webhooks.len(), 1);
+        assert_eq!(webhooks[0].id, created.id);
+        assert!(webhooks[0].secret.is_empty());
+
+        manager
+            .delete_webhook(created.id.parse().unwrap())
+            .await
+            .unwrap();
+        assert!(manager.list_webhooks().await.unwrap().is_empty());
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn webhooks_should_receive_signed_changes() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let (url, received) = receiver(StatusCode::OK).await;
+        manager
+            .create_webhook(webhook(
+                &url,
+                &[ReservationUpdateType::Create, ReservationUpdateType::Delete],
+            ))
+            .await
+            .unwrap();
+
+        let rsvp = reserve(&manager).await;
+        let id = rsvp.id.parse().unwrap();
+        manager.change_status(id).await.unwrap();
+        manager.delete(id).await.unwrap();
+
+        let sink = WebhookSink::new(migrated_pool.clone());
+        Publisher::new(migrated_pool.clone(), "webhooks", sink)
+            .publish_pending()
+            .await
+            .unwrap();
+        let dispatcher = WebhookDispatcher::new(migrated_pool.clone());
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 2);
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 0);
+
+        let received = received.lock().unwrap();
+        // the update is filtered out
+        let events: Vec<_> = received.iter().map(|(h, _)| &h[EVENT_HEADER]).collect();
+        assert_eq!(events, vec!["create", "delete"]);
+        for (headers, body) in received.iter() {
+            assert_eq!(headers[SIGNATURE_HEADER], sign(SECRET, body).as_str());
+            let json: serde_json::Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(json["reservation"]["id"], rsvp.id.as_str());
+        }
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn slow_receivers_should_not_hold_back_changes() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let app = Router::new().route(
+            "/hook",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                StatusCode::OK
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        manager
+            .create_webhook(webhook(&url, &[ReservationUpdateType::Create]))
+            .await
+            .unwrap();
+        let id = reserve(&manager).await.id.parse().unwrap();
+        let sink = WebhookSink::new(migrated_pool.clone());
+        Publisher::new(migrated_pool.clone(), "webhooks", sink)
+            .publish_pending()
+            .await
+            .unwrap();
+
+        let dispatcher = WebhookDispatcher::new(migrated_pool.clone());
+        let dispatching = tokio::spawn(async move { dispatcher.dispatch_due().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // changes made while the receiver answers are published without waiting for it
+        manager.change_status(id).await.unwrap();
+        manager.delete(id).await.unwrap();
+        let publisher = Publisher::new(migrated_pool.clone(), "test", MemorySink::default());
+        let published = tokio::time::timeout(Duration::from_secs(1), publisher.publish_pending())
+            .await
+            .expect("changes should not wait for the receiver");
+        assert_eq!(published.unwrap(), 3);
+        assert_eq!(dispatching.await.unwrap().unwrap(), 1);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn webhooks_should_only_see_their_tenant() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let acme = ReservationManager::new(migrated_pool.clone()).with_tenant("acme");
+        let (url, received) = receiver(StatusCode::OK).await;
+        let created = acme.create_webhook(webhook(&url, &[])).await.unwrap();
+        assert_eq!(created.tenant_id, "acme");
+
+        // other tenants neither see nor delete it
+        assert!(manager.list_webhooks().await.unwrap().is_empty());
+        let id = created.id.parse().unwrap();
+        assert!(manager.delete_webhook(id).await.unwrap_err().is_not_found());
+        assert_eq!(acme.list_webhooks().await.unwrap().len(), 1);
+
+        // nor is it called on their changes
+        reserve(&manager).await;
+        let rsvp = reserve(&acme).await;
+        let sink = WebhookSink::new(migrated_pool.clone());
+        Publisher::new(migrated_pool.clone(), "webhooks", sink)
+            .publish_pending()
+            .await
+            .unwrap();
+        let dispatcher = WebhookDispatcher::new(migrated_pool.clone());
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+        let received = received.lock().unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&received[0].1).unwrap();
+        assert_eq!(json["reservation"]["id"], rsvp.id.as_str());
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn failed_deliveries_should_be_retried_then_dead_lettered() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let (url, received) = receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        manager.create_webhook(webhook(&url, &[])).await.unwrap();
+        reserve(&manager).await;
+
+        let sink = WebhookSink::new(migrated_pool.clone());
+        Publisher::new(migrated_pool.clone(), "webhooks", sink)
+            .publish_pending()
+            .await
+            .unwrap();
+        let dispatcher = WebhookDispatcher::new(migrated_pool.clone())
+            .with_max_attempts(2)
+            .with_backoff(Duration::from_secs(3600));
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 0);
+        // not due before the backoff
+        dispatcher.dispatch_due().await.unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        let dispatcher = WebhookDispatcher::new(migrated_pool.clone())
+            .with_max_attempts(2)
+            .with_backoff(Duration::ZERO);
+        sqlx::query("UPDATE rsvp.webhook_deliveries SET next_attempt_at = now()")
+            .execute(&migrated_pool)
+            .await
+            .unwrap();
+        dispatcher.dispatch_due().await.unwrap();
+        assert_eq!(received.lock().unwrap().len(), 2);
+
+        let row = sqlx::query("SELECT attempts, last_error FROM rsvp.webhook_dead_letters")
+            .fetch_one(&migrated_pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<i32, _>("attempts"), 2);
+        assert!(row.get::<String, _>("last_error").contains("500"));
+        let pending: i64 = sqlx::query("SELECT COUNT(*) FROM rsvp.webhook_deliveries")
+            .fetch_one(&migrated_pool)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(pending, 0);
+    }
+}
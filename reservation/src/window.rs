@@ -1,6 +1,7 @@
 use abi::{to_utc_time, Reservation, ReservationQuery};
-use chrono::{DateTime, TimeZone};
+use chrono::{DateTime, TimeZone, Utc};
 use sqlx::postgres::types::PgRange;
+use std::ops::Bound;
 
 use crate::{validator::Validator, ReservationError};
 
@@ -24,13 +25,6 @@ impl Window<chrono::Utc> {
             end: to_utc_time(reservation.end.as_ref().unwrap()),
         }
     }
-
-    pub fn from_query(query: &ReservationQuery) -> Self {
-        Self {
-            start: to_utc_time(query.start.as_ref().unwrap()),
-            end: to_utc_time(query.end.as_ref().unwrap()),
-        }
-    }
 }
 
 impl<T: TimeZone> Validator for Window<T> {
@@ -54,3 +48,18 @@ where
         }
     }
 }
+
+/// convert the start and end of a query to PgRange, a missing bound means infinity.
+pub fn query_range(query: &ReservationQuery) -> Result<PgRange<DateTime<Utc>>, ReservationError> {
+    let start = query.start.as_ref().map(to_utc_time);
+    let end = query.end.as_ref().map(to_utc_time);
+    if let (Some(start), Some(end)) = (start, end) {
+        Window::new(start, end).validate()?;
+    }
+
+    let bound = |t: Option<DateTime<Utc>>| t.map_or(Bound::Unbounded, Bound::Excluded);
+    Ok(PgRange {
+        start: bound(start),
+        end: bound(end),
+    })
+}
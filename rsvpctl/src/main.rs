@@ -0,0 +1,354 @@
+mod output;
+
+use abi::{
+    ListenRequest, ReservationMatchMode, ReservationQueryBuilder, ReservationSortKey,
+    ReservationStatus, ReservationUpdateType,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::StreamExt;
+use output::{render, render_line, Format, Row};
+use reservation::{DbConfig, ReservationManager};
+use reservation_client::{ClientOptions, ReservationClient};
+use std::io::{self, Write};
+
+/// admin tool of the reservation service
+#[derive(Debug, Parser)]
+#[command(name = "rsvpctl", version)]
+struct Cli {
+    /// address of the reservation service
+    #[arg(
+        long,
+        env = "RSVPCTL_ENDPOINT",
+        default_value = "http://127.0.0.1:50051"
+    )]
+    endpoint: String,
+    /// tenant of the reservations, the default tenant if empty
+    #[arg(long, env = "RSVPCTL_TENANT", default_value = "")]
+    tenant: String,
+    /// bearer token of the caller, if the service authenticates with jwt
+    #[arg(long, env = "RSVPCTL_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// output format
+    #[arg(long, short, value_enum, default_value_t = Format::Table, global = true)]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// make a pending reservation
+    Reserve {
+        #[arg(long)]
+        user: String,
+        #[arg(long)]
+        resource: String,
+        /// start time in RFC 3339, e.g. 2022-11-18T12:00:00+08:00
+        #[arg(long)]
+        start: DateTime<Utc>,
+        /// end time in RFC 3339
+        #[arg(long)]
+        end: DateTime<Utc>,
+        #[arg(long, default_value = "")]
+        note: String,
+        /// book for the user as an admin, needed if the service takes the user from the token
+        #[arg(long)]
+        on_behalf_of: bool,
+    },
+    /// confirm a pending reservation
+    Confirm { id: String },
+    /// cancel a reservation
+    Cancel { id: String },
+    /// get a reservation by id
+    Get { id: String },
+    /// query reservations
+    Query(QueryArgs),
+    /// print reservation changes as they happen
+    Listen(ListenArgs),
+    /// manage the database schema directly, without the service
+    Migrate(MigrateArgs),
+}
+
+#[derive(Debug, Args)]
+struct MigrateArgs {
+    /// database of the reservation service
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: String,
+    #[command(subcommand)]
+    command: MigrateCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum MigrateCommand {
+    /// list the migrations and whether they are applied
+    Status,
+    /// apply the pending migrations
+    Up,
+    /// revert the latest migration, or all newer than the target version
+    Down {
+        #[arg(long)]
+        target: Option<i64>,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ListenArgs {
+    /// user id, can be repeated
+    #[arg(long)]
+    user: Vec<String>,
+    /// resource id, can be repeated
+    #[arg(long)]
+    resource: Vec<String>,
+    /// status, can be repeated
+    #[arg(long, value_enum)]
+    status: Vec<StatusArg>,
+    /// change type, can be repeated
+    #[arg(long, value_enum)]
+    op: Vec<OpArg>,
+    /// replay the changes after this sequence number first
+    #[arg(long)]
+    since: Option<i64>,
+}
+
+#[derive(Debug, Args)]
+struct QueryArgs {
+    /// user id, can be repeated
+    #[arg(long)]
+    user: Vec<String>,
+    /// resource id, can be repeated
+    #[arg(long)]
+    resource: Vec<String>,
+    /// status, can be repeated
+    #[arg(long, value_enum)]
+    status: Vec<StatusArg>,
+    /// start time in RFC 3339
+    #[arg(long)]
+    start: Option<DateTime<Utc>>,
+    /// end time in RFC 3339
+    #[arg(long)]
+    end: Option<DateTime<Utc>>,
+    /// how start and end are matched
+    #[arg(long, value_enum, default_value_t = ModeArg::Contains)]
+    mode: ModeArg,
+    /// sort key
+    #[arg(long, value_enum, default_value_t = SortArg::Start)]
+    sort: SortArg,
+    #[arg(long)]
+    desc: bool,
+    #[arg(long, default_value_t = 1)]
+    page: i64,
+    #[arg(long, default_value_t = 10)]
+    page_size: i64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StatusArg {
+    Pending,
+    Confirmed,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OpArg {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ModeArg {
+    Contains,
+    Overlaps,
+    StartsWithin,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SortArg {
+    Start,
+    End,
+    Id,
+}
+
+impl From<StatusArg> for ReservationStatus {
+    fn from(status: StatusArg) -> Self {
+        match status {
+            StatusArg::Pending => ReservationStatus::Pending,
+            StatusArg::Confirmed => ReservationStatus::Confirmed,
+            StatusArg::Blocked => ReservationStatus::Blocked,
+        }
+    }
+}
+
+impl From<OpArg> for ReservationUpdateType {
+    fn from(op: OpArg) -> Self {
+        match op {
+            OpArg::Create => ReservationUpdateType::Create,
+            OpArg::Update => ReservationUpdateType::Update,
+            OpArg::Delete => ReservationUpdateType::Delete,
+        }
+    }
+}
+
+impl From<ModeArg> for ReservationMatchMode {
+    fn from(mode: ModeArg) -> Self {
+        match mode {
+            ModeArg::Contains => ReservationMatchMode::Contains,
+            ModeArg::Overlaps => ReservationMatchMode::Overlaps,
+            ModeArg::StartsWithin => ReservationMatchMode::StartsWithin,
+        }
+    }
+}
+
+impl From<SortArg> for ReservationSortKey {
+    fn from(sort: SortArg) -> Self {
+        match sort {
+            SortArg::Start => ReservationSortKey::Start,
+            SortArg::End => ReservationSortKey::End,
+            SortArg::Id => ReservationSortKey::Id,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut stdout = io::stdout().lock();
+    if let Command::Migrate(args) = cli.command {
+        return migrate(args, &mut stdout).await;
+    }
+    let options = ClientOptions {
+        tenant: cli.tenant,
+        token: cli.token,
+        ..Default::default()
+    };
+    let client = ReservationClient::connect_with(cli.endpoint, options).await?;
+
+    let rsvps = match cli.command {
+        Command::Reserve {
+            user,
+            resource,
+            start,
+            end,
+            note,
+            on_behalf_of: false,
+        } => vec![client.reserve(user, resource, start, end, note).await?],
+        Command::Reserve {
+            user,
+            resource,
+            start,
+            end,
+            note,
+            on_behalf_of: true,
+        } => vec![
+            client
+                .reserve_on_behalf_of(user, resource, start, end, note)
+                .await?,
+        ],
+        Command::Confirm { id } => vec![client.confirm(&id).await?],
+        Command::Cancel { id } => vec![client.cancel(&id).await?],
+        Command::Get { id } => vec![client.get(&id).await?],
+        Command::Query(args) => {
+            let mut builder = ReservationQueryBuilder::default();
+            builder
+                .user_ids(args.user)
+                .resource_ids(args.resource)
+                .statuses(
+                    args.status
+                        .into_iter()
+                        .map(|s| ReservationStatus::from(s) as i32)
+                        .collect::<Vec<_>>(),
+                )
+                .match_mode(ReservationMatchMode::from(args.mode) as i32)
+                .sort_by(ReservationSortKey::from(args.sort) as i32)
+                .desc(args.desc)
+                .page(args.page)
+                .page_size(args.page_size);
+            if let Some(start) = args.start {
+                builder.start(abi::to_timestamp(start));
+            }
+            if let Some(end) = args.end {
+                builder.end(abi::to_timestamp(end));
+            }
+            client.query(builder.build()?).await?
+        }
+        Command::Migrate(_) => unreachable!("handled without the service"),
+        Command::Listen(args) => {
+            let request = ListenRequest {
+                resource_ids: args.resource,
+                user_ids: args.user,
+                statuses: args
+                    .status
+                    .into_iter()
+                    .map(|s| ReservationStatus::from(s) as i32)
+                    .collect(),
+                ops: args
+                    .op
+                    .into_iter()
+                    .map(|op| ReservationUpdateType::from(op) as i32)
+                    .collect(),
+                since: args.since,
+            };
+            let mut changes = client.listen(request).await?;
+            let mut header = true;
+            while let Some(change) = changes.next().await {
+                let row = Row::from(&change?);
+                match cli.output {
+                    Format::Json => render_line(&row, &mut stdout)?,
+                    format => render(format, &[row], header, &mut stdout)?,
+                }
+                stdout.flush()?;
+                header = false;
+            }
+            return Ok(());
+        }
+    };
+
+    let rows: Vec<Row> = rsvps.iter().map(Row::from).collect();
+    render(cli.output, &rows, true, &mut stdout)?;
+    Ok(())
+}
+
+async fn migrate(args: MigrateArgs, out: &mut impl Write) -> Result<()> {
+    let config = DbConfig {
+        url: args.database_url,
+        max_connections: 1,
+        migrate: false,
+        ..Default::default()
+    };
+    let manager = ReservationManager::from_config(&config).await?;
+    match args.command {
+        MigrateCommand::Status => {}
+        MigrateCommand::Up => manager.migrate().await?,
+        MigrateCommand::Down { target } => {
+            let target = match target {
+                Some(target) => target,
+                // the one before the latest applied migration
+                None => {
+                    let applied: Vec<_> = manager
+                        .migration_status()
+                        .await?
+                        .into_iter()
+                        .filter(|m| m.applied)
+                        .collect();
+                    match applied.len() {
+                        0 | 1 => 0,
+                        n => applied[n - 2].version,
+                    }
+                }
+            };
+            manager.migrate_down(target).await?;
+        }
+    }
+
+    for m in manager.migration_status().await? {
+        let state = match (m.applied, m.modified) {
+            (true, true) => "modified",
+            (true, false) => "applied",
+            (false, _) => "pending",
+        };
+        writeln!(out, "{:<16}{:<10}{}", m.version, state, m.description)?;
+    }
+    Ok(())
+}
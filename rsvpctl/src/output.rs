@@ -0,0 +1,221 @@
+use abi::{to_utc_time, ListenResponse, Reservation, ReservationStatus, ReservationUpdateType};
+use anyhow::Result;
+use chrono::SecondsFormat;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+/// output format of reservations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// a reservation flattened for printing
+#[derive(Debug, Serialize)]
+pub struct Row {
+    /// sequence number of a change, pass it as `--since` to resume listening
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seq: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    op: Option<String>,
+    id: String,
+    user_id: String,
+    resource_id: String,
+    status: String,
+    start: String,
+    end: String,
+    note: String,
+}
+
+impl From<&Reservation> for Row {
+    fn from(rsvp: &Reservation) -> Self {
+        let time = |t: &Option<prost_types::Timestamp>| {
+            t.as_ref()
+                .map(|t| to_utc_time(t).to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default()
+        };
+        let status = ReservationStatus::from_i32(rsvp.status).unwrap_or(ReservationStatus::Unknown);
+        Self {
+            seq: None,
+            op: None,
+            id: rsvp.id.clone(),
+            user_id: rsvp.user_id.clone(),
+            resource_id: rsvp.resource_id.clone(),
+            status: status.to_string(),
+            start: time(&rsvp.start),
+            end: time(&rsvp.end),
+            note: rsvp.note.clone(),
+        }
+    }
+}
+
+impl From<&ListenResponse> for Row {
+    fn from(change: &ListenResponse) -> Self {
+        let mut row = change
+            .reservation
+            .as_ref()
+            .map(Row::from)
+            .unwrap_or_else(|| Row::from(&Reservation::default()));
+        let op =
+            ReservationUpdateType::from_i32(change.op).unwrap_or(ReservationUpdateType::Unknown);
+        row.seq = Some(change.id);
+        row.op = Some(op.to_string());
+        row
+    }
+}
+
+impl Row {
+    fn headers(&self) -> Vec<&'static str> {
+        // rows of changes have both seq and op
+        let headers = vec![
+            "id",
+            "user_id",
+            "resource_id",
+            "status",
+            "start",
+            "end",
+            "note",
+        ];
+        match self.op {
+            Some(_) => ["seq", "op"].into_iter().chain(headers).collect(),
+            None => headers,
+        }
+    }
+
+    fn values(&self) -> Vec<String> {
+        let values = vec![
+            self.id.clone(),
+            self.user_id.clone(),
+            self.resource_id.clone(),
+            self.status.clone(),
+            self.start.clone(),
+            self.end.clone(),
+            self.note.clone(),
+        ];
+        match &self.op {
+            Some(op) => [self.seq.unwrap_or_default().to_string(), op.clone()]
+                .into_iter()
+                .chain(values)
+                .collect(),
+            None => values,
+        }
+    }
+}
+
+/// print rows in the format, `header` is ignored for json.
+///
+/// Table and csv print one line per row, json prints an array. For streaming, print the rows
+/// one by one with json lines instead.
+pub fn render(format: Format, rows: &[Row], header: bool, out: &mut impl Write) -> Result<()> {
+    match format {
+        Format::Table => render_table(rows, header, out)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, rows)?;
+            writeln!(out)?;
+        }
+        Format::Csv => {
+            let mut writer = csv::WriterBuilder::new().from_writer(out);
+            if let (true, Some(row)) = (header, rows.first()) {
+                writer.write_record(row.headers())?;
+            }
+            for row in rows {
+                writer.write_record(row.values())?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// print a row as a json line, used for streaming output.
+pub fn render_line(row: &Row, out: &mut impl Write) -> Result<()> {
+    serde_json::to_writer(&mut *out, row)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn render_table(rows: &[Row], header: bool, out: &mut impl Write) -> Result<()> {
+    let Some(first) = rows.first() else {
+        if header {
+            writeln!(out, "no reservations")?;
+        }
+        return Ok(());
+    };
+
+    let headers = first.headers();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row.values()) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let line = |values: Vec<String>| {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(v, w)| format!("{v:<w$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    if header {
+        let headers = headers.iter().map(|h| h.to_uppercase()).collect();
+        writeln!(out, "{}", line(headers))?;
+    }
+    for row in rows {
+        writeln!(out, "{}", line(row.values()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::to_timestamp;
+
+    fn rows() -> Vec<Row> {
+        let rsvp = Reservation {
+            id: "a3a4f0ce-c2a7-4a06-a08f-2d7f0c5e0d4b".to_string(),
+            user_id: "M4n5ter".to_string(),
+            status: ReservationStatus::Pending as i32,
+            resource_id: "class room 1".to_string(),
+            start: Some(to_timestamp("2022-11-18T04:00:00Z".parse().unwrap())),
+            end: Some(to_timestamp("2022-11-18T06:00:00Z".parse().unwrap())),
+            note: "hello, world".to_string(),
+            ..Default::default()
+        };
+        vec![Row::from(&rsvp)]
+    }
+
+    fn render_to_string(format: Format) -> String {
+        let mut out = vec![];
+        render(format, &rows(), true, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn render_should_work() {
+        assert_eq!(
+            render_to_string(Format::Csv),
+            "id,user_id,resource_id,status,start,end,note\n\
+             a3a4f0ce-c2a7-4a06-a08f-2d7f0c5e0d4b,M4n5ter,class room 1,pending,2022-11-18T04:00:00Z,2022-11-18T06:00:00Z,\"hello, world\"\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render_to_string(Format::Json)).unwrap();
+        assert_eq!(json[0]["status"], "pending");
+        assert_eq!(json[0]["start"], "2022-11-18T04:00:00Z");
+        assert!(json[0].get("op").is_none());
+
+        let table = render_to_string(Format::Table);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("ID  "));
+        assert!(lines[1].ends_with("hello, world"));
+    }
+}
@@ -0,0 +1,601 @@
+//! who is calling, from a jwt bearer token or a tls client certificate, and what they may do.
+//!
+//! Reads are open to every authenticated caller. A caller may only reserve, confirm, update or
+//! cancel the reservations of their own user id, unless they hold the `admin` or
+//! `resource-manager` role. Webhooks are managed by admins only.
+//!
+//! With the user id derived from the caller, a reservation is booked for the caller and only an
+//! admin may book for another user, by asking to book on behalf of them.
+//!
+//! A user may also delegate to another user: a `book` delegation lets the grantee book for the
+//! grantor, a `manage` one lets the grantee change the grantor's reservations too. Delegates
+//! booking with the user id derived from the caller ask to book on behalf of the grantor as well.
+
+use crate::{https::PeerCert, tenant, ApiError, ConfigError};
+use abi::{Delegation, Reservation, ReservationQuery};
+use axum::{
+    extract::ConnectInfo,
+    http::{header::AUTHORIZATION, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use reservation::{ReservationError, ReservationManager};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, path::Path, str::FromStr, sync::Arc};
+use tonic::metadata::MetadataMap;
+
+/// role granting more than booking for oneself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// may do everything, including managing webhooks
+    Admin,
+    /// may change the reservations of every user
+    ResourceManager,
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Role::Admin),
+            "resource-manager" => Ok(Role::ResourceManager),
+            _ => Err(()),
+        }
+    }
+}
+
+/// the authenticated caller of a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Caller {
+    /// user id of the caller, matched against the user_id of reservations
+    pub id: String,
+    pub roles: Vec<Role>,
+    /// the only tenant the caller may access, any if none
+    pub tenant: Option<String>,
+    /// book for the caller, see `Auth::with_derived_user_id`
+    pub(crate) derive_user_id: bool,
+}
+
+impl Caller {
+    pub fn new(id: impl Into<String>, roles: Vec<Role>) -> Self {
+        Self {
+            id: id.into(),
+            roles,
+            tenant: None,
+            derive_user_id: false,
+        }
+    }
+
+    /// the caller of every request when auth is disabled, may do everything.
+    pub fn unrestricted() -> Self {
+        Self::new("", vec![Role::Admin])
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.roles.contains(&Role::Admin)
+    }
+
+    /// whether the caller may change the reservations of other users.
+    pub fn is_privileged(&self) -> bool {
+        self.is_admin() || self.roles.contains(&Role::ResourceManager)
+    }
+
+    /// the caller may access the tenant.
+    pub(crate) fn check_tenant(&self, tenant: &str) -> Result<(), ReservationError> {
+        match &self.tenant {
+            Some(t) if t != tenant => Err(denied(format!("{} can't access tenant {tenant}", self))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn require_admin(&self) -> Result<(), ReservationError> {
+        match self.is_admin() {
+            true => Ok(()),
+            false => Err(denied(format!("{self} is not an admin"))),
+        }
+    }
+
+    pub(crate) fn require_privileged(&self) -> Result<(), ReservationError> {
+        match self.is_privileged() {
+            true => Ok(()),
+            false => Err(denied(format!(
+                "{self} is not an admin or resource manager"
+            ))),
+        }
+    }
+
+    /// the manager to book the reservation with. the caller may book it by their roles, or by a
+    /// delegation of the user, which the manager checks in the transaction of the booking.
+    pub(crate) fn authorize_reserve(
+        &self,
+        manager: &ReservationManager,
+        rsvp: &mut Reservation,
+        on_behalf_of: bool,
+    ) -> Result<ReservationManager, ReservationError> {
+        match self.check_reserve(rsvp, on_behalf_of) {
+            Ok(()) => Ok(manager.clone()),
+            Err(ReservationError::PermissionDenied(_)) if on_behalf_of || !self.derive_user_id => {
+                Ok(manager.clone().with_owner_check(true))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// the caller may book the reservation by their roles, one without a user is booked for the
+    /// caller. `on_behalf_of` is required of admins booking for another user if the user id is
+    /// derived.
+    fn check_reserve(
+        &self,
+        rsvp: &mut Reservation,
+        on_behalf_of: bool,
+    ) -> Result<(), ReservationError> {
+        if rsvp.user_id.is_empty() {
+            rsvp.user_id = self.id.clone();
+        }
+        if !self.derive_user_id || rsvp.user_id == self.id {
+            return self.check_owner(&rsvp.user_id);
+        }
+        match on_behalf_of && self.is_admin() {
+            true => Ok(()),
+            false => Err(denied(format!(
+                "{self} can't book for {}, the user is taken from the caller",
+                rsvp.user_id
+            ))),
+        }
+    }
+
+    /// limit the query to the caller's own reservations unless privileged.
+    pub(crate) fn authorize_query(
+        &self,
+        query: &mut ReservationQuery,
+    ) -> Result<(), ReservationError> {
+        if self.is_privileged() {
+            return Ok(());
+        }
+        for user_id in query.user_ids.iter().chain([&query.user_id]) {
+            if !user_id.is_empty() {
+                self.check_owner(user_id)?;
+            }
+        }
+        query.user_id = self.id.clone();
+        query.user_ids.clear();
+        Ok(())
+    }
+
+    /// the caller may grant the delegation, one without a grantor is granted by the caller.
+    pub(crate) fn authorize_delegation(
+        &self,
+        delegation: &mut Delegation,
+    ) -> Result<(), ReservationError> {
+        if delegation.grantor.is_empty() {
+            delegation.grantor = self.id.clone();
+        }
+        match delegation.grantor == self.id {
+            true => Ok(()),
+            false => self.require_admin(),
+        }
+    }
+
+    /// the caller may list the delegations of the user, the caller's own if empty.
+    pub(crate) fn authorize_delegations_of(
+        &self,
+        user_id: &mut String,
+    ) -> Result<(), ReservationError> {
+        if user_id.is_empty() {
+            user_id.clone_from(&self.id);
+        }
+        match *user_id == self.id {
+            true => Ok(()),
+            false => self.require_admin(),
+        }
+    }
+
+    /// the caller may revoke the delegation, as its grantor, its grantee or an admin.
+    pub(crate) fn authorize_revoke(&self, delegation: &Delegation) -> Result<(), ReservationError> {
+        match delegation.grantor == self.id || delegation.grantee == self.id {
+            true => Ok(()),
+            false => self.require_admin(),
+        }
+    }
+
+    fn check_owner(&self, user_id: &str) -> Result<(), ReservationError> {
+        if user_id == self.id || self.is_privileged() {
+            return Ok(());
+        }
+        Err(denied(format!(
+            "{self} can't change the reservations of {user_id}"
+        )))
+    }
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "caller {:?}", self.id)
+    }
+}
+
+/// what a request presents to prove who is calling
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Credentials<'a> {
+    /// token of the `authorization: Bearer <token>` header
+    pub bearer: Option<&'a str>,
+    /// der encoded certificate of the tls client
+    pub peer_cert: Option<&'a [u8]>,
+}
+
+impl<'a> Credentials<'a> {
+    /// the bearer token of grpc metadata.
+    pub(crate) fn from_metadata(metadata: &'a MetadataMap) -> Self {
+        let value = metadata
+            .get(AUTHORIZATION.as_str())
+            .and_then(|v| v.to_str().ok());
+        Self {
+            bearer: value.and_then(bearer),
+            peer_cert: None,
+        }
+    }
+
+    /// the bearer token of http headers.
+    pub(crate) fn from_headers(headers: &'a HeaderMap) -> Self {
+        let value = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+        Self {
+            bearer: value.and_then(bearer),
+            peer_cert: None,
+        }
+    }
+}
+
+fn bearer(value: &str) -> Option<&str> {
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// turn the credentials of a request into its caller
+pub trait Authenticator: Send + Sync + 'static {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Caller, ReservationError>;
+}
+
+/// the authenticator of the service, every request is unrestricted if none.
+#[derive(Clone, Default)]
+pub struct Auth {
+    authenticator: Option<Arc<dyn Authenticator>>,
+    derive_user_id: bool,
+}
+
+impl Auth {
+    pub fn new(authenticator: impl Authenticator) -> Self {
+        Self {
+            authenticator: Some(Arc::new(authenticator)),
+            derive_user_id: false,
+        }
+    }
+
+    /// no authentication, every caller may do everything.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// book every reservation for its caller instead of the user id of the request, a
+    /// different one is denied unless an admin books on behalf of that user.
+    pub fn with_derived_user_id(mut self) -> Self {
+        self.derive_user_id = true;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.authenticator.is_some()
+    }
+
+    pub fn authenticate(&self, credentials: &Credentials) -> Result<Caller, ReservationError> {
+        let caller = match &self.authenticator {
+            Some(authenticator) => authenticator.authenticate(credentials)?,
+            None => return Ok(Caller::unrestricted()),
+        };
+        Ok(Caller {
+            derive_user_id: self.derive_user_id,
+            ..caller
+        })
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("enabled", &self.is_enabled())
+            .field("derive_user_id", &self.derive_user_id)
+            .finish()
+    }
+}
+
+/// claims of the tokens accepted by JwtAuthenticator
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    /// unknown roles are ignored
+    #[serde(default)]
+    roles: Vec<String>,
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+/// verify bearer tokens against the keys of a jwks. the caller is the `sub` claim, with the
+/// `roles` and the `tenant` claims if given.
+#[derive(Debug, Clone)]
+pub struct JwtAuthenticator {
+    keys: JwkSet,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl JwtAuthenticator {
+    pub fn new(keys: JwkSet) -> Self {
+        Self {
+            keys,
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    /// read the jwks from a json file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        let keys = serde_json::from_str(&content)
+            .map_err(|e| ConfigError::Parse(path.into(), e.to_string()))?;
+        Ok(Self::new(keys))
+    }
+
+    /// only accept tokens of this `iss`
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// only accept tokens for this `aud`
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+}
+
+impl Authenticator for JwtAuthenticator {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Caller, ReservationError> {
+        let token = credentials
+            .bearer
+            .ok_or_else(|| unauthenticated("missing bearer token"))?;
+        let header = decode_header(token).map_err(|e| unauthenticated(e.to_string()))?;
+        // a token without kid can only be signed by the only key
+        let jwk = match &header.kid {
+            Some(kid) => self.keys.find(kid),
+            None if self.keys.keys.len() == 1 => self.keys.keys.first(),
+            None => None,
+        }
+        .ok_or_else(|| unauthenticated("unknown signing key"))?;
+        if jwk.common.algorithm.is_some_and(|alg| alg != header.alg) {
+            return Err(unauthenticated("unexpected token algorithm"));
+        }
+        let key = DecodingKey::from_jwk(jwk).map_err(|e| unauthenticated(e.to_string()))?;
+
+        let mut validation = Validation::new(header.alg);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        if let Some(audience) = &self.audience {
+            validation.set_audience(&[audience]);
+        }
+        let claims = decode::<Claims>(token, &key, &validation)
+            .map_err(|e| unauthenticated(e.to_string()))?
+            .claims;
+        if claims.sub.is_empty() {
+            return Err(unauthenticated("token has no subject"));
+        }
+        let roles = claims.roles.iter().filter_map(|r| r.parse().ok()).collect();
+        Ok(Caller {
+            tenant: claims.tenant,
+            ..Caller::new(claims.sub, roles)
+        })
+    }
+}
+
+/// identify callers by the common name of their tls client certificate, which the server
+/// verified against its client ca.
+#[derive(Debug, Clone, Default)]
+pub struct MtlsAuthenticator {
+    /// roles by common name
+    roles: HashMap<String, Vec<Role>>,
+}
+
+impl MtlsAuthenticator {
+    pub fn new(roles: HashMap<String, Vec<Role>>) -> Self {
+        Self { roles }
+    }
+}
+
+impl Authenticator for MtlsAuthenticator {
+    fn authenticate(&self, credentials: &Credentials) -> Result<Caller, ReservationError> {
+        let der = credentials
+            .peer_cert
+            .ok_or_else(|| unauthenticated("missing client certificate"))?;
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| unauthenticated(format!("invalid client certificate: {e}")))?;
+        let name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .filter(|cn| !cn.is_empty())
+            .ok_or_else(|| unauthenticated("client certificate has no common name"))?;
+        let roles = self.roles.get(name).cloned().unwrap_or_default();
+        Ok(Caller::new(name, roles))
+    }
+}
+
+/// authenticate a http request with the `Auth` extension, unrestricted without one, and add its
+/// caller as an extension.
+pub(crate) async fn authenticate<B>(
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, ApiError> {
+    let auth = request
+        .extensions()
+        .get::<Auth>()
+        .cloned()
+        .unwrap_or_default();
+    let peer_cert = request
+        .extensions()
+        .get::<ConnectInfo<PeerCert>>()
+        .and_then(|ConnectInfo(PeerCert(cert))| cert.clone());
+    let credentials = Credentials {
+        peer_cert: peer_cert.as_deref(),
+        ..Credentials::from_headers(request.headers())
+    };
+    let caller = auth.authenticate(&credentials)?;
+    caller.check_tenant(&tenant::from_headers(request.headers())?)?;
+    request.extensions_mut().insert(caller);
+    Ok(next.run(request).await)
+}
+
+fn unauthenticated(msg: impl Into<String>) -> ReservationError {
+    ReservationError::Unauthenticated(msg.into())
+}
+
+fn denied(msg: String) -> ReservationError {
+    ReservationError::PermissionDenied(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef0123";
+
+    fn jwks() -> JwkSet {
+        // base64 of SECRET, its length keeps it free of padding
+        serde_json::from_value(json!({
+            "keys": [{
+                "kty": "oct",
+                "kid": "k1",
+                "alg": "HS256",
+                "k": "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWYwMTIz",
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn token(kid: Option<&str>, claims: serde_json::Value) -> String {
+        let header = Header {
+            kid: kid.map(String::from),
+            ..Header::new(Algorithm::HS256)
+        };
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn authenticate(auth: &impl Authenticator, token: &str) -> Result<Caller, ReservationError> {
+        auth.authenticate(&Credentials {
+            bearer: Some(token),
+            peer_cert: None,
+        })
+    }
+
+    #[test]
+    fn jwt_should_be_verified_against_the_jwks() {
+        let auth = JwtAuthenticator::new(jwks()).with_issuer("rsvp");
+        let exp = chrono::Utc::now().timestamp() + 60;
+
+        let claims = json!({
+            "sub": "alice", "iss": "rsvp", "exp": exp,
+            "roles": ["resource-manager", "viewer"], "tenant": "acme",
+        });
+        let caller = authenticate(&auth, &token(Some("k1"), claims.clone())).unwrap();
+        assert_eq!(caller.id, "alice");
+        assert_eq!(caller.roles, [Role::ResourceManager]);
+        assert_eq!(caller.tenant.as_deref(), Some("acme"));
+        assert!(caller.check_tenant("acme").is_ok());
+        assert!(caller.check_tenant("").is_err());
+        // the only key signs tokens without kid
+        assert!(authenticate(&auth, &token(None, claims.clone())).is_ok());
+        assert!(authenticate(&auth, &token(Some("k2"), claims)).is_err());
+
+        let expired = json!({ "sub": "alice", "iss": "rsvp", "exp": exp - 3600 });
+        assert!(authenticate(&auth, &token(Some("k1"), expired)).is_err());
+        let other_issuer = json!({ "sub": "alice", "iss": "other", "exp": exp });
+        assert!(authenticate(&auth, &token(Some("k1"), other_issuer)).is_err());
+
+        let forged = encode(
+            &Header::new(Algorithm::HS256),
+            &json!({ "sub": "alice", "iss": "rsvp", "exp": exp }),
+            &EncodingKey::from_secret(b"another secret"),
+        )
+        .unwrap();
+        let err = authenticate(&auth, &forged).unwrap_err();
+        assert!(matches!(err, ReservationError::Unauthenticated(_)));
+        assert!(auth.authenticate(&Credentials::default()).is_err());
+    }
+
+    #[test]
+    fn mtls_should_identify_the_common_name() {
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "ops");
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        let der = cert.serialize_der().unwrap();
+
+        let auth = MtlsAuthenticator::new(HashMap::from([("ops".to_string(), vec![Role::Admin])]));
+        let caller = auth
+            .authenticate(&Credentials {
+                bearer: None,
+                peer_cert: Some(&der),
+            })
+            .unwrap();
+        assert_eq!(caller, Caller::new("ops", vec![Role::Admin]));
+        assert!(auth.authenticate(&Credentials::default()).is_err());
+    }
+
+    #[test]
+    fn callers_should_only_change_their_own_reservations() {
+        let alice = Caller::new("alice", vec![]);
+        let mut rsvp = Reservation::default();
+        alice.check_reserve(&mut rsvp, false).unwrap();
+        assert_eq!(rsvp.user_id, "alice");
+        rsvp.user_id = "bob".to_string();
+        let err = alice.check_reserve(&mut rsvp, false).unwrap_err();
+        assert!(matches!(err, ReservationError::PermissionDenied(_)));
+        let manager = Caller::new("carol", vec![Role::ResourceManager]);
+        assert!(manager.check_reserve(&mut rsvp, false).is_ok());
+        assert!(manager.require_admin().is_err());
+
+        let mut query = ReservationQuery::default();
+        alice.authorize_query(&mut query).unwrap();
+        assert_eq!(query.user_id, "alice");
+        query.user_ids = vec!["bob".to_string()];
+        assert!(alice.authorize_query(&mut query).is_err());
+        assert!(manager.authorize_query(&mut query).is_ok());
+    }
+
+    #[test]
+    fn derived_user_id_should_only_be_overridden_by_admins() {
+        let derived = |caller: Caller| Caller {
+            derive_user_id: true,
+            ..caller
+        };
+        let manager = derived(Caller::new("carol", vec![Role::ResourceManager]));
+        let admin = derived(Caller::new("root", vec![Role::Admin]));
+        let mut rsvp = Reservation {
+            user_id: "bob".to_string(),
+            ..Default::default()
+        };
+        assert!(manager.check_reserve(&mut rsvp, true).is_err());
+        assert!(admin.check_reserve(&mut rsvp, false).is_err());
+        assert!(admin.check_reserve(&mut rsvp, true).is_ok());
+
+        let mut own = Reservation::default();
+        manager.check_reserve(&mut own, false).unwrap();
+        assert_eq!(own.user_id, "carol");
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    rest::{ApiError, QueryParams},
+    Caller,
+};
+use axum::{
+    body::StreamBody,
+    extract::Query,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    Extension, Json,
+};
+use reservation::{
+    export_rows, import_ics, import_rows, BulkFormat, IcsImportOptions, ImportReport,
+    ReservationError, ReservationManager,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// query string of `POST /reservations/import`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ImportParams {
+    /// `ics`, `csv` or `ndjson`, guessed from the content type if not given
+    pub format: Option<String>,
+    /// only report what would be reserved
+    pub dry_run: bool,
+    /// only for ics, reserve every event for this user
+    pub user_id: Option<String>,
+    /// only for ics, reserve every event on this resource
+    pub resource_id: Option<String>,
+}
+
+/// `GET /reservations/export` stream every reservation matching the query as csv or ndjson,
+/// page and page_size are ignored.
+pub(crate) async fn export(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Query(params): Query<QueryParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let format: BulkFormat = params.format.as_deref().unwrap_or("csv").parse()?;
+    let rows = export_rows(manager, params.to_query()?, format);
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type())],
+        StreamBody::new(rows),
+    ))
+}
+
+/// `POST /reservations/import` reserve every reservation or event of the body, report the
+/// conflicts and failures instead of stopping at the first one. only for admins and resource
+/// managers.
+pub(crate) async fn import(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<ImportParams>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportReport>, ApiError> {
+    // rows may book for any user
+    caller.require_privileged()?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let format = match params.format.as_deref() {
+        Some(format) => format,
+        None if content_type.starts_with("text/calendar") => "ics",
+        None if content_type.starts_with("text/csv") => "csv",
+        None if content_type.starts_with("application/x-ndjson") => "ndjson",
+        None => {
+            return Err(ReservationError::InvalidFilter(
+                "format is required for this content type".to_string(),
+            )
+            .into())
+        }
+    };
+    let report = if format == "ics" {
+        let options = IcsImportOptions {
+            user_id: params.user_id,
+            resource_id: params.resource_id,
+            dry_run: params.dry_run,
+        };
+        import_ics(manager.as_ref(), &body, &options).await?
+    } else {
+        import_rows(manager.as_ref(), &body, format.parse()?, params.dry_run).await?
+    };
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use reservation::ReservationManager;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn bulk_api_should_work() {
+        let app = router(Arc::new(ReservationManager::new(migrated_pool.clone())));
+        let csv = "user_id,resource_id,start,end,note\nM4n5ter,room 1,2022-11-18T04:00:00Z,2022-11-18T06:00:00Z,\"a, b\"\n";
+        let import = |uri: &str, content_type: &str| {
+            let req = Request::post(uri)
+                .header("content-type", content_type)
+                .body(Body::from(csv))
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        let res = import("/reservations/import?dry_run=true", "text/csv")
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["dry_run"], true);
+        assert_eq!(report["reserved"].as_array().unwrap().len(), 1);
+
+        let res = import("/reservations/import", "text/plain").await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = import("/reservations/import?format=csv", "text/plain")
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["reserved"][0]["note"], "a, b");
+
+        let req = Request::get("/reservations/export?format=ndjson&user_id=M4n5ter")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let rows: Vec<_> = std::str::from_utf8(&body).unwrap().lines().collect();
+        assert_eq!(rows.len(), 1);
+        let row: serde_json::Value = serde_json::from_str(rows[0]).unwrap();
+        assert_eq!(row, report["reserved"][0]);
+
+        let req = Request::get("/reservations/export?format=xlsx")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
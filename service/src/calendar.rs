@@ -0,0 +1,126 @@
+use crate::rest::ApiError;
+use abi::{to_timestamp, ReservationQueryBuilder};
+use axum::{
+    extract::{Path, Query},
+    http::header,
+    response::IntoResponse,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use reservation::{export_ics, ReservationError, ReservationManager};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// query string of the calendar feeds, only reservations within the range are included
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CalendarParams {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// `GET /users/:user_id/calendar.ics` reservations of a user as an iCalendar feed.
+pub(crate) async fn user(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Path(user_id): Path<String>,
+    Query(params): Query<CalendarParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut builder = ReservationQueryBuilder::default();
+    builder.user_id(&user_id);
+    calendar(&manager, &user_id, builder, &params).await
+}
+
+/// `GET /resources/:resource_id/calendar.ics` reservations of a resource as an iCalendar feed.
+pub(crate) async fn resource(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Path(resource_id): Path<String>,
+    Query(params): Query<CalendarParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut builder = ReservationQueryBuilder::default();
+    builder.resource_id(&resource_id);
+    calendar(&manager, &resource_id, builder, &params).await
+}
+
+async fn calendar(
+    manager: &ReservationManager,
+    name: &str,
+    mut builder: ReservationQueryBuilder,
+    params: &CalendarParams,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(start) = params.start {
+        builder.start(to_timestamp(start));
+    }
+    if let Some(end) = params.end {
+        builder.end(to_timestamp(end));
+    }
+    let query = builder
+        .build()
+        .map_err(|e| ReservationError::InvalidFilter(e.to_string()))?;
+    let ics = export_ics(manager, name, query).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        ics,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router;
+    use axum::{body::Body, http::Request};
+    use reservation::{ReservationManager, Rsvp};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn calendar_feeds_should_work() {
+        let manager = Arc::new(ReservationManager::new(migrated_pool.clone()));
+        for (user_id, resource_id, day) in [("M4n5ter", "room 1", 18), ("Syuu", "room 1", 19)] {
+            manager
+                .reserve(abi::Reservation {
+                    user_id: user_id.to_string(),
+                    status: abi::ReservationStatus::Pending as i32,
+                    resource_id: resource_id.to_string(),
+                    start: Some(format!("2022-11-{day}T04:00:00Z").parse().unwrap()),
+                    end: Some(format!("2022-11-{day}T06:00:00Z").parse().unwrap()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let app = router(manager);
+        let get = |uri: &str| {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(req)
+        };
+
+        let res = get("/users/M4n5ter/calendar.ics").await.unwrap();
+        assert_eq!(
+            res.headers()["content-type"],
+            "text/calendar; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let ics = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("X-WR-CALNAME:M4n5ter\r\n"));
+
+        let res = get("/resources/room%201/calendar.ics?start=2022-11-19T00:00:00Z")
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let ics = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+        assert!(ics.contains("CONTACT:Syuu\r\n"));
+
+        // the exported feed can be imported on another resource
+        let req = Request::post("/reservations/import?resource_id=room%202")
+            .header("content-type", "text/calendar")
+            .body(Body::from(ics))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["reserved"][0]["resource_id"], "room 2");
+        assert_eq!(report["reserved"][0]["user_id"], "Syuu");
+        assert_eq!(report["conflicts"], serde_json::json!([]));
+    }
+}
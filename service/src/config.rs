@@ -0,0 +1,538 @@
+use crate::{https, Auth, GrpcWebConfig, JwtAuthenticator, MtlsAuthenticator, Role};
+use reservation::{DbConfig, ReservationManager};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio_rustls::rustls;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// environment variable naming the config file
+pub const CONFIG_ENV: &str = "RSVP_CONFIG";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("failed to parse {0}: {1}")]
+    Parse(PathBuf, String),
+    #[error("unsupported config format: {0}, expect yaml or toml")]
+    UnsupportedFormat(PathBuf),
+    #[error("invalid value of {0}: {1}")]
+    InvalidEnv(String, String),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+/// config of the service, loaded from a yaml or toml file and overridden by the environment.
+///
+/// Every field has a default, so a file only needs what differs, e.g.
+///
+/// ```yaml
+/// db:
+///   url: postgres://localhost/reservation
+///   max_connections: 20
+/// server:
+///   grpc_addr: 0.0.0.0:50051
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub db: DbConfig,
+    pub server: ServerConfig,
+    pub query: QueryConfig,
+    /// serve grpc and the http api over tls if given
+    pub tls: Option<TlsConfig>,
+    pub auth: AuthConfig,
+    pub features: FeatureConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// env: RSVP_ADDR
+    pub grpc_addr: SocketAddr,
+    /// env: RSVP_HTTP_ADDR
+    pub http_addr: SocketAddr,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            grpc_addr: ([0, 0, 0, 0], 50051).into(),
+            http_addr: ([0, 0, 0, 0], 8080).into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    /// page size of queries that don't specify one, env: RSVP_PAGE_SIZE
+    pub default_page_size: i64,
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        Self {
+            default_page_size: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// pem encoded certificate chain, env: RSVP_TLS_CERT
+    pub cert: PathBuf,
+    /// pem encoded private key, env: RSVP_TLS_KEY
+    pub key: PathBuf,
+    /// pem encoded ca the client certificates must be signed by, clients need no certificate if
+    /// not given, env: RSVP_TLS_CLIENT_CA
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// how callers are authenticated, env: RSVP_AUTH
+    pub mode: AuthMode,
+    /// jwks file verifying the bearer tokens of jwt mode, env: RSVP_JWKS
+    pub jwks: Option<PathBuf>,
+    /// `iss` required of the tokens if given, env: RSVP_JWT_ISSUER
+    pub issuer: Option<String>,
+    /// `aud` required of the tokens if given, env: RSVP_JWT_AUDIENCE
+    pub audience: Option<String>,
+    /// roles of the client certificate common names in mtls mode, e.g. `ops: [admin]`
+    pub roles: HashMap<String, Vec<Role>>,
+    /// book reservations for the caller, ignoring the user id of the request unless an admin
+    /// books on behalf of that user, env: RSVP_DERIVE_USER_ID
+    pub derive_user_id: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// every caller may do everything
+    #[default]
+    None,
+    /// bearer tokens verified against the jwks
+    Jwt,
+    /// tls client certificates verified against `tls.client_ca`
+    Mtls,
+}
+
+impl FromStr for AuthMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "" => Ok(AuthMode::None),
+            "jwt" => Ok(AuthMode::Jwt),
+            "mtls" => Ok(AuthMode::Mtls),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureConfig {
+    /// serve the http/json api, env: RSVP_HTTP
+    pub http: bool,
+    /// accept grpc-web requests, env: RSVP_GRPC_WEB
+    pub grpc_web: bool,
+    /// origins allowed to call grpc-web, empty means any, env: RSVP_CORS_ORIGINS (comma separated)
+    pub cors_origins: Vec<String>,
+    /// deliver changes to the webhooks, env: RSVP_WEBHOOKS
+    pub webhooks: bool,
+    /// append changes to this file, env: RSVP_OUTBOX_FILE
+    pub outbox_file: Option<PathBuf>,
+    /// publish changes to this nats server, env: RSVP_NATS_URL
+    pub nats_url: Option<String>,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        Self {
+            http: true,
+            grpc_web: false,
+            cors_origins: Vec::new(),
+            webhooks: true,
+            outbox_file: None,
+            nats_url: None,
+        }
+    }
+}
+
+impl Config {
+    /// load the file named by `RSVP_CONFIG` if set, apply the environment and validate.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match env::var_os(CONFIG_ENV) {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env(config_vars(env::vars_os())?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// parse a yaml or toml file, by its extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let parse: fn(&str) -> Result<Self, String> =
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("yaml" | "yml") => |s| serde_yaml::from_str(s).map_err(|e| e.to_string()),
+                Some("toml") => |s| toml::from_str(s).map_err(|e| e.to_string()),
+                _ => return Err(ConfigError::UnsupportedFormat(path.into())),
+            };
+        let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+        parse(&content).map_err(|e| ConfigError::Parse(path.into(), e))
+    }
+
+    /// override the config with the variables documented on the fields, unknown ones are ignored.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        for (name, value) in vars {
+            match name.as_str() {
+                "DATABASE_URL" => self.db.url = value,
+                "RSVP_DB_MAX_CONNECTIONS" => self.db.max_connections = parse(&name, &value)?,
+                "RSVP_DB_MIN_CONNECTIONS" => self.db.min_connections = parse(&name, &value)?,
+                "RSVP_DB_CONNECT_TIMEOUT" => self.db.connect_timeout = parse(&name, &value)?,
+                "RSVP_DB_MIGRATE" => self.db.migrate = flag(&name, &value)?,
+                "RSVP_ADDR" => self.server.grpc_addr = parse(&name, &value)?,
+                "RSVP_HTTP_ADDR" => self.server.http_addr = parse(&name, &value)?,
+                "RSVP_PAGE_SIZE" => self.query.default_page_size = parse(&name, &value)?,
+                "RSVP_TLS_CERT" => self.tls_mut().cert = value.into(),
+                "RSVP_TLS_KEY" => self.tls_mut().key = value.into(),
+                "RSVP_TLS_CLIENT_CA" => self.tls_mut().client_ca = Some(value.into()),
+                "RSVP_AUTH" => self.auth.mode = parse(&name, &value)?,
+                "RSVP_JWKS" => self.auth.jwks = Some(value.into()),
+                "RSVP_JWT_ISSUER" => self.auth.issuer = Some(value),
+                "RSVP_JWT_AUDIENCE" => self.auth.audience = Some(value),
+                "RSVP_DERIVE_USER_ID" => self.auth.derive_user_id = flag(&name, &value)?,
+                "RSVP_HTTP" => self.features.http = flag(&name, &value)?,
+                "RSVP_GRPC_WEB" => self.features.grpc_web = flag(&name, &value)?,
+                "RSVP_CORS_ORIGINS" => {
+                    self.features.cors_origins =
+                        GrpcWebConfig::new(value.split(',')).allowed_origins
+                }
+                "RSVP_WEBHOOKS" => self.features.webhooks = flag(&name, &value)?,
+                "RSVP_OUTBOX_FILE" => self.features.outbox_file = Some(value.into()),
+                "RSVP_NATS_URL" => self.features.nats_url = Some(value),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// check the values that can't be checked by their types.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| Err(ConfigError::Invalid(msg.to_string()));
+        if self.db.url.is_empty() {
+            return invalid("db.url is required");
+        }
+        if !self.db.url.starts_with("postgres://") && !self.db.url.starts_with("postgresql://") {
+            return invalid("db.url must be a postgres url");
+        }
+        if self.db.max_connections == 0 {
+            return invalid("db.max_connections must be positive");
+        }
+        if self.db.min_connections > self.db.max_connections {
+            return invalid("db.min_connections must not exceed db.max_connections");
+        }
+        if self.query.default_page_size <= 0 {
+            return invalid("query.default_page_size must be positive");
+        }
+        if self.features.http && self.server.grpc_addr == self.server.http_addr {
+            return invalid("server.grpc_addr and server.http_addr must differ");
+        }
+        if let Some(tls) = &self.tls {
+            if tls.cert.as_os_str().is_empty() || tls.key.as_os_str().is_empty() {
+                return invalid("tls needs both cert and key");
+            }
+        }
+        if self.auth.derive_user_id && self.auth.mode == AuthMode::None {
+            return invalid("auth.derive_user_id needs an auth mode");
+        }
+        match self.auth.mode {
+            AuthMode::None => {}
+            AuthMode::Jwt if self.auth.jwks.is_none() => return invalid("auth.jwks is required"),
+            AuthMode::Jwt => {}
+            AuthMode::Mtls if self.tls.as_ref().is_none_or(|t| t.client_ca.is_none()) => {
+                return invalid("mtls auth needs tls.client_ca");
+            }
+            AuthMode::Mtls => {}
+        }
+        if cfg!(not(feature = "nats")) && self.features.nats_url.is_some() {
+            return invalid("features.nats_url needs the nats feature");
+        }
+        Ok(())
+    }
+
+    /// connect to the database with the pool options.
+    pub async fn connect(&self) -> Result<PgPool, sqlx::Error> {
+        self.db.pool_options().connect(&self.db.url).await
+    }
+
+    /// a ReservationManager on the pool, with the configured defaults.
+    pub fn manager(&self, pool: PgPool) -> ReservationManager {
+        ReservationManager::new(pool).with_default_page_size(self.query.default_page_size)
+    }
+
+    /// grpc-web config, none if disabled.
+    pub fn grpc_web(&self) -> Option<GrpcWebConfig> {
+        self.features
+            .grpc_web
+            .then(|| GrpcWebConfig::new(&self.features.cors_origins))
+    }
+
+    /// read the certificate and key, none if tls is disabled.
+    pub fn server_tls(&self) -> Result<Option<ServerTlsConfig>, ConfigError> {
+        let Some(tls) = &self.tls else {
+            return Ok(None);
+        };
+        let read = |path: &PathBuf| fs::read(path).map_err(|e| ConfigError::Io(path.clone(), e));
+        let identity = Identity::from_pem(read(&tls.cert)?, read(&tls.key)?);
+        let mut config = ServerTlsConfig::new().identity(identity);
+        if let Some(ca) = &tls.client_ca {
+            config = config.client_ca_root(Certificate::from_pem(read(ca)?));
+        }
+        Ok(Some(config))
+    }
+
+    /// the tls config of the http api from the same files, none if tls is disabled.
+    pub fn http_tls(&self) -> Result<Option<Arc<rustls::ServerConfig>>, ConfigError> {
+        let Some(tls) = &self.tls else {
+            return Ok(None);
+        };
+        let read = |path: &PathBuf| fs::read(path).map_err(|e| ConfigError::Io(path.clone(), e));
+        let client_ca = tls.client_ca.as_ref().map(read).transpose()?;
+        let config =
+            https::server_config(&read(&tls.cert)?, &read(&tls.key)?, client_ca.as_deref())
+                .map_err(|e| ConfigError::Invalid(format!("tls: {e}")))?;
+        Ok(Some(Arc::new(config)))
+    }
+
+    /// the authenticator of the mode, reads the jwks of jwt mode.
+    pub fn auth(&self) -> Result<Auth, ConfigError> {
+        let auth = &self.auth;
+        let authenticated = match auth.mode {
+            AuthMode::None => return Ok(Auth::none()),
+            AuthMode::Jwt => {
+                let path = auth
+                    .jwks
+                    .as_ref()
+                    .ok_or_else(|| ConfigError::Invalid("auth.jwks is required".to_string()))?;
+                let mut jwt = JwtAuthenticator::from_file(path)?;
+                if let Some(issuer) = &auth.issuer {
+                    jwt = jwt.with_issuer(issuer);
+                }
+                if let Some(audience) = &auth.audience {
+                    jwt = jwt.with_audience(audience);
+                }
+                Auth::new(jwt)
+            }
+            AuthMode::Mtls => Auth::new(MtlsAuthenticator::new(auth.roles.clone())),
+        };
+        match auth.derive_user_id {
+            true => Ok(authenticated.with_derived_user_id()),
+            false => Ok(authenticated),
+        }
+    }
+
+    fn tls_mut(&mut self) -> &mut TlsConfig {
+        self.tls.get_or_insert_with(|| TlsConfig {
+            cert: PathBuf::new(),
+            key: PathBuf::new(),
+            client_ca: None,
+        })
+    }
+}
+
+/// the variables read by `Config::apply_env` as strings. other variables are skipped unread, so
+/// ones that aren't unicode do no harm, like the path in `RSVP_CONFIG`.
+fn config_vars(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Result<Vec<(String, String)>, ConfigError> {
+    vars.into_iter()
+        .filter_map(|(name, value)| {
+            let name = name
+                .into_string()
+                .ok()
+                .filter(|name| name == "DATABASE_URL" || name.starts_with("RSVP_"))
+                .filter(|name| name != CONFIG_ENV)?;
+            let value = value.into_string().map_err(|value| {
+                ConfigError::InvalidEnv(name.clone(), value.to_string_lossy().into())
+            });
+            Some(value.map(|value| (name, value)))
+        })
+        .collect()
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ConfigError::InvalidEnv(name.to_string(), value.to_string()))
+}
+
+fn flag(name: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.trim() {
+        "true" | "1" => Ok(true),
+        "false" | "0" | "" => Ok(false),
+        _ => Err(ConfigError::InvalidEnv(name.to_string(), value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn yaml_and_toml_should_load_the_same_config() {
+        let dir = env::temp_dir().join(format!("rsvp-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let yaml = dir.join("config.yaml");
+        fs::write(
+            &yaml,
+            "db:\n  url: postgres://localhost/rsvp\n  max_connections: 20\nserver:\n  grpc_addr: 127.0.0.1:50051\nfeatures:\n  grpc_web: true\n  cors_origins: [https://app.example.com]\n",
+        )
+        .unwrap();
+        let toml = dir.join("config.toml");
+        fs::write(
+            &toml,
+            "[db]\nurl = \"postgres://localhost/rsvp\"\nmax_connections = 20\n\n[server]\ngrpc_addr = \"127.0.0.1:50051\"\n\n[features]\ngrpc_web = true\ncors_origins = [\"https://app.example.com\"]\n",
+        )
+        .unwrap();
+
+        let config = Config::from_file(&yaml).unwrap();
+        assert_eq!(config, Config::from_file(&toml).unwrap());
+        assert_eq!(config.db.max_connections, 20);
+        assert_eq!(config.db.connect_timeout, 30);
+        assert_eq!(config.server.http_addr, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(
+            config.grpc_web().unwrap().allowed_origins,
+            ["https://app.example.com"]
+        );
+        assert!(config.validate().is_ok());
+
+        fs::write(&yaml, "db:\n  uri: postgres://localhost/rsvp\n").unwrap();
+        assert!(matches!(
+            Config::from_file(&yaml),
+            Err(ConfigError::Parse(..))
+        ));
+        assert!(matches!(
+            Config::from_file(dir.join("config.json")),
+            Err(ConfigError::UnsupportedFormat(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn env_should_override_and_be_validated() {
+        let mut config = Config::default();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        config
+            .apply_env(vars(&[
+                ("DATABASE_URL", "postgres://db/rsvp"),
+                ("RSVP_DB_MAX_CONNECTIONS", "5"),
+                ("RSVP_HTTP_ADDR", "127.0.0.1:8081"),
+                ("RSVP_PAGE_SIZE", "50"),
+                ("RSVP_WEBHOOKS", "0"),
+                ("RSVP_CORS_ORIGINS", "https://a.com, https://b.com"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+        assert_eq!(config.db.url, "postgres://db/rsvp");
+        assert_eq!(config.db.max_connections, 5);
+        assert_eq!(config.server.http_addr, "127.0.0.1:8081".parse().unwrap());
+        assert_eq!(config.query.default_page_size, 50);
+        assert!(!config.features.webhooks);
+        assert_eq!(
+            config.features.cors_origins,
+            ["https://a.com", "https://b.com"]
+        );
+        assert!(config.grpc_web().is_none());
+        assert!(config.validate().is_ok());
+
+        let err = config
+            .apply_env(vars(&[("RSVP_DB_MAX_CONNECTIONS", "many")]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid value of RSVP_DB_MAX_CONNECTIONS: many"
+        );
+
+        for (name, value) in [
+            ("RSVP_DB_MIN_CONNECTIONS", "6"),
+            ("RSVP_PAGE_SIZE", "0"),
+            ("RSVP_ADDR", "127.0.0.1:8081"),
+            ("RSVP_TLS_CERT", "cert.pem"),
+            ("DATABASE_URL", "mysql://db/rsvp"),
+            ("RSVP_AUTH", "jwt"),
+            ("RSVP_AUTH", "mtls"),
+            ("RSVP_DERIVE_USER_ID", "true"),
+        ] {
+            let mut invalid = config.clone();
+            invalid.apply_env(vars(&[(name, value)])).unwrap();
+            assert!(
+                invalid.validate().is_err(),
+                "{name}={value} should be invalid"
+            );
+        }
+        assert!(config.apply_env(vars(&[("RSVP_AUTH", "basic")])).is_err());
+
+        // a nats url is only used by a build with the nats feature
+        let mut nats = config.clone();
+        nats.apply_env(vars(&[("RSVP_NATS_URL", "nats://localhost:4222")]))
+            .unwrap();
+        assert_eq!(nats.validate().is_ok(), cfg!(feature = "nats"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_config_vars_should_be_read_from_the_environment() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let not_unicode = || OsString::from_vec(vec![b'r', 0xff]);
+        let read = config_vars([
+            ("RSVP_PAGE_SIZE".into(), "20".into()),
+            ("DATABASE_URL".into(), "postgres://db/rsvp".into()),
+            ("PATH".into(), not_unicode()),
+            (CONFIG_ENV.into(), not_unicode()),
+            (not_unicode(), "value".into()),
+        ])
+        .unwrap();
+        assert_eq!(
+            read,
+            vars(&[
+                ("RSVP_PAGE_SIZE", "20"),
+                ("DATABASE_URL", "postgres://db/rsvp")
+            ])
+        );
+
+        let err = config_vars([("RSVP_ADDR".into(), not_unicode())]).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidEnv(name, _) if name == "RSVP_ADDR"));
+    }
+}
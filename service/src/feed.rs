@@ -0,0 +1,230 @@
+use crate::rest::ApiError;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension,
+};
+use futures::{Stream, StreamExt};
+use reservation::{ListenFilter, ReservationChange, ReservationManager, Rsvp};
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc, time::Duration};
+
+/// header a reconnecting EventSource sends with the id of the last event it received
+const LAST_EVENT_ID: &str = "last-event-id";
+/// websocket clients are pinged this often, one not answering by the next ping is dropped
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// query string of the change feed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FeedParams {
+    pub user_id: Option<String>,
+    pub resource_id: Option<String>,
+    /// resume after this change id, the `Last-Event-ID` header takes precedence
+    pub last_event_id: Option<i64>,
+}
+
+impl FeedParams {
+    fn to_filter(&self, headers: &HeaderMap) -> ListenFilter {
+        let last_event_id = headers
+            .get(LAST_EVENT_ID)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        ListenFilter {
+            user_ids: self.user_id.iter().cloned().collect(),
+            resource_ids: self.resource_id.iter().cloned().collect(),
+            since: last_event_id.or(self.last_event_id),
+            ..Default::default()
+        }
+    }
+}
+
+async fn changes(
+    manager: &ReservationManager,
+    params: &FeedParams,
+    headers: &HeaderMap,
+) -> Result<impl Stream<Item = Result<ReservationChange, String>>, ApiError> {
+    let changes = manager.listen(params.to_filter(headers)).await?;
+    // errors are shown like the ones of the api, without the details of internal ones
+    Ok(changes.map(|change| change.map_err(|e| ApiError::from(e).to_body().1.message)))
+}
+
+/// `GET /changes` stream changes as server-sent events, the event type is the op and the event id
+/// is the change id to resume from.
+pub(crate) async fn sse(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Query(params): Query<FeedParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    let events = changes(&manager, &params, &headers).await?.map(|change| {
+        let event = match change {
+            Ok(change) => Event::default()
+                .id(change.id.to_string())
+                .event(change.op.to_string())
+                .json_data(&change)
+                .unwrap_or_else(|e| Event::default().event("error").data(e.to_string())),
+            // the stream ends after an error, the client reconnects with the last event id
+            Err(e) => Event::default().event("error").data(e),
+        };
+        Ok::<_, Infallible>(event)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// `GET /changes/ws` stream changes over a websocket, one json text message per change.
+pub(crate) async fn ws(
+    Extension(manager): Extension<Arc<ReservationManager>>,
+    Query(params): Query<FeedParams>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // listen before upgrading, so a bad request is answered with an error body
+    let changes = changes(&manager, &params, &headers).await?;
+    Ok(upgrade.on_upgrade(|socket| relay(socket, changes)))
+}
+
+/// send the changes until the stream ends or the client goes away, which is noticed by reading
+/// the socket and pinging it, so the listener of a quiet feed is released too.
+async fn relay(
+    mut socket: WebSocket,
+    changes: impl Stream<Item = Result<ReservationChange, String>>,
+) {
+    futures::pin_mut!(changes);
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.reset();
+    let mut awaiting_pong = false;
+    loop {
+        tokio::select! {
+            change = changes.next() => {
+                let Some(change) = change else {
+                    break;
+                };
+                let message = match change {
+                    Ok(change) => serde_json::to_string(&change).unwrap_or_default(),
+                    Err(e) => serde_json::json!({ "error": e }).to_string(),
+                };
+                if socket.send(Message::Text(message)).await.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                None | Some(Err(_)) | Some(Ok(Message::Close(_))) => return,
+                Some(Ok(Message::Pong(_))) => awaiting_pong = false,
+                // anything else the client sends is ignored
+                Some(Ok(_)) => {}
+            },
+            _ = ping.tick() => {
+                if awaiting_pong || socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return;
+                }
+                awaiting_pong = true;
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::router;
+    use futures::StreamExt;
+    use reservation::{ReservationManager, Rsvp};
+    use std::sync::Arc;
+
+    fn reservation(user_id: &str, resource_id: &str) -> abi::Reservation {
+        abi::Reservation {
+            user_id: user_id.to_string(),
+            status: abi::ReservationStatus::Pending as i32,
+            resource_id: resource_id.to_string(),
+            start: Some("2022-11-18T04:00:00Z".parse().unwrap()),
+            end: Some("2022-11-18T06:00:00Z".parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn sse_should_stream_filtered_changes() {
+        let manager = Arc::new(ReservationManager::new(migrated_pool.clone()));
+        let rsvp_1 = manager
+            .reserve(reservation("M4n5ter", "room 1"))
+            .await
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router(manager.clone()).into_make_service()),
+        );
+
+        // resume from the beginning, only changes of room 2
+        let req = hyper::Request::get(format!("http://{addr}/changes?resource_id=room%202"))
+            .header("last-event-id", "0")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let res = hyper::Client::new().request(req).await.unwrap();
+        assert_eq!(res.headers()["content-type"], "text/event-stream");
+
+        let rsvp_2 = manager
+            .reserve(reservation("Syuu", "room 2"))
+            .await
+            .unwrap();
+        manager.delete(rsvp_2.id.parse().unwrap()).await.unwrap();
+
+        let mut body = res.into_body();
+        let mut text = String::new();
+        while text.matches("\n\n").count() < 2 {
+            let chunk = body.next().await.unwrap().unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        assert!(!text.contains(&rsvp_1.id));
+        let events: Vec<_> = text.split("\n\n").filter(|e| !e.is_empty()).collect();
+        assert!(events[0].contains("event:create"));
+        assert!(events[0].contains(&rsvp_2.id));
+        assert!(events[1].contains("event:delete"));
+        assert!(events[1].contains("\"user_id\":\"Syuu\""));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn ws_should_release_the_listener_when_the_client_leaves() {
+        let manager = Arc::new(ReservationManager::new(migrated_pool.clone()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router(manager.clone()).into_make_service()),
+        );
+        let listeners = || async {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT count(*) FROM pg_stat_activity WHERE datname = current_database() AND query LIKE 'LISTEN%'",
+            )
+            .fetch_one(&migrated_pool)
+            .await
+            .unwrap()
+        };
+
+        // no change ever matches the filter, so nothing is sent to the client
+        let url = format!("ws://{addr}/changes/ws?resource_id=nowhere");
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        assert_eq!(listeners().await, 1);
+
+        socket.close(None).await.unwrap();
+        let mut released = false;
+        for _ in 0..50 {
+            if listeners().await == 0 {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(released, "the listener should be released");
+    }
+}
@@ -0,0 +1,195 @@
+use crate::RsvpService;
+use abi::reservation_service_server::ReservationServiceServer;
+use http::{header::HeaderName, Method};
+use std::{env, time::Duration};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::{Server, ServerTlsConfig};
+use tonic_web::GrpcWebLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// headers a grpc-web client sends, beside the custom metadata
+const ALLOW_HEADERS: [&str; 5] = [
+    "x-grpc-web",
+    "content-type",
+    "x-user-agent",
+    "grpc-timeout",
+    "authorization",
+];
+/// headers a grpc-web client needs to read the status of a call
+const EXPOSE_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+/// browsers cache the preflight result for a day
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// grpc-web config, browsers from the allowed origins can call the grpc service directly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrpcWebConfig {
+    /// allowed origins, e.g. `https://app.example.com`, empty means any origin
+    pub allowed_origins: Vec<String>,
+}
+
+impl GrpcWebConfig {
+    /// read the config from `RSVP_GRPC_WEB` and `RSVP_CORS_ORIGINS`.
+    ///
+    /// grpc-web is disabled unless `RSVP_GRPC_WEB` is `true` or `1`, origins are comma separated.
+    pub fn from_env() -> Option<Self> {
+        let enabled = env::var("RSVP_GRPC_WEB").unwrap_or_default();
+        if !matches!(enabled.as_str(), "true" | "1") {
+            return None;
+        }
+        let origins = env::var("RSVP_CORS_ORIGINS").unwrap_or_default();
+        Some(Self::new(origins.split(',')))
+    }
+
+    pub fn new(origins: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let allowed_origins = origins
+            .into_iter()
+            .map(|o| o.as_ref().trim().to_string())
+            .filter(|o| !o.is_empty() && o != "*")
+            .collect();
+        Self { allowed_origins }
+    }
+
+    /// cors layer answering the preflight requests of grpc-web clients.
+    pub fn cors_layer(&self) -> CorsLayer {
+        let origin = if self.allowed_origins.is_empty() {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.allowed_origins
+                    .iter()
+                    .filter_map(|o| o.parse().ok())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods([Method::POST])
+            .allow_headers(ALLOW_HEADERS.map(HeaderName::from_static))
+            .expose_headers(EXPOSE_HEADERS.map(HeaderName::from_static))
+            .max_age(MAX_AGE)
+    }
+}
+
+/// serve the grpc service on the listener, also accept grpc-web requests if `web` is given.
+///
+/// grpc-web covers unary and server streaming calls, so browsers can `query` and `listen` too.
+/// Connections are served over tls if `tls` is given.
+pub async fn serve_grpc(
+    service: RsvpService,
+    web: Option<GrpcWebConfig>,
+    tls: Option<ServerTlsConfig>,
+    listener: TcpListener,
+) -> Result<(), tonic::transport::Error> {
+    let incoming = TcpListenerStream::new(listener);
+    let service = ReservationServiceServer::new(service);
+    let mut server = match tls {
+        Some(tls) => Server::builder().tls_config(tls)?,
+        None => Server::builder(),
+    };
+    match web {
+        Some(web) => {
+            server
+                .accept_http1(true)
+                .layer(web.cors_layer())
+                .layer(GrpcWebLayer::new())
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await
+        }
+        None => {
+            server
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::GetRequest;
+    use hyper::{body::to_bytes, Body, Client, Request};
+    use prost::Message;
+    use reservation::ReservationManager;
+
+    const ORIGIN: &str = "https://app.example.com";
+
+    async fn serve(pool: sqlx::PgPool, web: Option<GrpcWebConfig>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = RsvpService::new(ReservationManager::new(pool));
+        tokio::spawn(serve_grpc(service, web, None, listener));
+        format!("http://{addr}/reservation.ReservationService/get")
+    }
+
+    fn preflight(uri: &str, origin: &str) -> Request<Body> {
+        Request::options(uri)
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type,x-grpc-web")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn config_should_parse_origins() {
+        let config = GrpcWebConfig::new(" https://a.com,https://b.com ,".split(','));
+        assert_eq!(
+            config.allowed_origins,
+            vec!["https://a.com", "https://b.com"]
+        );
+        assert!(GrpcWebConfig::new(["*"]).allowed_origins.is_empty());
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn grpc_web_should_work_for_allowed_origins() {
+        let uri = serve(migrated_pool.clone(), Some(GrpcWebConfig::new([ORIGIN]))).await;
+        let client = Client::new();
+
+        let res = client.request(preflight(&uri, ORIGIN)).await.unwrap();
+        assert_eq!(res.headers()["access-control-allow-origin"], ORIGIN);
+        let res = client
+            .request(preflight(&uri, "https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+
+        // a grpc-web frame: flag, big endian length, then the message
+        let msg = GetRequest {
+            id: "a3a4f0ce-c2a7-4a06-a08f-2d7f0c5e0d4b".to_string(),
+        }
+        .encode_to_vec();
+        let mut frame = vec![0u8];
+        frame.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&msg);
+        let req = Request::post(&uri)
+            .header("origin", ORIGIN)
+            .header("content-type", "application/grpc-web+proto")
+            .header("x-grpc-web", "1")
+            .body(Body::from(frame))
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        assert_eq!(res.headers()["content-type"], "application/grpc-web+proto");
+        assert_eq!(res.headers()["access-control-allow-origin"], ORIGIN);
+        // not found, the status is in the headers or in the trailer frame
+        match res.headers().get("grpc-status") {
+            Some(status) => assert_eq!(status, "5"),
+            None => {
+                let body = to_bytes(res.into_body()).await.unwrap();
+                assert!(String::from_utf8_lossy(&body).contains("grpc-status:5"));
+            }
+        }
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn grpc_web_should_be_disabled_by_default() {
+        let uri = serve(migrated_pool.clone(), None).await;
+        // without grpc-web the server only speaks http2, an http1 request is refused
+        assert!(Client::new()
+            .request(preflight(&uri, ORIGIN))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,227 @@
+//! serve the http api over tls, with the certificate of a tls client passed to the
+//! authenticator like the grpc service does.
+
+use axum::{extract::connect_info::Connected, Router};
+use hyper::server::accept;
+use std::{io, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{
+    rustls::{
+        server::{AllowAnyAuthenticatedClient, ServerConfig},
+        Certificate, PrivateKey, RootCertStore,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+
+/// connections waiting for the server while others finish their handshake
+const PENDING_CONNECTIONS: usize = 64;
+/// a client not finishing its tls handshake in time is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// wait before accepting again after an error, e.g. running out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// the der encoded certificate the tls client of the request's connection presented.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeerCert(pub(crate) Option<Vec<u8>>);
+
+impl Connected<&TlsStream<TcpStream>> for PeerCert {
+    fn connect_info(stream: &TlsStream<TcpStream>) -> Self {
+        let (_, conn) = stream.get_ref();
+        let cert = conn.peer_certificates().and_then(|certs| certs.first());
+        Self(cert.map(|cert| cert.0.clone()))
+    }
+}
+
+/// tls config of the http api from pem encoded certificate chain, key and client ca. clients
+/// need a certificate signed by the ca if given, like the grpc service requires.
+pub(crate) fn server_config(
+    cert: &[u8],
+    key: &[u8],
+    client_ca: Option<&[u8]>,
+) -> Result<ServerConfig, String> {
+    let chain = certs(cert)?;
+    let key = rustls_pemfile::read_all(&mut &*key)
+        .map_err(|e| format!("invalid key: {e}"))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or("no private key found")?;
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certs(ca)? {
+                roots
+                    .add(&cert)
+                    .map_err(|e| format!("invalid client ca: {e}"))?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(chain, key)
+        .map_err(|e| e.to_string())?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn certs(pem: &[u8]) -> Result<Vec<Certificate>, String> {
+    let certs =
+        rustls_pemfile::certs(&mut &*pem).map_err(|e| format!("invalid certificate: {e}"))?;
+    if certs.is_empty() {
+        return Err("no certificate found".into());
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// serve the http api on the listener, over tls if `tls` is given.
+///
+/// Handshakes run concurrently, a failed or timed out one only drops its connection. Accept
+/// errors are logged and retried after a short pause.
+pub async fn serve_http(
+    app: Router,
+    tls: Option<Arc<ServerConfig>>,
+    listener: TcpListener,
+) -> Result<(), hyper::Error> {
+    let Some(tls) = tls else {
+        let incoming = accept::from_stream(TcpListenerStream::new(listener));
+        return axum::Server::builder(incoming)
+            .serve(app.into_make_service())
+            .await;
+    };
+
+    let (tx, rx) = mpsc::channel::<io::Result<TlsStream<TcpStream>>>(PENDING_CONNECTIONS);
+    let acceptor = TlsAcceptor::from(tls);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("failed to accept a connection: {e}");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            if tx.is_closed() {
+                return;
+            }
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+                if let Ok(Ok(stream)) = handshake.await {
+                    let _ = tx.send(Ok(stream)).await;
+                }
+            });
+        }
+    });
+    axum::Server::builder(accept::from_stream(ReceiverStream::new(rx)))
+        .serve(app.into_make_service_with_connect_info::<PeerCert>())
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{router_with_auth, Auth, MtlsAuthenticator, Role};
+    use hyper::{client::conn, Body, Request, StatusCode};
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa};
+    use reservation::ReservationManager;
+    use std::collections::HashMap;
+    use tokio_rustls::{
+        rustls::{ClientConfig, ServerName},
+        TlsConnector,
+    };
+
+    fn issue(ca: &rcgen::Certificate, params: CertificateParams) -> (Vec<u8>, Vec<u8>) {
+        let cert = rcgen::Certificate::from_params(params).unwrap();
+        let pem = cert.serialize_pem_with_signer(ca).unwrap();
+        (
+            pem.into_bytes(),
+            cert.serialize_private_key_pem().into_bytes(),
+        )
+    }
+
+    fn client(ca: &[u8], identity: Option<(&[u8], &[u8])>) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        for cert in certs(ca).unwrap() {
+            roots.add(&cert).unwrap();
+        }
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let config = match identity {
+            Some((cert, key)) => {
+                let key = rustls_pemfile::pkcs8_private_keys(&mut &*key).unwrap();
+                builder
+                    .with_single_cert(certs(cert).unwrap(), PrivateKey(key[0].clone()))
+                    .unwrap()
+            }
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    async fn get(connector: &TlsConnector, addr: &str, uri: &str) -> io::Result<StatusCode> {
+        let stream = TcpStream::connect(addr).await?;
+        let name = ServerName::try_from("localhost").unwrap();
+        let stream = connector.connect(name, stream).await?;
+        let (mut sender, connection) = conn::handshake(stream).await.map_err(io::Error::other)?;
+        tokio::spawn(connection);
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(io::Error::other)?;
+        Ok(response.status())
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn http_should_authenticate_tls_client_certificates() {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(params).unwrap();
+        let ca_pem = ca.serialize_pem().unwrap().into_bytes();
+        let (server_cert, server_key) =
+            issue(&ca, CertificateParams::new(vec!["localhost".into()]));
+        // webpki rejects an empty subject alt name extension
+        let mut params = CertificateParams::new(vec!["ops".into()]);
+        params.distinguished_name.push(DnType::CommonName, "ops");
+        let (client_cert, client_key) = issue(&ca, params);
+
+        let tls = server_config(&server_cert, &server_key, Some(&ca_pem)).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let auth = Auth::new(MtlsAuthenticator::new(HashMap::from([(
+            "ops".to_string(),
+            vec![Role::Admin],
+        )])));
+        let manager = Arc::new(ReservationManager::new(migrated_pool.clone()));
+        tokio::spawn(serve_http(
+            router_with_auth(manager, auth),
+            Some(Arc::new(tls)),
+            listener,
+        ));
+
+        // the certificate identifies an admin, who may list the webhooks
+        let ops = client(&ca_pem, Some((&client_cert, &client_key)));
+        let status = get(&ops, &addr, "/webhooks").await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+
+        // a client without a certificate can't even connect
+        let anonymous = client(&ca_pem, None);
+        assert!(get(&anonymous, &addr, "/webhooks").await.is_err());
+
+        // the server keeps serving after a failed handshake
+        let status = get(&ops, &addr, "/webhooks").await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,834 @@
+mod auth;
+mod bulk;
+mod calendar;
+mod config;
+mod feed;
+mod grpc_web;
+mod https;
+mod rest;
+mod tenant;
+
+use abi::{
+    reservation_service_server::ReservationService, CancelByFilterRequest, CancelByFilterResponse,
+    CancelRequest, CancelResponse, ConfirmRequest, ConfirmResponse, CreateDelegationRequest,
+    CreateDelegationResponse, CreateWebhookRequest, CreateWebhookResponse, DeleteDelegationRequest,
+    DeleteDelegationResponse, DeleteWebhookRequest, DeleteWebhookResponse, ErrorReason,
+    FilterRequest, FilterResponse, GetRequest, GetResponse, ListDelegationsRequest,
+    ListDelegationsResponse, ListWebhooksRequest, ListWebhooksResponse, ListenRequest,
+    ListenResponse, QueryRequest, ReserveRequest, ReserveResponse, UpdateRequest, UpdateResponse,
+    ERROR_REASON_KEY,
+};
+use futures::{Stream, TryStreamExt};
+use reservation::{
+    Delegations, ListenFilter, ReservationError, ReservationId, ReservationManager, Rsvp, Webhooks,
+};
+use sqlx::types::Uuid;
+use std::{pin::Pin, str::FromStr};
+use tonic::{metadata::MetadataValue, Code, Request, Response, Status};
+
+pub use auth::{
+    Auth, Authenticator, Caller, Credentials, JwtAuthenticator, MtlsAuthenticator, Role,
+};
+pub use bulk::ImportParams;
+pub use calendar::CalendarParams;
+pub use config::{
+    AuthConfig, AuthMode, Config, ConfigError, FeatureConfig, QueryConfig, ServerConfig, TlsConfig,
+    CONFIG_ENV,
+};
+pub use feed::FeedParams;
+pub use grpc_web::{serve_grpc, GrpcWebConfig};
+pub use https::serve_http;
+pub use reservation::DbConfig;
+pub use rest::{router, router_with_auth, ApiError, ErrorBody};
+pub use tenant::TENANT_HEADER;
+
+type ReservationStream = Pin<Box<dyn Stream<Item = Result<abi::Reservation, Status>> + Send>>;
+type ListenStream = Pin<Box<dyn Stream<Item = Result<ListenResponse, Status>> + Send>>;
+
+/// grpc service of reservations, backed by a ReservationManager. requests are scoped to the
+/// tenant in the `x-tenant-id` metadata, and made by the caller the auth identifies.
+pub struct RsvpService {
+    manager: ReservationManager,
+    auth: Auth,
+}
+
+impl RsvpService {
+    pub fn new(manager: ReservationManager) -> Self {
+        Self {
+            manager,
+            auth: Auth::none(),
+        }
+    }
+
+    /// authenticate the callers with the auth, instead of letting everyone do everything
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// the caller of the request, by its bearer token or tls client certificate
+    fn caller<T>(&self, request: &Request<T>) -> Result<Caller, ReservationError> {
+        let certs = request.peer_certs();
+        let credentials = Credentials {
+            peer_cert: certs.as_ref().and_then(|c| c.first()).map(|c| c.get_ref()),
+            ..Credentials::from_metadata(request.metadata())
+        };
+        self.auth.authenticate(&credentials)
+    }
+
+    /// the caller of the request, and the manager of the request's tenant recording the caller
+    /// as the actor of the changes, only changing the caller's reservations unless privileged
+    fn scoped<T>(
+        &self,
+        request: &Request<T>,
+    ) -> Result<(ReservationManager, Caller), ReservationError> {
+        let caller = self.caller(request)?;
+        let tenant = tenant::from_metadata(request.metadata())?;
+        caller.check_tenant(&tenant)?;
+        let manager = self
+            .manager
+            .clone()
+            .with_tenant(tenant)
+            .with_actor(&caller.id)
+            .with_owner_check(!caller.is_privileged());
+        Ok((manager, caller))
+    }
+}
+
+#[tonic::async_trait]
+impl ReservationService for RsvpService {
+    /// make a reservation
+    async fn reserve(
+        &self,
+        request: Request<ReserveRequest>,
+    ) -> Result<Response<ReserveResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let mut rsvp = request
+            .reservation
+            .ok_or_else(|| Status::invalid_argument("missing reservation"))?;
+        let manager = caller
+            .authorize_reserve(&manager, &mut rsvp, request.on_behalf_of)
+            .map_err(to_status)?;
+        let rsvp = manager
+            .reserve_idempotent(request.idempotency_key, rsvp)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(ReserveResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    /// confirm a pending reservation
+    async fn confirm(
+        &self,
+        request: Request<ConfirmRequest>,
+    ) -> Result<Response<ConfirmResponse>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let id = parse_id(&request.id).map_err(to_status)?;
+        let rsvp = manager
+            .change_status_idempotent(request.idempotency_key, id)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(ConfirmResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    /// update the reservation note
+    async fn update(
+        &self,
+        request: Request<UpdateRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let id = parse_id(&request.id).map_err(to_status)?;
+        let rsvp = manager
+            .update_note(id, request.note)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(UpdateResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    /// cancel a reservation
+    async fn cancel(
+        &self,
+        request: Request<CancelRequest>,
+    ) -> Result<Response<CancelResponse>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let id = parse_id(&request.id).map_err(to_status)?;
+        let rsvp = manager
+            .delete_idempotent(request.idempotency_key, id)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(CancelResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    /// cancel all reservations matching the query
+    async fn cancel_by_filter(
+        &self,
+        request: Request<CancelByFilterRequest>,
+    ) -> Result<Response<CancelByFilterResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        let request = request.into_inner();
+        let mut query = request
+            .query
+            .ok_or_else(|| Status::invalid_argument("missing query"))?;
+        caller.authorize_query(&mut query).map_err(to_status)?;
+        let reservations = manager
+            .cancel_by_filter(query, request.dry_run)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(CancelByFilterResponse { reservations }))
+    }
+
+    /// get a reservation by id
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let id = parse_id(&request.into_inner().id).map_err(to_status)?;
+        let rsvp = manager.get(id).await.map_err(to_status)?;
+        Ok(Response::new(GetResponse {
+            reservation: Some(rsvp),
+        }))
+    }
+
+    type queryStream = ReservationStream;
+
+    /// query reservations, the time range is matched by the query's match mode
+    async fn query(
+        &self,
+        request: Request<QueryRequest>,
+    ) -> Result<Response<Self::queryStream>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let query = request.into_inner().query.unwrap_or_default();
+        let rsvps = manager.query(query).await.map_err(to_status)?;
+        let stream = futures::stream::iter(rsvps.into_iter().map(Ok));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// filter reservations, order by reservation id
+    async fn filter(
+        &self,
+        request: Request<FilterRequest>,
+    ) -> Result<Response<FilterResponse>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let filter = request.into_inner().filter.unwrap_or_default();
+        let (pager, reservations) = manager.filter(filter).await.map_err(to_status)?;
+        Ok(Response::new(FilterResponse {
+            reservations,
+            pager: Some(pager),
+        }))
+    }
+
+    type listenStream = ListenStream;
+
+    /// listen to reservation changes matching the request, replay the missed ones if since is set
+    async fn listen(
+        &self,
+        request: Request<ListenRequest>,
+    ) -> Result<Response<Self::listenStream>, Status> {
+        let (manager, _) = self.scoped(&request).map_err(to_status)?;
+        let filter = ListenFilter::from(request.into_inner());
+        let changes = manager.listen(filter).await.map_err(to_status)?;
+        let stream = changes.map_ok(ListenResponse::from).map_err(to_status);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    /// create a webhook called on reservation changes
+    async fn create_webhook(
+        &self,
+        request: Request<CreateWebhookRequest>,
+    ) -> Result<Response<CreateWebhookResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        caller.require_admin().map_err(to_status)?;
+        let webhook = request
+            .into_inner()
+            .webhook
+            .ok_or_else(|| Status::invalid_argument("missing webhook"))?;
+        let webhook = manager.create_webhook(webhook).await.map_err(to_status)?;
+        Ok(Response::new(CreateWebhookResponse {
+            webhook: Some(webhook),
+        }))
+    }
+
+    /// list the webhooks without their secrets
+    async fn list_webhooks(
+        &self,
+        request: Request<ListWebhooksRequest>,
+    ) -> Result<Response<ListWebhooksResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        caller.require_admin().map_err(to_status)?;
+        let webhooks = manager.list_webhooks().await.map_err(to_status)?;
+        Ok(Response::new(ListWebhooksResponse { webhooks }))
+    }
+
+    /// delete a webhook
+    async fn delete_webhook(
+        &self,
+        request: Request<DeleteWebhookRequest>,
+    ) -> Result<Response<DeleteWebhookResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        caller.require_admin().map_err(to_status)?;
+        let id = parse_webhook_id(&request.into_inner().id).map_err(to_status)?;
+        let webhook = manager.delete_webhook(id).await.map_err(to_status)?;
+        Ok(Response::new(DeleteWebhookResponse {
+            webhook: Some(webhook),
+        }))
+    }
+
+    /// let the grantee book or manage reservations for the grantor
+    async fn create_delegation(
+        &self,
+        request: Request<CreateDelegationRequest>,
+    ) -> Result<Response<CreateDelegationResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        let mut delegation = request
+            .into_inner()
+            .delegation
+            .ok_or_else(|| Status::invalid_argument("missing delegation"))?;
+        caller
+            .authorize_delegation(&mut delegation)
+            .map_err(to_status)?;
+        let delegation = manager
+            .create_delegation(delegation)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(CreateDelegationResponse {
+            delegation: Some(delegation),
+        }))
+    }
+
+    /// list the delegations granted by or to a user
+    async fn list_delegations(
+        &self,
+        request: Request<ListDelegationsRequest>,
+    ) -> Result<Response<ListDelegationsResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        let mut user_id = request.into_inner().user_id;
+        caller
+            .authorize_delegations_of(&mut user_id)
+            .map_err(to_status)?;
+        let delegations = manager
+            .list_delegations(&user_id)
+            .await
+            .map_err(to_status)?;
+        Ok(Response::new(ListDelegationsResponse { delegations }))
+    }
+
+    /// revoke a delegation
+    async fn delete_delegation(
+        &self,
+        request: Request<DeleteDelegationRequest>,
+    ) -> Result<Response<DeleteDelegationResponse>, Status> {
+        let (manager, caller) = self.scoped(&request).map_err(to_status)?;
+        let id = parse_delegation_id(&request.into_inner().id).map_err(to_status)?;
+        let delegation = manager.get_delegation(id).await.map_err(to_status)?;
+        caller.authorize_revoke(&delegation).map_err(to_status)?;
+        let delegation = manager.delete_delegation(id).await.map_err(to_status)?;
+        Ok(Response::new(DeleteDelegationResponse {
+            delegation: Some(delegation),
+        }))
+    }
+}
+
+/// map a reservation error to a grpc status, with the reason in the `x-rsvp-error` metadata.
+/// internal errors are logged here and reported without their details, which may tell about
+/// the database.
+fn to_status(e: ReservationError) -> Status {
+    let (code, reason) = match &e {
+        ReservationError::NotFound => (Code::NotFound, ErrorReason::NotFound),
+        ReservationError::Conflict(_) => (Code::FailedPrecondition, ErrorReason::Conflict),
+        ReservationError::DBError(_)
+        | ReservationError::MigrateError(_)
+        | ReservationError::SinkError(_)
+        | ReservationError::FormatError(_)
+        | ReservationError::Unknown => (Code::Internal, ErrorReason::Internal),
+        ReservationError::InvalidReservationId => {
+            (Code::InvalidArgument, ErrorReason::InvalidReservationId)
+        }
+        ReservationError::InvalidTimespan => (Code::InvalidArgument, ErrorReason::InvalidTimespan),
+        ReservationError::IdempotencyKeyReused(_) => {
+            (Code::InvalidArgument, ErrorReason::IdempotencyKeyReused)
+        }
+        ReservationError::InvalidUserId(_)
+        | ReservationError::InvalidStatus
+        | ReservationError::InvalidResourceId(_)
+        | ReservationError::InvalidFilter(_)
+        | ReservationError::InvalidTenantId(_)
+        | ReservationError::InvalidWebhook(_)
+        | ReservationError::InvalidDelegation(_) => {
+            (Code::InvalidArgument, ErrorReason::InvalidArgument)
+        }
+        ReservationError::Unauthenticated(_) => {
+            (Code::Unauthenticated, ErrorReason::Unauthenticated)
+        }
+        ReservationError::PermissionDenied(_) => {
+            (Code::PermissionDenied, ErrorReason::PermissionDenied)
+        }
+    };
+    let message = match reason {
+        // reservations, webhooks and delegations are all reported missing the same way
+        ErrorReason::NotFound => "not found".to_string(),
+        ErrorReason::Internal => {
+            tracing::error!("internal error: {e}");
+            "internal error".to_string()
+        }
+        _ => e.to_string(),
+    };
+    let mut status = Status::new(code, message);
+    status.metadata_mut().insert(
+        ERROR_REASON_KEY,
+        MetadataValue::from_static(reason.as_str_name()),
+    );
+    status
+}
+
+/// parse a reservation id from request
+fn parse_id(id: &str) -> Result<ReservationId, ReservationError> {
+    ReservationId::from_str(id).map_err(|_| ReservationError::InvalidReservationId)
+}
+
+/// parse a webhook id from request
+fn parse_webhook_id(id: &str) -> Result<Uuid, ReservationError> {
+    Uuid::from_str(id).map_err(|_| ReservationError::InvalidWebhook(format!("invalid id: {id}")))
+}
+
+/// parse a delegation id from request
+fn parse_delegation_id(id: &str) -> Result<Uuid, ReservationError> {
+    Uuid::from_str(id).map_err(|_| ReservationError::InvalidDelegation(format!("invalid id: {id}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abi::{
+        to_timestamp, ReservationMatchMode, ReservationQuery, ReservationQueryBuilder,
+        ReservationStatus,
+    };
+    use chrono::{DateTime, FixedOffset, Utc};
+    use prost_types::Timestamp;
+
+    fn reserve_request(user_id: &str, start: &str, end: &str) -> ReserveRequest {
+        let start: DateTime<FixedOffset> = start.parse().unwrap();
+        let end: DateTime<FixedOffset> = end.parse().unwrap();
+        ReserveRequest {
+            reservation: Some(abi::Reservation {
+                user_id: user_id.to_string(),
+                status: ReservationStatus::Pending as i32,
+                resource_id: "class room 1".to_string(),
+                start: Some(to_timestamp(start.with_timezone(&Utc))),
+                end: Some(to_timestamp(end.with_timezone(&Utc))),
+                ..Default::default()
+            }),
+            idempotency_key: "".to_string(),
+            on_behalf_of: false,
+        }
+    }
+
+    #[test]
+    fn internal_errors_should_not_leak_details() {
+        let status = to_status(ReservationError::DBError(sqlx::Error::PoolTimedOut));
+        assert_eq!(status.code(), tonic::Code::Internal);
+        assert_eq!(status.message(), "internal error");
+        let status = to_status(ReservationError::NotFound);
+        assert_eq!(status.message(), "not found");
+        let status = to_status(ReservationError::InvalidTimespan);
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(
+            status.metadata().get(ERROR_REASON_KEY).unwrap(),
+            "ERROR_REASON_INVALID_TIMESPAN"
+        );
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn query_stream_should_honor_match_mode() {
+        let service = RsvpService::new(ReservationManager::new(migrated_pool.clone()));
+        let overnight = service
+            .reserve(Request::new(reserve_request(
+                "M4n5ter",
+                "2022-11-17T22:00:00+0800",
+                "2022-11-18T08:00:00+0800",
+            )))
+            .await
+            .unwrap()
+            .into_inner()
+            .reservation
+            .unwrap();
+
+        let query = ReservationQueryBuilder::default()
+            .start("2022-11-18T00:00:00+0800".parse::<Timestamp>().unwrap())
+            .end("2022-11-19T00:00:00+0800".parse::<Timestamp>().unwrap())
+            .match_mode(ReservationMatchMode::Overlaps as i32)
+            .build()
+            .unwrap();
+        let stream = service
+            .query(Request::new(QueryRequest { query: Some(query) }))
+            .await
+            .unwrap()
+            .into_inner();
+        let rsvps: Vec<_> = stream.try_collect().await.unwrap();
+        assert_eq!(rsvps, vec![overnight]);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn errors_should_map_to_status_codes() {
+        let service = RsvpService::new(ReservationManager::new(migrated_pool.clone()));
+        let request = || {
+            reserve_request(
+                "M4n5ter",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+            )
+        };
+        service.reserve(Request::new(request())).await.unwrap();
+
+        let status = service.reserve(Request::new(request())).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        let status = service
+            .get(Request::new(GetRequest {
+                id: "not a uuid".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        let status = service
+            .get(Request::new(GetRequest {
+                id: "a3a4f0ce-c2a7-4a06-a08f-2d7f0c5e0d4b".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    fn of_tenant<T>(message: T, tenant: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert(TENANT_HEADER, tenant.parse().unwrap());
+        request
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn requests_should_be_scoped_to_the_tenant() {
+        let service = RsvpService::new(ReservationManager::new(migrated_pool.clone()));
+        let request = || {
+            reserve_request(
+                "M4n5ter",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+            )
+        };
+        let rsvp = service
+            .reserve(of_tenant(request(), "acme"))
+            .await
+            .unwrap()
+            .into_inner()
+            .reservation
+            .unwrap();
+        assert_eq!(rsvp.tenant_id, "acme");
+        // the same room is free in the default tenant
+        service.reserve(Request::new(request())).await.unwrap();
+
+        let get = || GetRequest {
+            id: rsvp.id.clone(),
+        };
+        let status = service.get(Request::new(get())).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let found = service.get(of_tenant(get(), "acme")).await.unwrap();
+        assert_eq!(found.into_inner().reservation.unwrap(), rsvp);
+
+        let status = service
+            .get(of_tenant(get(), "with space"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    /// the bearer token is the caller's id, `root` is an admin
+    struct TokenIsCaller;
+
+    impl Authenticator for TokenIsCaller {
+        fn authenticate(&self, credentials: &Credentials) -> Result<Caller, ReservationError> {
+            match credentials.bearer {
+                Some("root") => Ok(Caller::new("root", vec![Role::Admin])),
+                Some(id) => Ok(Caller::new(id, vec![])),
+                None => Err(ReservationError::Unauthenticated("no token".to_string())),
+            }
+        }
+    }
+
+    fn as_caller<T>(message: T, token: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        let value = format!("Bearer {token}").parse().unwrap();
+        request.metadata_mut().insert("authorization", value);
+        request
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn only_owners_and_admins_should_change_reservations() {
+        let service = RsvpService::new(ReservationManager::new(migrated_pool.clone()))
+            .with_auth(Auth::new(TokenIsCaller));
+        let request =
+            || reserve_request("", "2022-11-18T12:00:00+0800", "2022-11-18T14:00:00+0800");
+        let status = service.reserve(Request::new(request())).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let rsvp = service
+            .reserve(as_caller(request(), "alice"))
+            .await
+            .unwrap()
+            .into_inner()
+            .reservation
+            .unwrap();
+        assert_eq!(rsvp.user_id, "alice");
+
+        let for_alice = reserve_request(
+            "alice",
+            "2022-11-19T12:00:00+0800",
+            "2022-11-19T14:00:00+0800",
+        );
+        let status = service
+            .reserve(as_caller(for_alice.clone(), "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        service.reserve(as_caller(for_alice, "root")).await.unwrap();
+
+        let cancel = || CancelRequest {
+            id: rsvp.id.clone(),
+            idempotency_key: "".to_string(),
+        };
+        let status = service
+            .cancel(as_caller(cancel(), "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        // reads are open to every caller
+        let get = GetRequest {
+            id: rsvp.id.clone(),
+        };
+        service.get(as_caller(get, "bob")).await.unwrap();
+        service.cancel(as_caller(cancel(), "alice")).await.unwrap();
+
+        // bob may only cancel his own reservations, of which there are none
+        let cancel_all = CancelByFilterRequest {
+            query: Some(ReservationQuery {
+                resource_id: "class room 1".to_string(),
+                ..Default::default()
+            }),
+            dry_run: true,
+        };
+        let cancelled = service
+            .cancel_by_filter(as_caller(cancel_all.clone(), "bob"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(cancelled.reservations.is_empty());
+        let cancelled = service
+            .cancel_by_filter(as_caller(cancel_all, "root"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(cancelled.reservations.len(), 1);
+
+        let status = service
+            .list_webhooks(as_caller(ListWebhooksRequest {}, "alice"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        service
+            .list_webhooks(as_caller(ListWebhooksRequest {}, "root"))
+            .await
+            .unwrap();
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn derived_user_id_should_be_overridden_on_behalf_of() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let service = RsvpService::new(manager.clone())
+            .with_auth(Auth::new(TokenIsCaller).with_derived_user_id());
+        let request = |user_id: &str| {
+            reserve_request(
+                user_id,
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+            )
+        };
+        let status = service
+            .reserve(as_caller(request("bob"), "alice"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let status = service
+            .reserve(as_caller(request("bob"), "root"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let on_behalf_of = ReserveRequest {
+            on_behalf_of: true,
+            ..request("bob")
+        };
+        let rsvp = service
+            .reserve(as_caller(on_behalf_of, "root"))
+            .await
+            .unwrap()
+            .into_inner()
+            .reservation
+            .unwrap();
+        assert_eq!(rsvp.user_id, "bob");
+
+        // the audit trail records who booked for bob
+        let filter = ListenFilter {
+            since: Some(0),
+            ..Default::default()
+        };
+        let mut changes = manager.listen(filter).await.unwrap();
+        let change = changes.try_next().await.unwrap().unwrap();
+        assert_eq!(change.reservation.id, rsvp.id);
+        assert_eq!((change.actor.as_str(), change.on_behalf_of), ("root", true));
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn delegates_should_book_and_manage_for_the_grantor() {
+        let manager = ReservationManager::new(migrated_pool.clone());
+        let service = RsvpService::new(manager.clone())
+            .with_auth(Auth::new(TokenIsCaller).with_derived_user_id());
+        let delegate = |grantee: &str, scope: abi::DelegationScope| CreateDelegationRequest {
+            delegation: Some(abi::Delegation {
+                grantee: grantee.to_string(),
+                scope: scope as i32,
+                ..Default::default()
+            }),
+        };
+        let for_alice = ReserveRequest {
+            on_behalf_of: true,
+            ..reserve_request(
+                "alice",
+                "2022-11-18T12:00:00+0800",
+                "2022-11-18T14:00:00+0800",
+            )
+        };
+        let status = service
+            .reserve(as_caller(for_alice.clone(), "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let book = service
+            .create_delegation(as_caller(
+                delegate("bob", abi::DelegationScope::Book),
+                "alice",
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .delegation
+            .unwrap();
+        assert_eq!(book.grantor, "alice");
+        // a delegate still asks to book on behalf of the grantor
+        let status = service
+            .reserve(as_caller(
+                ReserveRequest {
+                    on_behalf_of: false,
+                    ..for_alice.clone()
+                },
+                "bob",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let rsvp = service
+            .reserve(as_caller(for_alice.clone(), "bob"))
+            .await
+            .unwrap()
+            .into_inner()
+            .reservation
+            .unwrap();
+        assert_eq!(
+            (rsvp.user_id.as_str(), rsvp.booked_by.as_str()),
+            ("alice", "bob")
+        );
+
+        let filter = ListenFilter {
+            since: Some(0),
+            ..Default::default()
+        };
+        let mut changes = manager.listen(filter).await.unwrap();
+        let change = changes.try_next().await.unwrap().unwrap();
+        assert_eq!((change.actor.as_str(), change.on_behalf_of), ("bob", true));
+
+        // booking doesn't allow changing the grantor's reservations, managing does
+        let cancel = || CancelRequest {
+            id: rsvp.id.clone(),
+            idempotency_key: "".to_string(),
+        };
+        let status = service
+            .cancel(as_caller(cancel(), "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        service
+            .create_delegation(as_caller(
+                delegate("bob", abi::DelegationScope::Manage),
+                "alice",
+            ))
+            .await
+            .unwrap();
+        service.cancel(as_caller(cancel(), "bob")).await.unwrap();
+
+        // only the grantor or an admin delegates for the grantor, lists and revokes
+        let mut for_carol = delegate("carol", abi::DelegationScope::Book);
+        for_carol.delegation.as_mut().unwrap().grantor = "alice".to_string();
+        let status = service
+            .create_delegation(as_caller(for_carol.clone(), "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        service
+            .create_delegation(as_caller(for_carol, "root"))
+            .await
+            .unwrap();
+        let list = |user_id: &str| ListDelegationsRequest {
+            user_id: user_id.to_string(),
+        };
+        let status = service
+            .list_delegations(as_caller(list("alice"), "carol"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let delegations = service
+            .list_delegations(as_caller(list(""), "alice"))
+            .await
+            .unwrap()
+            .into_inner()
+            .delegations;
+        assert_eq!(delegations.len(), 2);
+
+        let revoke = || DeleteDelegationRequest {
+            id: book.id.clone(),
+        };
+        let status = service
+            .delete_delegation(as_caller(revoke(), "carol"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        service
+            .delete_delegation(as_caller(revoke(), "alice"))
+            .await
+            .unwrap();
+        let status = service
+            .reserve(as_caller(for_alice, "bob"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+}
@@ -1,3 +1,81 @@
-fn main() {
-    println!("Hello, world!");
+#[cfg(feature = "nats")]
+use reservation::NatsSink;
+use reservation::{FileSink, Publisher, ReservationManager, Sink, WebhookDispatcher, WebhookSink};
+use reservation_service::{router_with_auth, serve_grpc, serve_http, Config, RsvpService};
+use std::{error::Error, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+    let config = Config::load()?;
+    let addr = config.server.grpc_addr;
+    let http_addr = config.server.http_addr;
+    let web = config.grpc_web();
+    let tls = config.server_tls()?;
+    let http_tls = config.http_tls()?;
+    let auth = config.auth()?;
+
+    let pool = config.connect().await?;
+    if config.db.migrate {
+        ReservationManager::new(pool.clone()).migrate().await?;
+    }
+    if let Some(path) = &config.features.outbox_file {
+        let sink = FileSink::new(path);
+        tokio::spawn(publish(Publisher::new(pool.clone(), "file", sink)));
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = &config.features.nats_url {
+        let sink = NatsSink::connect(url, "reservation").await?;
+        tokio::spawn(publish(Publisher::new(pool.clone(), "nats", sink)));
+    }
+    if config.features.webhooks {
+        // queue the webhook deliveries of every change, then send them
+        let sink = WebhookSink::new(pool.clone());
+        tokio::spawn(publish(Publisher::new(pool.clone(), "webhooks", sink)));
+        tokio::spawn(dispatch(WebhookDispatcher::new(pool.clone())));
+    }
+    let service = RsvpService::new(config.manager(pool.clone())).with_auth(auth.clone());
+
+    tracing::info!("reservation service listening on {addr}");
+    if let Some(web) = &web {
+        tracing::info!(
+            "grpc-web enabled, allowed origins: {:?}",
+            web.allowed_origins
+        );
+    }
+    let grpc = serve_grpc(service, web, tls, TcpListener::bind(addr).await?);
+    if !config.features.http {
+        grpc.await?;
+        return Ok(());
+    }
+
+    tracing::info!("http on {http_addr}");
+    let app = router_with_auth(Arc::new(config.manager(pool)), auth);
+    let http = serve_http(app, http_tls, TcpListener::bind(http_addr).await?);
+    tokio::try_join!(
+        async { grpc.await.map_err(Box::<dyn Error>::from) },
+        async { http.await.map_err(Box::<dyn Error>::from) },
+    )?;
+    Ok(())
+}
+
+/// publish reservation changes until the process exits, restart the publisher after an error
+async fn publish<S: Sink>(publisher: Publisher<S>) {
+    loop {
+        if let Err(e) = publisher.run().await {
+            tracing::error!("outbox publisher failed: {e}");
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// send webhook deliveries until the process exits, restart the dispatcher after an error
+async fn dispatch(dispatcher: WebhookDispatcher) {
+    loop {
+        if let Err(e) = dispatcher.run().await {
+            tracing::error!("webhook dispatcher failed: {e}");
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
 }
@@ -0,0 +1,525 @@
+use crate::{
+    auth, bulk, calendar, feed, parse_delegation_id, parse_id, parse_webhook_id, tenant, Auth,
+    Caller,
+};
+use abi::{
+    to_timestamp, Delegation, Reservation, ReservationMatchMode, ReservationQueryBuilder,
+    ReservationSortKey, ReservationStatus, Webhook,
+};
+use axum::{
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, Utc};
+use reservation::{Delegations, ReservationError, ReservationManager, Rsvp, Webhooks};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// header carrying the idempotency key of reserve, confirm and cancel
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+type Manager = Extension<Arc<ReservationManager>>;
+type Me = Extension<Caller>;
+
+/// http/json api of reservations, backed by the same ReservationManager as the grpc service.
+/// requests are scoped to the tenant in the `x-tenant-id` header. every request is unrestricted,
+/// see `router_with_auth`.
+///
+/// - `POST /reservations` make a reservation
+/// - `GET /reservations` query reservations
+/// - `POST /reservations/cancel` cancel all reservations matching the query
+/// - `GET /reservations/export` stream reservations matching the query as csv or ndjson
+/// - `POST /reservations/import` reserve every row of a csv, ndjson or iCalendar document
+/// - `GET /reservations/:id` get a reservation
+/// - `PATCH /reservations/:id` update the note
+/// - `DELETE /reservations/:id` cancel a reservation
+/// - `POST /reservations/:id/confirm` confirm a pending reservation
+/// - `GET /changes` stream changes as server-sent events
+/// - `GET /changes/ws` stream changes over a websocket
+/// - `GET /users/:user_id/calendar.ics` reservations of a user as an iCalendar feed
+/// - `GET /resources/:resource_id/calendar.ics` reservations of a resource as an iCalendar feed
+/// - `POST /webhooks` create a webhook
+/// - `GET /webhooks` list the webhooks
+/// - `DELETE /webhooks/:id` delete a webhook
+/// - `POST /delegations` let the grantee book or manage reservations for the grantor
+/// - `GET /delegations` list the delegations granted by or to a user
+/// - `DELETE /delegations/:id` revoke a delegation
+pub fn router(manager: Arc<ReservationManager>) -> Router {
+    Router::new()
+        .route("/reservations", post(reserve).get(query))
+        .route("/reservations/cancel", post(cancel_by_filter))
+        .route("/reservations/export", get(bulk::export))
+        .route("/reservations/import", post(bulk::import))
+        .route(
+            "/reservations/:id",
+            get(get_reservation).patch(update).delete(cancel),
+        )
+        .route("/reservations/:id/confirm", post(confirm))
+        .route("/changes", get(feed::sse))
+        .route("/changes/ws", get(feed::ws))
+        .route("/users/:user_id/calendar.ics", get(calendar::user))
+        .route(
+            "/resources/:resource_id/calendar.ics",
+            get(calendar::resource),
+        )
+        .route("/webhooks", post(create_webhook).get(list_webhooks))
+        .route("/webhooks/:id", delete(delete_webhook))
+        .route(
+            "/delegations",
+            post(create_delegation).get(list_delegations),
+        )
+        .route("/delegations/:id", delete(delete_delegation))
+        // every handler gets the manager of the request's tenant, and the caller
+        .layer(middleware::from_fn(tenant::scope))
+        .layer(middleware::from_fn(auth::authenticate))
+        .layer(Extension(manager))
+}
+
+/// the http/json api with the callers authenticated by a bearer token, or by the tls client
+/// certificate of the connection when served by `serve_http` over tls.
+pub fn router_with_auth(manager: Arc<ReservationManager>, auth: Auth) -> Router {
+    router(manager).layer(Extension(auth))
+}
+
+/// error body returned for every failed request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// stable machine readable code, e.g. `not_found`, `conflict`
+    pub code: String,
+    pub message: String,
+}
+
+/// a ReservationError rendered as an http response
+pub struct ApiError(ReservationError);
+
+impl From<ReservationError> for ApiError {
+    fn from(e: ReservationError) -> Self {
+        Self(e)
+    }
+}
+
+// keep the mapping in line with the grpc status codes
+impl ApiError {
+    /// the status and body shown to the client. internal errors are logged here and reported
+    /// without their details.
+    pub(crate) fn to_body(&self) -> (StatusCode, ErrorBody) {
+        let e = &self.0;
+        let (status, code) = match e {
+            ReservationError::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+            ReservationError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+            ReservationError::DBError(_)
+            | ReservationError::MigrateError(_)
+            | ReservationError::SinkError(_)
+            | ReservationError::FormatError(_)
+            | ReservationError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+            ReservationError::Unauthenticated(_) => (StatusCode::UNAUTHORIZED, "unauthenticated"),
+            ReservationError::PermissionDenied(_) => (StatusCode::FORBIDDEN, "permission_denied"),
+            ReservationError::IdempotencyKeyReused(_) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "idempotency_key_reused")
+            }
+            ReservationError::InvalidReservationId
+            | ReservationError::InvalidTimespan
+            | ReservationError::InvalidUserId(_)
+            | ReservationError::InvalidStatus
+            | ReservationError::InvalidResourceId(_)
+            | ReservationError::InvalidFilter(_)
+            | ReservationError::InvalidTenantId(_)
+            | ReservationError::InvalidWebhook(_)
+            | ReservationError::InvalidDelegation(_) => {
+                (StatusCode::BAD_REQUEST, "invalid_argument")
+            }
+        };
+        let message = match (e, status) {
+            // reservations, webhooks and delegations are all reported missing the same way
+            (ReservationError::NotFound, _) => "not found".to_string(),
+            // details of internal errors may tell about the database, they are only logged
+            (e, StatusCode::INTERNAL_SERVER_ERROR) => {
+                tracing::error!("internal error: {e}");
+                "internal error".to_string()
+            }
+            (e, _) => e.to_string(),
+        };
+        let body = ErrorBody {
+            code: code.to_string(),
+            message,
+        };
+        (status, body)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.to_body();
+        (status, Json(body)).into_response()
+    }
+}
+
+/// query string of `GET /reservations`, `POST /reservations/cancel` and
+/// `GET /reservations/export`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct QueryParams {
+    pub user_id: Option<String>,
+    pub resource_id: Option<String>,
+    pub status: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    /// `contains`, `overlaps` or `starts_within`
+    pub mode: Option<String>,
+    /// `start`, `end` or `id`
+    pub sort: Option<String>,
+    pub desc: bool,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    /// only for cancel, return the matching reservations without cancelling them
+    pub dry_run: bool,
+    /// only for export, `csv` or `ndjson`
+    pub format: Option<String>,
+}
+
+impl QueryParams {
+    pub(crate) fn to_query(&self) -> Result<abi::ReservationQuery, ReservationError> {
+        let invalid = |name: &str, value: &str| {
+            ReservationError::InvalidFilter(format!("invalid {name}: {value}"))
+        };
+        let mut builder = ReservationQueryBuilder::default();
+        builder.desc(self.desc);
+        if let Some(user_id) = &self.user_id {
+            builder.user_id(user_id);
+        }
+        if let Some(resource_id) = &self.resource_id {
+            builder.resource_id(resource_id);
+        }
+        if let Some(status) = &self.status {
+            let s: ReservationStatus = status.parse().map_err(|_| invalid("status", status))?;
+            builder.status(s as i32);
+        }
+        if let Some(start) = self.start {
+            builder.start(to_timestamp(start));
+        }
+        if let Some(end) = self.end {
+            builder.end(to_timestamp(end));
+        }
+        if let Some(mode) = &self.mode {
+            let m = match mode.as_str() {
+                "contains" => ReservationMatchMode::Contains,
+                "overlaps" => ReservationMatchMode::Overlaps,
+                "starts_within" => ReservationMatchMode::StartsWithin,
+                _ => return Err(invalid("mode", mode)),
+            };
+            builder.match_mode(m as i32);
+        }
+        if let Some(sort) = &self.sort {
+            let key = match sort.as_str() {
+                "start" => ReservationSortKey::Start,
+                "end" => ReservationSortKey::End,
+                "id" => ReservationSortKey::Id,
+                _ => return Err(invalid("sort", sort)),
+            };
+            builder.sort_by(key as i32);
+        }
+        if let Some(page) = self.page {
+            builder.page(page);
+        }
+        if let Some(page_size) = self.page_size {
+            builder.page_size(page_size);
+        }
+        builder
+            .build()
+            .map_err(|e| ReservationError::InvalidFilter(e.to_string()))
+    }
+}
+
+/// query string of `POST /reservations`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReserveParams {
+    /// an admin or delegate books for the user_id of the body, see `Auth::with_derived_user_id`
+    pub on_behalf_of: bool,
+}
+
+/// query string of `GET /delegations`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DelegationParams {
+    /// the caller if empty
+    user_id: String,
+}
+
+/// body of `PATCH /reservations/:id`
+#[derive(Debug, Deserialize)]
+pub struct UpdateBody {
+    pub note: String,
+}
+
+fn idempotency_key(headers: &HeaderMap) -> String {
+    headers
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+async fn reserve(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Query(params): Query<ReserveParams>,
+    headers: HeaderMap,
+    Json(mut rsvp): Json<Reservation>,
+) -> Result<(StatusCode, Json<Reservation>), ApiError> {
+    let manager = caller.authorize_reserve(&manager, &mut rsvp, params.on_behalf_of)?;
+    // status is optional in json, a new reservation is pending unless told otherwise
+    if rsvp.status == ReservationStatus::Unknown as i32 {
+        rsvp.status = ReservationStatus::Pending as i32;
+    }
+    let rsvp = manager
+        .reserve_idempotent(idempotency_key(&headers), rsvp)
+        .await?;
+    Ok((StatusCode::CREATED, Json(rsvp)))
+}
+
+async fn confirm(
+    Extension(manager): Manager,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Reservation>, ApiError> {
+    let id = parse_id(&id)?;
+    let rsvp = manager
+        .change_status_idempotent(idempotency_key(&headers), id)
+        .await?;
+    Ok(Json(rsvp))
+}
+
+async fn update(
+    Extension(manager): Manager,
+    Path(id): Path<String>,
+    Json(body): Json<UpdateBody>,
+) -> Result<Json<Reservation>, ApiError> {
+    let id = parse_id(&id)?;
+    let rsvp = manager.update_note(id, body.note).await?;
+    Ok(Json(rsvp))
+}
+
+async fn cancel(
+    Extension(manager): Manager,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Reservation>, ApiError> {
+    let id = parse_id(&id)?;
+    let rsvp = manager
+        .delete_idempotent(idempotency_key(&headers), id)
+        .await?;
+    Ok(Json(rsvp))
+}
+
+async fn get_reservation(
+    Extension(manager): Manager,
+    Path(id): Path<String>,
+) -> Result<Json<Reservation>, ApiError> {
+    let rsvp = manager.get(parse_id(&id)?).await?;
+    Ok(Json(rsvp))
+}
+
+async fn query(
+    Extension(manager): Manager,
+    Query(params): Query<QueryParams>,
+) -> Result<Json<Vec<Reservation>>, ApiError> {
+    let rsvps = manager.query(params.to_query()?).await?;
+    Ok(Json(rsvps))
+}
+
+async fn cancel_by_filter(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Query(params): Query<QueryParams>,
+) -> Result<Json<Vec<Reservation>>, ApiError> {
+    let mut query = params.to_query()?;
+    caller.authorize_query(&mut query)?;
+    let rsvps = manager.cancel_by_filter(query, params.dry_run).await?;
+    Ok(Json(rsvps))
+}
+
+async fn create_webhook(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Json(webhook): Json<Webhook>,
+) -> Result<(StatusCode, Json<Webhook>), ApiError> {
+    caller.require_admin()?;
+    let webhook = manager.create_webhook(webhook).await?;
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+async fn list_webhooks(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    caller.require_admin()?;
+    let webhooks = manager.list_webhooks().await?;
+    Ok(Json(webhooks))
+}
+
+async fn delete_webhook(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Path(id): Path<String>,
+) -> Result<Json<Webhook>, ApiError> {
+    caller.require_admin()?;
+    let webhook = manager.delete_webhook(parse_webhook_id(&id)?).await?;
+    Ok(Json(webhook))
+}
+
+async fn create_delegation(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Json(mut delegation): Json<Delegation>,
+) -> Result<(StatusCode, Json<Delegation>), ApiError> {
+    caller.authorize_delegation(&mut delegation)?;
+    let delegation = manager.create_delegation(delegation).await?;
+    Ok((StatusCode::CREATED, Json(delegation)))
+}
+
+async fn list_delegations(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Query(mut params): Query<DelegationParams>,
+) -> Result<Json<Vec<Delegation>>, ApiError> {
+    caller.authorize_delegations_of(&mut params.user_id)?;
+    let delegations = manager.list_delegations(&params.user_id).await?;
+    Ok(Json(delegations))
+}
+
+async fn delete_delegation(
+    Extension(manager): Manager,
+    Extension(caller): Me,
+    Path(id): Path<String>,
+) -> Result<Json<Delegation>, ApiError> {
+    let id = parse_delegation_id(&id)?;
+    caller.authorize_revoke(&manager.get_delegation(id).await?)?;
+    let delegation = manager.delete_delegation(id).await?;
+    Ok(Json(delegation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Method, Request},
+    };
+    use tower::ServiceExt;
+
+    async fn call(
+        app: &Router,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        let body = match body {
+            Some(body) => {
+                request = request.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json = if bytes.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        (status, json)
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn rest_api_should_work() {
+        let app = router(Arc::new(ReservationManager::new(migrated_pool.clone())));
+        let body = serde_json::json!({
+            "user_id": "M4n5ter",
+            "resource_id": "class room 1",
+            "start": "2022-11-18T12:00:00+08:00",
+            "end": "2022-11-18T14:00:00+08:00",
+            "note": "hello",
+        });
+
+        let (status, rsvp) = call(&app, Method::POST, "/reservations", Some(body.clone())).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(rsvp["status"], "pending");
+        assert_eq!(rsvp["start"], "2022-11-18T04:00:00Z");
+        let id = rsvp["id"].as_str().unwrap().to_string();
+
+        let (status, err) = call(&app, Method::POST, "/reservations", Some(body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(err["code"], "conflict");
+
+        let uri = format!("/reservations/{id}/confirm");
+        let (status, rsvp) = call(&app, Method::POST, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rsvp["status"], "confirmed");
+
+        let uri = format!("/reservations/{id}");
+        let note = serde_json::json!({ "note": "world" });
+        let (_, rsvp) = call(&app, Method::PATCH, &uri, Some(note)).await;
+        assert_eq!(rsvp["note"], "world");
+
+        let uri = "/reservations?user_id=M4n5ter&status=confirmed&start=2022-11-18T00:00:00Z&end=2022-11-19T00:00:00Z";
+        let (status, rsvps) = call(&app, Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rsvps.as_array().unwrap().len(), 1);
+        assert_eq!(rsvps[0]["id"], id.as_str());
+
+        let (status, err) = call(&app, Method::GET, "/reservations?mode=nearby", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(err["code"], "invalid_argument");
+
+        let uri = format!("/reservations/{id}");
+        let (status, _) = call(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, err) = call(&app, Method::GET, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(err["code"], "not_found");
+
+        let (status, _) = call(&app, Method::GET, "/reservations/not-a-uuid", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx_database_tester::test(pool(variable = "migrated_pool", migrations = "../migrations"))]
+    async fn webhook_api_should_work() {
+        let app = router(Arc::new(ReservationManager::new(migrated_pool.clone())));
+        let body = serde_json::json!({
+            "url": "https://example.com/hook",
+            "secret": "0123456789abcdef",
+            "ops": ["create", "delete"],
+        });
+        let (status, webhook) = call(&app, Method::POST, "/webhooks", Some(body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(webhook["ops"], serde_json::json!(["create", "delete"]));
+        assert_eq!(webhook["secret"], "0123456789abcdef");
+
+        let body = serde_json::json!({ "url": "https://example.com/hook", "secret": "short" });
+        let (status, err) = call(&app, Method::POST, "/webhooks", Some(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(err["code"], "invalid_argument");
+
+        let (_, webhooks) = call(&app, Method::GET, "/webhooks", None).await;
+        assert_eq!(webhooks.as_array().unwrap().len(), 1);
+        assert_eq!(webhooks[0]["secret"], "");
+
+        let uri = format!("/webhooks/{}", webhook["id"].as_str().unwrap());
+        let (status, _) = call(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, err) = call(&app, Method::DELETE, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(err["message"], "not found");
+    }
+}
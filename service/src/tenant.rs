@@ -0,0 +1,76 @@
+//! tenant of a request, from the `x-tenant-id` http header or grpc metadata.
+
+use crate::{ApiError, Caller};
+use axum::{
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use reservation::{ReservationError, ReservationManager};
+use std::sync::Arc;
+use tonic::metadata::MetadataMap;
+
+/// header, or grpc metadata key, naming the tenant of a request. requests without it belong to
+/// the default tenant
+pub const TENANT_HEADER: &str = "x-tenant-id";
+/// length limit of tenant ids, the size of the tenant_id column
+const MAX_LEN: usize = 64;
+
+/// the tenant named by the header value, empty for the default tenant.
+fn parse(value: Option<&[u8]>) -> Result<String, ReservationError> {
+    let Some(value) = value else {
+        return Ok(String::new());
+    };
+    match std::str::from_utf8(value) {
+        Ok(tenant) if tenant.len() <= MAX_LEN && tenant.chars().all(|c| c.is_ascii_graphic()) => {
+            Ok(tenant.to_string())
+        }
+        _ => Err(ReservationError::InvalidTenantId(
+            String::from_utf8_lossy(value).into_owned(),
+        )),
+    }
+}
+
+/// the tenant of a grpc request.
+pub(crate) fn from_metadata(metadata: &MetadataMap) -> Result<String, ReservationError> {
+    parse(metadata.get(TENANT_HEADER).map(|v| v.as_bytes()))
+}
+
+/// the tenant of an http request.
+pub(crate) fn from_headers(headers: &HeaderMap) -> Result<String, ReservationError> {
+    parse(headers.get(TENANT_HEADER).map(|v| v.as_bytes()))
+}
+
+/// replace the manager of the request by one scoped to the tenant of the request, recording the
+/// caller as the actor of the changes and only changing the caller's reservations unless
+/// privileged.
+pub(crate) async fn scope<B>(mut request: Request<B>, next: Next<B>) -> Result<Response, ApiError> {
+    let tenant = from_headers(request.headers())?;
+    let caller = request.extensions().get::<Caller>();
+    let actor = caller.map(|caller| caller.id.clone()).unwrap_or_default();
+    let check_owner = caller.is_some_and(|caller| !caller.is_privileged());
+    if let Some(manager) = request.extensions().get::<Arc<ReservationManager>>() {
+        let manager = manager
+            .as_ref()
+            .clone()
+            .with_tenant(tenant)
+            .with_actor(actor)
+            .with_owner_check(check_owner);
+        request.extensions_mut().insert(Arc::new(manager));
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tenant_should_be_parsed() {
+        assert_eq!(parse(None).unwrap(), "");
+        assert_eq!(parse(Some(b"acme")).unwrap(), "acme");
+        assert!(parse(Some(b"with space")).is_err());
+        assert!(parse(Some("x".repeat(65).as_bytes())).is_err());
+        assert!(parse(Some(&[0xff, 0xfe])).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+-- timestamps are microseconds since the unix epoch, the timespan (start_at, end_at) excludes both bounds
+CREATE TABLE reservations (
+ id BLOB NOT NULL PRIMARY KEY,
+ user_id VARCHAR ( 64 ) NOT NULL,
+ status TEXT NOT NULL DEFAULT 'pending' CHECK ( status IN ( 'unknown', 'pending', 'confirmed', 'blocked' ) ),
+ resource_id VARCHAR ( 64 ) NOT NULL,
+ start_at INTEGER NOT NULL,
+ end_at INTEGER NOT NULL,
+ note TEXT,
+ CHECK ( start_at <= end_at )
+);
+CREATE INDEX reservations_resource_id_idx ON reservations ( resource_id, start_at );
+CREATE INDEX reservations_user_id_idx ON reservations ( user_id );
+CREATE INDEX reservations_start_idx ON reservations ( start_at );
+-- emulate the reservations_conflict exclusion constraint, an empty timespan overlaps nothing
+CREATE TRIGGER reservations_conflict_insert BEFORE INSERT ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+CREATE TRIGGER reservations_conflict_update BEFORE UPDATE OF resource_id, start_at, end_at ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.id <> NEW.id AND r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+-- reservation change queue, user_id, resource_id and status are kept so deleted reservations can still be filtered
+CREATE TABLE reservation_changes (
+ id INTEGER PRIMARY KEY AUTOINCREMENT,
+ reservation_id BLOB NOT NULL,
+ op TEXT NOT NULL CHECK ( op IN ( 'unknown', 'create', 'update', 'delete' ) ),
+ user_id VARCHAR ( 64 ),
+ resource_id VARCHAR ( 64 ),
+ status TEXT
+);
+CREATE TRIGGER reservations_insert_trigger AFTER INSERT ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( NEW.id, 'create', NEW.user_id, NEW.resource_id, NEW.status );
+END;
+-- only status changes are recorded
+CREATE TRIGGER reservations_update_trigger AFTER UPDATE OF status ON reservations
+WHEN OLD.status <> NEW.status
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( NEW.id, 'update', NEW.user_id, NEW.resource_id, NEW.status );
+END;
+CREATE TRIGGER reservations_delete_trigger AFTER DELETE ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( OLD.id, 'delete', OLD.user_id, OLD.resource_id, OLD.status );
+END;
+-- responses of requests sent with an idempotency key, a retried request returns the stored response
+CREATE TABLE idempotency_keys (
+ key VARCHAR ( 128 ) NOT NULL PRIMARY KEY,
+ op VARCHAR ( 16 ) NOT NULL,
+ response BLOB,
+ expires_at INTEGER NOT NULL
+);
+CREATE INDEX idempotency_keys_expires_at_idx ON idempotency_keys ( expires_at );
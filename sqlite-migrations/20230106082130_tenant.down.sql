@@ -0,0 +1,48 @@
+CREATE TABLE idempotency_keys_default (
+ key VARCHAR ( 128 ) NOT NULL PRIMARY KEY,
+ op VARCHAR ( 16 ) NOT NULL,
+ response BLOB,
+ expires_at INTEGER NOT NULL
+);
+INSERT INTO idempotency_keys_default SELECT key, op, response, expires_at FROM idempotency_keys WHERE tenant_id = '';
+DROP TABLE idempotency_keys;
+ALTER TABLE idempotency_keys_default RENAME TO idempotency_keys;
+CREATE INDEX idempotency_keys_expires_at_idx ON idempotency_keys ( expires_at );
+DROP TRIGGER reservations_insert_trigger;
+DROP TRIGGER reservations_update_trigger;
+DROP TRIGGER reservations_delete_trigger;
+CREATE TRIGGER reservations_insert_trigger AFTER INSERT ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( NEW.id, 'create', NEW.user_id, NEW.resource_id, NEW.status );
+END;
+CREATE TRIGGER reservations_update_trigger AFTER UPDATE OF status ON reservations
+WHEN OLD.status <> NEW.status
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( NEW.id, 'update', NEW.user_id, NEW.resource_id, NEW.status );
+END;
+CREATE TRIGGER reservations_delete_trigger AFTER DELETE ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status )
+ VALUES ( OLD.id, 'delete', OLD.user_id, OLD.resource_id, OLD.status );
+END;
+DROP TRIGGER reservations_conflict_insert;
+DROP TRIGGER reservations_conflict_update;
+CREATE TRIGGER reservations_conflict_insert BEFORE INSERT ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+CREATE TRIGGER reservations_conflict_update BEFORE UPDATE OF resource_id, start_at, end_at ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.id <> NEW.id AND r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+DROP INDEX reservation_changes_tenant_id_idx;
+DROP INDEX reservations_resource_id_idx;
+CREATE INDEX reservations_resource_id_idx ON reservations ( resource_id, start_at );
+ALTER TABLE reservation_changes DROP COLUMN tenant_id;
+ALTER TABLE reservations DROP COLUMN tenant_id;
@@ -0,0 +1,54 @@
+-- reservations, their changes and idempotency keys belong to a tenant, '' is the default tenant
+ALTER TABLE reservations ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+ALTER TABLE reservation_changes ADD COLUMN tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '';
+DROP INDEX reservations_resource_id_idx;
+CREATE INDEX reservations_resource_id_idx ON reservations ( tenant_id, resource_id, start_at );
+CREATE INDEX reservation_changes_tenant_id_idx ON reservation_changes ( tenant_id, id );
+-- resources of different tenants never conflict
+DROP TRIGGER reservations_conflict_insert;
+DROP TRIGGER reservations_conflict_update;
+CREATE TRIGGER reservations_conflict_insert BEFORE INSERT ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.tenant_id = NEW.tenant_id AND r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+CREATE TRIGGER reservations_conflict_update BEFORE UPDATE OF tenant_id, resource_id, start_at, end_at ON reservations
+WHEN NEW.start_at < NEW.end_at
+ AND EXISTS ( SELECT 1 FROM reservations r WHERE r.id <> NEW.id AND r.tenant_id = NEW.tenant_id AND r.resource_id = NEW.resource_id AND r.start_at < NEW.end_at AND NEW.start_at < r.end_at AND r.start_at < r.end_at )
+BEGIN
+ SELECT RAISE ( ABORT, 'reservations_conflict' );
+END;
+DROP TRIGGER reservations_insert_trigger;
+DROP TRIGGER reservations_update_trigger;
+DROP TRIGGER reservations_delete_trigger;
+CREATE TRIGGER reservations_insert_trigger AFTER INSERT ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( NEW.id, 'create', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+END;
+-- only status changes are recorded
+CREATE TRIGGER reservations_update_trigger AFTER UPDATE OF status ON reservations
+WHEN OLD.status <> NEW.status
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( NEW.id, 'update', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+END;
+CREATE TRIGGER reservations_delete_trigger AFTER DELETE ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( OLD.id, 'delete', OLD.user_id, OLD.resource_id, OLD.status, OLD.tenant_id );
+END;
+-- keys are per tenant, sqlite can't change a primary key in place
+CREATE TABLE idempotency_keys_tenant (
+ tenant_id VARCHAR ( 64 ) NOT NULL DEFAULT '',
+ key VARCHAR ( 128 ) NOT NULL,
+ op VARCHAR ( 16 ) NOT NULL,
+ response BLOB,
+ expires_at INTEGER NOT NULL,
+ PRIMARY KEY ( tenant_id, key )
+);
+INSERT INTO idempotency_keys_tenant ( key, op, response, expires_at ) SELECT key, op, response, expires_at FROM idempotency_keys;
+DROP TABLE idempotency_keys;
+ALTER TABLE idempotency_keys_tenant RENAME TO idempotency_keys;
+CREATE INDEX idempotency_keys_expires_at_idx ON idempotency_keys ( expires_at );
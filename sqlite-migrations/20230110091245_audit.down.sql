@@ -0,0 +1,23 @@
+DROP TRIGGER reservations_insert_trigger;
+DROP TRIGGER reservations_update_trigger;
+DROP TRIGGER reservations_delete_trigger;
+CREATE TRIGGER reservations_insert_trigger AFTER INSERT ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( NEW.id, 'create', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+END;
+-- only status changes are recorded
+CREATE TRIGGER reservations_update_trigger AFTER UPDATE OF status ON reservations
+WHEN OLD.status <> NEW.status
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( NEW.id, 'update', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id );
+END;
+CREATE TRIGGER reservations_delete_trigger AFTER DELETE ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id )
+ VALUES ( OLD.id, 'delete', OLD.user_id, OLD.resource_id, OLD.status, OLD.tenant_id );
+END;
+DROP TABLE change_actor;
+ALTER TABLE reservation_changes DROP COLUMN on_behalf_of;
+ALTER TABLE reservation_changes DROP COLUMN actor;
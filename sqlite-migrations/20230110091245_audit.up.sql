@@ -0,0 +1,29 @@
+-- who made every change. sqlite has no session settings, the manager writes the actor to the
+-- single row of change_actor first in its write transactions, which hold the database lock
+ALTER TABLE reservation_changes ADD COLUMN actor VARCHAR ( 64 ) NOT NULL DEFAULT '';
+ALTER TABLE reservation_changes ADD COLUMN on_behalf_of BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE TABLE change_actor (
+ id INTEGER PRIMARY KEY CHECK ( id = 1 ),
+ actor VARCHAR ( 64 ) NOT NULL DEFAULT ''
+);
+INSERT INTO change_actor ( id ) VALUES ( 1 );
+DROP TRIGGER reservations_insert_trigger;
+DROP TRIGGER reservations_update_trigger;
+DROP TRIGGER reservations_delete_trigger;
+CREATE TRIGGER reservations_insert_trigger AFTER INSERT ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+ SELECT NEW.id, 'create', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id, a.actor, a.actor <> '' AND a.actor <> NEW.user_id FROM change_actor a;
+END;
+-- only status changes are recorded
+CREATE TRIGGER reservations_update_trigger AFTER UPDATE OF status ON reservations
+WHEN OLD.status <> NEW.status
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+ SELECT NEW.id, 'update', NEW.user_id, NEW.resource_id, NEW.status, NEW.tenant_id, a.actor, a.actor <> '' AND a.actor <> NEW.user_id FROM change_actor a;
+END;
+CREATE TRIGGER reservations_delete_trigger AFTER DELETE ON reservations
+BEGIN
+ INSERT INTO reservation_changes ( reservation_id, op, user_id, resource_id, status, tenant_id, actor, on_behalf_of )
+ SELECT OLD.id, 'delete', OLD.user_id, OLD.resource_id, OLD.status, OLD.tenant_id, a.actor, a.actor <> '' AND a.actor <> OLD.user_id FROM change_actor a;
+END;
@@ -0,0 +1,2 @@
+-- who booked a reservation, differs from user_id if booked on behalf of the user
+ALTER TABLE reservations ADD COLUMN booked_by VARCHAR ( 64 ) NOT NULL DEFAULT '';